@@ -0,0 +1,272 @@
+use crate::diff::Data;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// How to pick random values for one argument of a challenge. Written in
+/// challenge files as a spec such as `list of 0..20 ints in -100..100`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Gen {
+    /// An integer in `min..=max`.
+    Int { min: i64, max: i64 },
+    Bool,
+    /// A list of `min_len..=max_len` elements.
+    List { min_len: usize, max_len: usize, elem: Box<Gen> },
+    /// A generated list, sorted ascending.
+    Sorted(Box<Gen>),
+    /// One of these expressions, such as a function to pass in.
+    OneOf(Vec<String>),
+}
+
+impl Gen {
+    pub fn int(min: i64, max: i64) -> Gen {
+        Gen::Int { min, max }
+    }
+
+    pub fn list(min_len: usize, max_len: usize, elem: Gen) -> Gen {
+        Gen::List { min_len, max_len, elem: Box::new(elem) }
+    }
+
+    pub fn sorted(list: Gen) -> Gen {
+        Gen::Sorted(Box::new(list))
+    }
+
+    pub fn one_of(exprs: &[&str]) -> Gen {
+        Gen::OneOf(exprs.iter().map(|e| e.to_string()).collect())
+    }
+
+    pub fn generate(&self, rng: &mut fastrand::Rng) -> Data {
+        match self {
+            Gen::Int { min, max } => Data::Number(rng.i64(*min..=*max)),
+            Gen::Bool => Data::Bool(rng.bool()),
+            Gen::List { min_len, max_len, elem } => {
+                let len = rng.usize(*min_len..=*max_len);
+                Data::List((0..len).map(|_| elem.generate(rng)).collect())
+            }
+            Gen::Sorted(list) => sort(list.generate(rng)),
+            Gen::OneOf(exprs) => Data::Opaque(exprs[rng.usize(..exprs.len())].clone()),
+        }
+    }
+
+    /// Smaller values this generator could also have produced, simplest
+    /// first: numbers move towards zero, lists get shorter and then their
+    /// elements shrink, and `OneOf` falls back to earlier options.
+    pub fn shrink(&self, value: &Data) -> Vec<Data> {
+        match (self, value) {
+            (Gen::Int { min, max }, Data::Number(n)) => {
+                // Worked out in i128, as the distance can be wider than an i64
+                let (wide, target) = (i128::from(*n), i128::from(0.clamp(*min, *max)));
+                let mut smaller = Vec::new();
+                for candidate in [target, wide - (wide - target) / 2, wide - (wide - target).signum()] {
+                    let Ok(candidate) = i64::try_from(candidate) else { continue };
+                    if candidate != *n && !smaller.contains(&Data::Number(candidate)) {
+                        smaller.push(Data::Number(candidate));
+                    }
+                }
+                smaller
+            }
+            (Gen::Bool, Data::Bool(true)) => vec![Data::Bool(false)],
+            (Gen::List { min_len, elem, .. }, Data::List(items)) => {
+                let mut smaller = Vec::new();
+                if items.len() > *min_len {
+                    smaller.push(Data::List(items[..*min_len].to_vec()));
+                    if items.len() / 2 > *min_len {
+                        smaller.push(Data::List(items[..items.len() / 2].to_vec()));
+                    }
+                    for i in 0..items.len() {
+                        let mut fewer = items.clone();
+                        fewer.remove(i);
+                        smaller.push(Data::List(fewer));
+                    }
+                }
+                for (i, item) in items.iter().enumerate() {
+                    for item in elem.shrink(item) {
+                        let mut changed = items.clone();
+                        changed[i] = item;
+                        smaller.push(Data::List(changed));
+                    }
+                }
+                smaller
+            }
+            (Gen::Sorted(list), value) => list.shrink(value).into_iter().map(sort).collect(),
+            (Gen::OneOf(exprs), Data::Opaque(expr)) => exprs.iter()
+                .take_while(|e| *e != expr)
+                .map(|e| Data::Opaque(e.clone()))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>, plural: bool) -> fmt::Result {
+        let s = if plural { "s" } else { "" };
+        match self {
+            Gen::Int { min, max } => write!(f, "int{} in {}..{}", s, min, max),
+            Gen::Bool => write!(f, "bool{}", s),
+            Gen::List { min_len, max_len, elem } => {
+                write!(f, "list{} of {}..{} ", s, min_len, max_len)?;
+                elem.write(f, true)
+            }
+            Gen::Sorted(list) => {
+                write!(f, "sorted ")?;
+                list.write(f, plural)
+            }
+            Gen::OneOf(exprs) => write!(f, "one of {}", exprs.join("; ")),
+        }
+    }
+}
+
+fn sort(value: Data) -> Data {
+    match value {
+        Data::List(mut items) => {
+            items.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+            Data::List(items)
+        }
+        other => other,
+    }
+}
+
+/// Candidate argument lists with one argument shrunk, in the order
+/// `Gen::shrink` gives them.
+pub fn shrink_args(gens: &[Gen], args: &[Data]) -> Vec<Vec<Data>> {
+    let mut smaller = Vec::new();
+    for (i, (gen, arg)) in gens.iter().zip(args).enumerate() {
+        for arg in gen.shrink(arg) {
+            let mut changed = args.to_vec();
+            changed[i] = arg;
+            smaller.push(changed);
+        }
+    }
+    smaller
+}
+
+impl fmt::Display for Gen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, false)
+    }
+}
+
+impl FromStr for Gen {
+    type Err = String;
+
+    /// Parse a spec: `int in A..B`, `bool`, `list of A..B <elements>`,
+    /// `sorted <list>` or `one of <expr>; <expr>`. Element specs may be
+    /// plural, as in `list of 0..5 ints in 1..9`. Ranges include both ends.
+    fn from_str(spec: &str) -> Result<Gen, String> {
+        let spec = spec.trim();
+        if let Some(exprs) = spec.strip_prefix("one of ") {
+            let exprs: Vec<String> = exprs.split(';').map(|e| e.trim().to_string()).collect();
+            if exprs.iter().any(String::is_empty) {
+                return Err(format!("Empty option in '{}'", spec));
+            }
+            return Ok(Gen::OneOf(exprs));
+        }
+
+        let (word, rest) = spec.split_once(' ').unwrap_or((spec, ""));
+        match word.trim_end_matches('s') {
+            "int" => {
+                let range = rest.strip_prefix("in ").ok_or_else(|| format!("Expected 'in A..B' after '{}'", word))?;
+                let (min, max) = parse_range(range)?;
+                Ok(Gen::Int { min, max })
+            }
+            "bool" if rest.is_empty() => Ok(Gen::Bool),
+            "list" => {
+                let rest = rest.strip_prefix("of ").ok_or_else(|| format!("Expected 'of A..B' after '{}'", word))?;
+                let (range, elem) = rest.split_once(' ').ok_or_else(|| format!("Missing element spec in '{}'", spec))?;
+                let (min_len, max_len) = parse_range(range)?;
+                let min_len = usize::try_from(min_len).map_err(|_| format!("Negative length in '{}'", spec))?;
+                let max_len = usize::try_from(max_len).map_err(|_| format!("Negative length in '{}'", spec))?;
+                Ok(Gen::list(min_len, max_len, elem.parse()?))
+            }
+            "sorted" => match rest.parse()? {
+                list @ Gen::List { .. } => Ok(Gen::sorted(list)),
+                _ => Err(format!("Only lists can be sorted: '{}'", spec)),
+            },
+            _ => Err(format!("Unknown generator '{}'", spec)),
+        }
+    }
+}
+
+/// An inclusive range `A..B` with `A <= B`.
+fn parse_range(range: &str) -> Result<(i64, i64), String> {
+    let invalid = || format!("Invalid range '{}'", range);
+    let (min, max) = range.trim().split_once("..").ok_or_else(invalid)?;
+    let min: i64 = min.parse().map_err(|_| invalid())?;
+    let max: i64 = max.parse().map_err(|_| invalid())?;
+    if min > max {
+        return Err(invalid());
+    }
+    Ok((min, max))
+}
+
+impl TryFrom<String> for Gen {
+    type Error = String;
+
+    fn try_from(spec: String) -> Result<Gen, String> {
+        spec.parse()
+    }
+}
+
+impl From<Gen> for String {
+    fn from(gen: Gen) -> String {
+        gen.to_string()
+    }
+}
+
+/// Test input source for generated arguments.
+pub fn input_source(args: &[Data]) -> String {
+    args.iter()
+        .map(|arg| match arg {
+            // A bare negative number after another argument would parse as
+            // a subtraction
+            Data::Number(n) if *n < 0 => format!("({})", n),
+            other => other.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spec_round_trip() {
+        let specs = [
+            "int in -100..100",
+            "list of 0..20 ints in -100..100",
+            "sorted list of 0..8 lists of 1..3 bools",
+            "one of (\\x -> x * 2); (\\x -> x + 1)",
+        ];
+        for spec in specs {
+            let gen: Gen = spec.parse().unwrap();
+            assert_eq!(gen.to_string(), spec);
+        }
+        assert_eq!("list of 0..5 int in 1..9".parse(), Ok(Gen::list(0, 5, Gen::int(1, 9))));
+
+        for bad in ["int 1..2", "int in 5..1", "list of 0..5", "sorted int in 0..1", "float in 0..1", "one of a;"] {
+            assert!(bad.parse::<Gen>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_shrink_stays_in_bounds() {
+        assert_eq!(Gen::int(-5, 5).shrink(&Data::Number(4)), vec![Data::Number(0), Data::Number(2), Data::Number(3)]);
+        assert_eq!(Gen::int(3, 9).shrink(&Data::Number(4)), vec![Data::Number(3)]);
+        assert_eq!(
+            Gen::int(i64::MIN, i64::MAX).shrink(&Data::Number(i64::MIN)),
+            vec![Data::Number(0), Data::Number(i64::MIN / 2), Data::Number(i64::MIN + 1)]
+        );
+        assert_eq!(Gen::int(1, i64::MAX).shrink(&Data::Number(i64::MAX)).len(), 3);
+        assert!(Gen::int(-5, 5).shrink(&Data::Number(0)).is_empty());
+
+        let gen = Gen::sorted(Gen::list(2, 4, Gen::int(0, 9)));
+        let value = Data::List(vec![Data::Number(1), Data::Number(5), Data::Number(7)]);
+        for smaller in gen.shrink(&value) {
+            let Data::List(items) = smaller else { panic!("expected a list") };
+            assert!(items.len() >= 2);
+            assert!(items.windows(2).all(|w| w[0] <= w[1]));
+        }
+    }
+}
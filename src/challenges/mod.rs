@@ -1,4 +1,15 @@
+pub mod generate;
+pub mod validate;
+
+use crate::diff::Data;
+use crate::lang::parser;
+use crate::lang::types::Type;
+use crate::score::Metric;
+use anyhow::{Context, Result};
+use generate::{input_source, Gen};
 use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Challenge {
@@ -8,16 +19,121 @@ pub struct Challenge {
     pub type_signature: String,
     pub difficulty: usize,
     pub par_score: usize,
+    /// What `par_score` is measured in.
+    #[serde(default)]
+    pub metric: Metric,
     pub test_cases: Vec<TestCase>,
     pub is_tutorial: bool,
     pub hint: Option<String>,
+    /// Only run on submission, so a solution can't get away with matching
+    /// the visible inputs.
+    #[serde(default)]
+    pub hidden_tests: Vec<TestCase>,
+    #[serde(default)]
+    pub reference: Option<Reference>,
+}
+
+/// A known-good solution, used to check submissions on random inputs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reference {
+    pub solution: String,
+    /// One generator per argument.
+    pub inputs: Vec<Gen>,
+    #[serde(default)]
+    pub compare: Comparison,
+}
+
+impl Challenge {
+    /// The parsed type signature, if it parses.
+    pub fn signature(&self) -> Option<Type> {
+        parser::parse_type(&self.type_signature).ok()
+    }
+}
+
+impl Reference {
+    /// A reference whose results must match exactly.
+    pub fn new(solution: &str, inputs: Vec<Gen>) -> Self {
+        Reference { solution: solution.to_string(), inputs, compare: Comparison::Exact }
+    }
+
+    /// Random arguments, one from each input generator.
+    pub fn generate(&self, rng: &mut fastrand::Rng) -> Vec<Data> {
+        self.inputs.iter().map(|gen| gen.generate(rng)).collect()
+    }
+
+    /// A test case comparing a solution with the reference on `args`.
+    pub fn test_case(&self, args: &[Data]) -> TestCase {
+        let input = input_source(args);
+        TestCase {
+            // The expected value is whatever the reference gives
+            expected: format!("({}) {}", self.solution, input),
+            description: format!("random input {}", input),
+            input,
+            compare: self.compare,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TestCase {
     pub input: String,
+    /// An expression evaluated and compared to the result as a value, so
+    /// formatting doesn't matter.
     pub expected: String,
     pub description: String,
+    #[serde(default)]
+    pub compare: Comparison,
+}
+
+impl TestCase {
+    /// A test whose result must equal `expected` exactly.
+    pub fn new(input: &str, expected: &str, description: &str) -> Self {
+        TestCase {
+            input: input.to_string(),
+            expected: expected.to_string(),
+            description: description.to_string(),
+            compare: Comparison::Exact,
+        }
+    }
+}
+
+/// How a test's result is checked against its expected value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Comparison {
+    /// The values must be equal.
+    #[default]
+    Exact,
+    /// The lists must hold the same elements the same number of times, in
+    /// any order.
+    Unordered,
+    /// The lists must hold the same elements, ignoring order and duplicates.
+    SetEquality,
+    /// Numbers may differ by at most this much, anywhere in the value.
+    Tolerance(i64),
+}
+
+impl Comparison {
+    /// A short note shown next to tests that aren't compared exactly.
+    pub fn note(&self) -> Option<String> {
+        match self {
+            Comparison::Exact => None,
+            Comparison::Unordered => Some("any order".to_string()),
+            Comparison::SetEquality => Some("as a set".to_string()),
+            Comparison::Tolerance(t) => Some(format!("within {}", t)),
+        }
+    }
+}
+
+/// Functions passed to challenges that take one, for random tests.
+const MAPPERS: &[&str] = &["(\\x -> x * 2)", "(\\x -> x + 1)", "(\\x -> x * x - 3)", "(\\x -> 0 - x)"];
+const PREDICATES: &[&str] = &["(\\x -> x > 0)", "(\\x -> x % 2 == 0)", "(\\x -> x < 5)", "(\\x -> true)"];
+
+/// Read a challenge pack: a JSON array of challenges.
+pub fn load_pack(path: &Path) -> Result<Vec<Challenge>> {
+    let json = fs::read_to_string(path)
+        .with_context(|| format!("Could not read {}", path.display()))?;
+    serde_json::from_str(&json)
+        .with_context(|| format!("Invalid challenge pack {}", path.display()))
 }
 
 pub fn get_all_challenges() -> Vec<Challenge> {
@@ -41,25 +157,19 @@ fn get_tutorial_challenges() -> Vec<Challenge> {
             type_signature: "Int -> Int".to_string(),
             difficulty: 1,
             par_score: 80,
+            metric: Metric::Chars,
             is_tutorial: true,
             hint: Some("Use the * operator. Try: \\x -> x * 2".to_string()),
             test_cases: vec![
-                TestCase {
-                    input: "5".to_string(),
-                    expected: "10".to_string(),
-                    description: "double 5".to_string(),
-                },
-                TestCase {
-                    input: "0".to_string(),
-                    expected: "0".to_string(),
-                    description: "double 0".to_string(),
-                },
-                TestCase {
-                    input: "-3".to_string(),
-                    expected: "-6".to_string(),
-                    description: "double -3".to_string(),
-                },
+                TestCase::new("5", "10", "double 5"),
+                TestCase::new("0", "0", "double 0"),
+                TestCase::new("-3", "-6", "double -3"),
+            ],
+            hidden_tests: vec![
+                TestCase::new("137", "274", "hidden test 1"),
+                TestCase::new("-250", "-500", "hidden test 2"),
             ],
+            reference: Some(Reference::new("\\x -> x * 2", vec![Gen::int(-1000, 1000)])),
         },
         Challenge {
             id: 2,
@@ -68,25 +178,19 @@ fn get_tutorial_challenges() -> Vec<Challenge> {
             type_signature: "[Int] -> [Int]".to_string(),
             difficulty: 1,
             par_score: 90,
+            metric: Metric::Chars,
             is_tutorial: true,
             hint: Some("Use filter with a lambda: filter (\\x -> x > 0)".to_string()),
             test_cases: vec![
-                TestCase {
-                    input: "[1, -2, 3, -4, 5]".to_string(),
-                    expected: "[1, 3, 5]".to_string(),
-                    description: "filter positives".to_string(),
-                },
-                TestCase {
-                    input: "[-1, -2, -3]".to_string(),
-                    expected: "[]".to_string(),
-                    description: "all negative".to_string(),
-                },
-                TestCase {
-                    input: "[1, 2, 3]".to_string(),
-                    expected: "[1, 2, 3]".to_string(),
-                    description: "all positive".to_string(),
-                },
+                TestCase::new("[1, -2, 3, -4, 5]", "[1, 3, 5]", "filter positives"),
+                TestCase::new("[-1, -2, -3]", "[]", "all negative"),
+                TestCase::new("[1, 2, 3]", "[1, 2, 3]", "all positive"),
+            ],
+            hidden_tests: vec![
+                TestCase::new("[0, 7, -7, 12]", "[7, 12]", "hidden test 1"),
+                TestCase::new("[]", "[]", "hidden test 2"),
             ],
+            reference: Some(Reference::new("filter (\\x -> x > 0)", vec![Gen::list(0, 12, Gen::int(-50, 50))])),
         },
         Challenge {
             id: 3,
@@ -95,25 +199,19 @@ fn get_tutorial_challenges() -> Vec<Challenge> {
             type_signature: "[Int] -> [Int]".to_string(),
             difficulty: 1,
             par_score: 70,
+            metric: Metric::Chars,
             is_tutorial: true,
             hint: Some("Use partial application: take 3".to_string()),
             test_cases: vec![
-                TestCase {
-                    input: "[1, 2, 3, 4, 5]".to_string(),
-                    expected: "[1, 2, 3]".to_string(),
-                    description: "first three of five".to_string(),
-                },
-                TestCase {
-                    input: "[1, 2]".to_string(),
-                    expected: "[1, 2]".to_string(),
-                    description: "list shorter than 3".to_string(),
-                },
-                TestCase {
-                    input: "[]".to_string(),
-                    expected: "[]".to_string(),
-                    description: "empty list".to_string(),
-                },
+                TestCase::new("[1, 2, 3, 4, 5]", "[1, 2, 3]", "first three of five"),
+                TestCase::new("[1, 2]", "[1, 2]", "list shorter than 3"),
+                TestCase::new("[]", "[]", "empty list"),
             ],
+            hidden_tests: vec![
+                TestCase::new("[9, 8, 7, 6]", "[9, 8, 7]", "hidden test 1"),
+                TestCase::new("[4]", "[4]", "hidden test 2"),
+            ],
+            reference: Some(Reference::new("take 3", vec![Gen::list(0, 10, Gen::int(-50, 50))])),
         },
         Challenge {
             id: 4,
@@ -122,25 +220,22 @@ fn get_tutorial_challenges() -> Vec<Challenge> {
             type_signature: "[Int] -> Int".to_string(),
             difficulty: 2,
             par_score: 100,
+            metric: Metric::Chars,
             is_tutorial: true,
             hint: Some("Use match with patterns: match list with [] -> 0 | h::t -> h".to_string()),
             test_cases: vec![
-                TestCase {
-                    input: "[1, 2, 3]".to_string(),
-                    expected: "1".to_string(),
-                    description: "head of [1,2,3]".to_string(),
-                },
-                TestCase {
-                    input: "[]".to_string(),
-                    expected: "0".to_string(),
-                    description: "empty list".to_string(),
-                },
-                TestCase {
-                    input: "[42]".to_string(),
-                    expected: "42".to_string(),
-                    description: "single element".to_string(),
-                },
+                TestCase::new("[1, 2, 3]", "1", "head of [1,2,3]"),
+                TestCase::new("[]", "0", "empty list"),
+                TestCase::new("[42]", "42", "single element"),
+            ],
+            hidden_tests: vec![
+                TestCase::new("[-8, 3]", "-8", "hidden test 1"),
+                TestCase::new("[0, 5]", "0", "hidden test 2"),
             ],
+            reference: Some(Reference::new(
+                "\\l -> match l with [] -> 0 | h::t -> h",
+                vec![Gen::list(0, 5, Gen::int(-50, 50))],
+            )),
         },
         Challenge {
             id: 5,
@@ -149,25 +244,19 @@ fn get_tutorial_challenges() -> Vec<Challenge> {
             type_signature: "Int -> Int".to_string(),
             difficulty: 2,
             par_score: 110,
+            metric: Metric::Chars,
             is_tutorial: true,
-            hint: Some("Use >> for forward pipe or create functions: \\x -> (x * 2) ^ 2".to_string()),
+            hint: Some("Use >> to compose functions or write one directly: \\x -> (x * 2) ^ 2".to_string()),
             test_cases: vec![
-                TestCase {
-                    input: "3".to_string(),
-                    expected: "36".to_string(),
-                    description: "3 * 2 = 6, 6^2 = 36".to_string(),
-                },
-                TestCase {
-                    input: "5".to_string(),
-                    expected: "100".to_string(),
-                    description: "5 * 2 = 10, 10^2 = 100".to_string(),
-                },
-                TestCase {
-                    input: "0".to_string(),
-                    expected: "0".to_string(),
-                    description: "0 * 2 = 0, 0^2 = 0".to_string(),
-                },
+                TestCase::new("3", "36", "3 * 2 = 6, 6^2 = 36"),
+                TestCase::new("5", "100", "5 * 2 = 10, 10^2 = 100"),
+                TestCase::new("0", "0", "0 * 2 = 0, 0^2 = 0"),
+            ],
+            hidden_tests: vec![
+                TestCase::new("-4", "64", "hidden test 1"),
+                TestCase::new("11", "484", "hidden test 2"),
             ],
+            reference: Some(Reference::new("\\x -> (x * 2) ^ 2", vec![Gen::int(-1000, 1000)])),
         },
     ]
 }
@@ -181,25 +270,19 @@ fn get_regular_challenges() -> Vec<Challenge> {
             type_signature: "[Int] -> Int".to_string(),
             difficulty: 2,
             par_score: 80,
+            metric: Metric::Chars,
             is_tutorial: false,
             hint: None,
             test_cases: vec![
-                TestCase {
-                    input: "[1, 2, 3, 4, 5]".to_string(),
-                    expected: "15".to_string(),
-                    description: "sum of 1..5".to_string(),
-                },
-                TestCase {
-                    input: "[]".to_string(),
-                    expected: "0".to_string(),
-                    description: "empty list".to_string(),
-                },
-                TestCase {
-                    input: "[-1, 1, -2, 2]".to_string(),
-                    expected: "0".to_string(),
-                    description: "mixed signs".to_string(),
-                },
+                TestCase::new("[1, 2, 3, 4, 5]", "15", "sum of 1..5"),
+                TestCase::new("[]", "0", "empty list"),
+                TestCase::new("[-1, 1, -2, 2]", "0", "mixed signs"),
             ],
+            hidden_tests: vec![
+                TestCase::new("[100, -50, 25]", "75", "hidden test 1"),
+                TestCase::new("[7]", "7", "hidden test 2"),
+            ],
+            reference: Some(Reference::new("sum", vec![Gen::list(0, 20, Gen::int(-100, 100))])),
         },
         Challenge {
             id: 7,
@@ -208,25 +291,19 @@ fn get_regular_challenges() -> Vec<Challenge> {
             type_signature: "[Int] -> [Int]".to_string(),
             difficulty: 2,
             par_score: 70,
+            metric: Metric::Chars,
             is_tutorial: false,
             hint: None,
             test_cases: vec![
-                TestCase {
-                    input: "[1, 2, 3, 4, 5]".to_string(),
-                    expected: "[5, 4, 3, 2, 1]".to_string(),
-                    description: "reverse [1..5]".to_string(),
-                },
-                TestCase {
-                    input: "[]".to_string(),
-                    expected: "[]".to_string(),
-                    description: "empty list".to_string(),
-                },
-                TestCase {
-                    input: "[1]".to_string(),
-                    expected: "[1]".to_string(),
-                    description: "single element".to_string(),
-                },
+                TestCase::new("[1, 2, 3, 4, 5]", "[5, 4, 3, 2, 1]", "reverse [1..5]"),
+                TestCase::new("[]", "[]", "empty list"),
+                TestCase::new("[1]", "[1]", "single element"),
+            ],
+            hidden_tests: vec![
+                TestCase::new("[3, 1, 2, 3]", "[3, 2, 1, 3]", "hidden test 1"),
+                TestCase::new("[-1, -2]", "[-2, -1]", "hidden test 2"),
             ],
+            reference: Some(Reference::new("reverse", vec![Gen::list(0, 12, Gen::int(-50, 50))])),
         },
         Challenge {
             id: 8,
@@ -235,25 +312,19 @@ fn get_regular_challenges() -> Vec<Challenge> {
             type_signature: "[Int] -> [Int]".to_string(),
             difficulty: 2,
             par_score: 90,
+            metric: Metric::Chars,
             is_tutorial: false,
             hint: None,
             test_cases: vec![
-                TestCase {
-                    input: "[1, 2, 3, 4, 5, 6]".to_string(),
-                    expected: "[2, 4, 6]".to_string(),
-                    description: "evens from 1..6".to_string(),
-                },
-                TestCase {
-                    input: "[1, 3, 5]".to_string(),
-                    expected: "[]".to_string(),
-                    description: "all odd".to_string(),
-                },
-                TestCase {
-                    input: "[]".to_string(),
-                    expected: "[]".to_string(),
-                    description: "empty list".to_string(),
-                },
+                TestCase::new("[1, 2, 3, 4, 5, 6]", "[2, 4, 6]", "evens from 1..6"),
+                TestCase::new("[1, 3, 5]", "[]", "all odd"),
+                TestCase::new("[]", "[]", "empty list"),
+            ],
+            hidden_tests: vec![
+                TestCase::new("[0, -2, -3, 7, 10]", "[0, -2, 10]", "hidden test 1"),
+                TestCase::new("[11, 13]", "[]", "hidden test 2"),
             ],
+            reference: Some(Reference::new("filter (\\x -> x % 2 == 0)", vec![Gen::list(0, 12, Gen::int(-50, 50))])),
         },
         Challenge {
             id: 9,
@@ -262,30 +333,23 @@ fn get_regular_challenges() -> Vec<Challenge> {
             type_signature: "Int -> Int".to_string(),
             difficulty: 3,
             par_score: 130,
+            metric: Metric::Chars,
             is_tutorial: false,
             hint: None,
             test_cases: vec![
-                TestCase {
-                    input: "0".to_string(),
-                    expected: "0".to_string(),
-                    description: "fib(0)".to_string(),
-                },
-                TestCase {
-                    input: "1".to_string(),
-                    expected: "1".to_string(),
-                    description: "fib(1)".to_string(),
-                },
-                TestCase {
-                    input: "10".to_string(),
-                    expected: "55".to_string(),
-                    description: "fib(10)".to_string(),
-                },
-                TestCase {
-                    input: "15".to_string(),
-                    expected: "610".to_string(),
-                    description: "fib(15)".to_string(),
-                },
+                TestCase::new("0", "0", "fib(0)"),
+                TestCase::new("1", "1", "fib(1)"),
+                TestCase::new("10", "55", "fib(10)"),
+                TestCase::new("15", "610", "fib(15)"),
+            ],
+            hidden_tests: vec![
+                TestCase::new("7", "13", "hidden test 1"),
+                TestCase::new("12", "144", "hidden test 2"),
             ],
+            reference: Some(Reference::new(
+                "let fib = \\n -> if n < 2 then n else fib (n - 1) + fib (n - 2) in fib",
+                vec![Gen::int(0, 18)],
+            )),
         },
         Challenge {
             id: 10,
@@ -294,30 +358,23 @@ fn get_regular_challenges() -> Vec<Challenge> {
             type_signature: "Int -> Bool".to_string(),
             difficulty: 3,
             par_score: 140,
+            metric: Metric::Chars,
             is_tutorial: false,
             hint: None,
             test_cases: vec![
-                TestCase {
-                    input: "2".to_string(),
-                    expected: "true".to_string(),
-                    description: "2 is prime".to_string(),
-                },
-                TestCase {
-                    input: "17".to_string(),
-                    expected: "true".to_string(),
-                    description: "17 is prime".to_string(),
-                },
-                TestCase {
-                    input: "20".to_string(),
-                    expected: "false".to_string(),
-                    description: "20 is not prime".to_string(),
-                },
-                TestCase {
-                    input: "1".to_string(),
-                    expected: "false".to_string(),
-                    description: "1 is not prime".to_string(),
-                },
+                TestCase::new("2", "true", "2 is prime"),
+                TestCase::new("17", "true", "17 is prime"),
+                TestCase::new("20", "false", "20 is not prime"),
+                TestCase::new("1", "false", "1 is not prime"),
+            ],
+            hidden_tests: vec![
+                TestCase::new("91", "false", "hidden test 1"),
+                TestCase::new("0", "false", "hidden test 2"),
             ],
+            reference: Some(Reference::new(
+                "let p = \\n, d -> if d * d > n then true else if n % d == 0 then false else p n (d + 1) in \\n -> if n < 2 then false else p n 2",
+                vec![Gen::int(-5, 300)],
+            )),
         },
         Challenge {
             id: 11,
@@ -326,25 +383,19 @@ fn get_regular_challenges() -> Vec<Challenge> {
             type_signature: "[[Int]] -> [Int]".to_string(),
             difficulty: 2,
             par_score: 70,
+            metric: Metric::Chars,
             is_tutorial: false,
             hint: None,
             test_cases: vec![
-                TestCase {
-                    input: "[[1, 2], [3, 4], [5]]".to_string(),
-                    expected: "[1, 2, 3, 4, 5]".to_string(),
-                    description: "flatten nested".to_string(),
-                },
-                TestCase {
-                    input: "[]".to_string(),
-                    expected: "[]".to_string(),
-                    description: "empty list".to_string(),
-                },
-                TestCase {
-                    input: "[[]]".to_string(),
-                    expected: "[]".to_string(),
-                    description: "nested empty".to_string(),
-                },
+                TestCase::new("[[1, 2], [3, 4], [5]]", "[1, 2, 3, 4, 5]", "flatten nested"),
+                TestCase::new("[]", "[]", "empty list"),
+                TestCase::new("[[]]", "[]", "nested empty"),
             ],
+            hidden_tests: vec![
+                TestCase::new("[[7], [], [8, 9]]", "[7, 8, 9]", "hidden test 1"),
+                TestCase::new("[[], []]", "[]", "hidden test 2"),
+            ],
+            reference: Some(Reference::new("concat", vec![Gen::list(0, 5, Gen::list(0, 5, Gen::int(-20, 20)))])),
         },
         Challenge {
             id: 12,
@@ -353,25 +404,22 @@ fn get_regular_challenges() -> Vec<Challenge> {
             type_signature: "[Int] -> [Int]".to_string(),
             difficulty: 4,
             par_score: 180,
+            metric: Metric::Chars,
             is_tutorial: false,
             hint: None,
             test_cases: vec![
-                TestCase {
-                    input: "[3, 1, 4, 1, 5, 9, 2, 6]".to_string(),
-                    expected: "[1, 1, 2, 3, 4, 5, 6, 9]".to_string(),
-                    description: "sort random".to_string(),
-                },
-                TestCase {
-                    input: "[]".to_string(),
-                    expected: "[]".to_string(),
-                    description: "empty list".to_string(),
-                },
-                TestCase {
-                    input: "[5, 4, 3, 2, 1]".to_string(),
-                    expected: "[1, 2, 3, 4, 5]".to_string(),
-                    description: "reverse sorted".to_string(),
-                },
+                TestCase::new("[3, 1, 4, 1, 5, 9, 2, 6]", "[1, 1, 2, 3, 4, 5, 6, 9]", "sort random"),
+                TestCase::new("[]", "[]", "empty list"),
+                TestCase::new("[5, 4, 3, 2, 1]", "[1, 2, 3, 4, 5]", "reverse sorted"),
+            ],
+            hidden_tests: vec![
+                TestCase::new("[2, -3, 2, 0, -3, 8]", "[-3, -3, 0, 2, 2, 8]", "hidden test 1"),
+                TestCase::new("[7]", "[7]", "hidden test 2"),
             ],
+            reference: Some(Reference::new(
+                "let q = \\l -> match l with [] -> [] | p::r -> q [x | x <- r, x < p] ++ [p] ++ q [x | x <- r, x >= p] in q",
+                vec![Gen::list(0, 15, Gen::int(-50, 50))],
+            )),
         },
         Challenge {
             id: 13,
@@ -380,25 +428,25 @@ fn get_regular_challenges() -> Vec<Challenge> {
             type_signature: "Int -> [Int] -> Int".to_string(),
             difficulty: 3,
             par_score: 110,
+            metric: Metric::Chars,
             is_tutorial: false,
             hint: None,
             test_cases: vec![
-                TestCase {
-                    input: "3 [1, 2, 3, 3, 4, 3]".to_string(),
-                    expected: "3".to_string(),
-                    description: "3 appears 3 times".to_string(),
-                },
-                TestCase {
-                    input: "5 [1, 2, 3, 4]".to_string(),
-                    expected: "0".to_string(),
-                    description: "not in list".to_string(),
-                },
-                TestCase {
-                    input: "1 []".to_string(),
-                    expected: "0".to_string(),
-                    description: "empty list".to_string(),
-                },
+                TestCase::new("3 [1, 2, 3, 3, 4, 3]", "3", "3 appears 3 times"),
+                TestCase::new("5 [1, 2, 3, 4]", "0", "not in list"),
+                TestCase::new("1 []", "0", "empty list"),
+            ],
+            hidden_tests: vec![
+                TestCase::new("0 [0, 1, 0, 0]", "3", "hidden test 1"),
+                TestCase::new("-2 [2, -2, 2]", "1", "hidden test 2"),
             ],
+            reference: Some(Reference::new(
+                "\\x, l -> length (filter (\\y -> y == x) l)",
+                vec![
+                    Gen::int(0, 5),
+                    Gen::list(0, 12, Gen::int(0, 5)),
+                ],
+            )),
         },
         Challenge {
             id: 14,
@@ -407,25 +455,22 @@ fn get_regular_challenges() -> Vec<Challenge> {
             type_signature: "[Int] -> [Int]".to_string(),
             difficulty: 3,
             par_score: 150,
+            metric: Metric::Chars,
             is_tutorial: false,
             hint: None,
             test_cases: vec![
-                TestCase {
-                    input: "[1, 2, 3, 2, 4, 1, 5]".to_string(),
-                    expected: "[1, 2, 3, 4, 5]".to_string(),
-                    description: "remove dups".to_string(),
-                },
-                TestCase {
-                    input: "[1, 1, 1]".to_string(),
-                    expected: "[1]".to_string(),
-                    description: "all same".to_string(),
-                },
-                TestCase {
-                    input: "[]".to_string(),
-                    expected: "[]".to_string(),
-                    description: "empty list".to_string(),
-                },
+                TestCase::new("[1, 2, 3, 2, 4, 1, 5]", "[1, 2, 3, 4, 5]", "remove dups"),
+                TestCase::new("[1, 1, 1]", "[1]", "all same"),
+                TestCase::new("[]", "[]", "empty list"),
             ],
+            hidden_tests: vec![
+                TestCase::new("[4, 3, 4, 3, 5, 4]", "[4, 3, 5]", "hidden test 1"),
+                TestCase::new("[-1, 0, -1]", "[-1, 0]", "hidden test 2"),
+            ],
+            reference: Some(Reference::new(
+                "let go = \\seen, l -> match l with [] -> [] | h::t -> if elem h seen then go seen t else h :: go (h :: seen) t in go []",
+                vec![Gen::list(0, 12, Gen::int(0, 6))],
+            )),
         },
         Challenge {
             id: 15,
@@ -434,25 +479,25 @@ fn get_regular_challenges() -> Vec<Challenge> {
             type_signature: "(Int -> Int) -> [Int] -> [Int]".to_string(),
             difficulty: 4,
             par_score: 150,
+            metric: Metric::Chars,
             is_tutorial: false,
             hint: None,
             test_cases: vec![
-                TestCase {
-                    input: "(\\x -> x * 2) [1, 2, 3]".to_string(),
-                    expected: "[2, 4, 6]".to_string(),
-                    description: "double each".to_string(),
-                },
-                TestCase {
-                    input: "(\\x -> x + 1) []".to_string(),
-                    expected: "[]".to_string(),
-                    description: "empty list".to_string(),
-                },
-                TestCase {
-                    input: "(\\x -> x ^ 2) [1, 2, 3, 4]".to_string(),
-                    expected: "[1, 4, 9, 16]".to_string(),
-                    description: "square each".to_string(),
-                },
+                TestCase::new("(\\x -> x * 2) [1, 2, 3]", "[2, 4, 6]", "double each"),
+                TestCase::new("(\\x -> x + 1) []", "[]", "empty list"),
+                TestCase::new("(\\x -> x ^ 2) [1, 2, 3, 4]", "[1, 4, 9, 16]", "square each"),
+            ],
+            hidden_tests: vec![
+                TestCase::new("(\\x -> x - 3) [10, 0, -5]", "[7, -3, -8]", "hidden test 1"),
+                TestCase::new("(\\x -> x * x) [3, -3]", "[9, 9]", "hidden test 2"),
             ],
+            reference: Some(Reference::new(
+                "\\f, l -> foldr (\\x, acc -> f x :: acc) [] l",
+                vec![
+                    Gen::one_of(MAPPERS),
+                    Gen::list(0, 10, Gen::int(-20, 20)),
+                ],
+            )),
         },
         Challenge {
             id: 16,
@@ -461,25 +506,25 @@ fn get_regular_challenges() -> Vec<Challenge> {
             type_signature: "(Int -> Bool) -> [Int] -> [Int]".to_string(),
             difficulty: 4,
             par_score: 150,
+            metric: Metric::Chars,
             is_tutorial: false,
             hint: None,
             test_cases: vec![
-                TestCase {
-                    input: "(\\x -> x > 2) [1, 2, 3, 4]".to_string(),
-                    expected: "[3, 4]".to_string(),
-                    description: "greater than 2".to_string(),
-                },
-                TestCase {
-                    input: "(\\x -> x > 10) [1, 2, 3]".to_string(),
-                    expected: "[]".to_string(),
-                    description: "none match".to_string(),
-                },
-                TestCase {
-                    input: "(\\x -> true) [1, 2, 3]".to_string(),
-                    expected: "[1, 2, 3]".to_string(),
-                    description: "all match".to_string(),
-                },
+                TestCase::new("(\\x -> x > 2) [1, 2, 3, 4]", "[3, 4]", "greater than 2"),
+                TestCase::new("(\\x -> x > 10) [1, 2, 3]", "[]", "none match"),
+                TestCase::new("(\\x -> true) [1, 2, 3]", "[1, 2, 3]", "all match"),
+            ],
+            hidden_tests: vec![
+                TestCase::new("(\\x -> x % 3 == 0) [3, 4, 6, 7, 9]", "[3, 6, 9]", "hidden test 1"),
+                TestCase::new("(\\x -> x < 0) [1, 2]", "[]", "hidden test 2"),
             ],
+            reference: Some(Reference::new(
+                "\\p, l -> foldr (\\x, acc -> if p x then x :: acc else acc) [] l",
+                vec![
+                    Gen::one_of(PREDICATES),
+                    Gen::list(0, 10, Gen::int(-20, 20)),
+                ],
+            )),
         },
         Challenge {
             id: 17,
@@ -488,33 +533,34 @@ fn get_regular_challenges() -> Vec<Challenge> {
             type_signature: "[Int] -> [Int] -> [[Int]]".to_string(),
             difficulty: 2,
             par_score: 70,
+            metric: Metric::Chars,
             is_tutorial: false,
             hint: None,
             test_cases: vec![
-                TestCase {
-                    input: "[1, 2, 3] [4, 5, 6]".to_string(),
-                    expected: "[[1, 4], [2, 5], [3, 6]]".to_string(),
-                    description: "zip equal lists".to_string(),
-                },
-                TestCase {
-                    input: "[1, 2] [3, 4, 5]".to_string(),
-                    expected: "[[1, 3], [2, 4]]".to_string(),
-                    description: "first shorter".to_string(),
-                },
-                TestCase {
-                    input: "[] [1, 2]".to_string(),
-                    expected: "[]".to_string(),
-                    description: "empty first".to_string(),
-                },
+                TestCase::new("[1, 2, 3] [4, 5, 6]", "[[1, 4], [2, 5], [3, 6]]", "zip equal lists"),
+                TestCase::new("[1, 2] [3, 4, 5]", "[[1, 3], [2, 4]]", "first shorter"),
+                TestCase::new("[] [1, 2]", "[]", "empty first"),
             ],
+            hidden_tests: vec![
+                TestCase::new("[7, 8, 9] [1]", "[[7, 1]]", "hidden test 1"),
+                TestCase::new("[-1, -2] [-3, -4]", "[[-1, -3], [-2, -4]]", "hidden test 2"),
+            ],
+            reference: Some(Reference::new(
+                "let z = \\a, b -> match [a, b] with [x::xs, y::ys] -> [x, y] :: z xs ys | _ -> [] in z",
+                vec![
+                    Gen::list(0, 8, Gen::int(-20, 20)),
+                    Gen::list(0, 8, Gen::int(-20, 20)),
+                ],
+            )),
         },
         Challenge {
             id: 18,
             name: "Cartesian Product".to_string(),
-            description: "Create all pairs from two lists.".to_string(),
+            description: "Create all pairs from two lists, in any order.".to_string(),
             type_signature: "[Int] -> [Int] -> [[Int]]".to_string(),
             difficulty: 4,
             par_score: 170,
+            metric: Metric::Chars,
             is_tutorial: false,
             hint: None,
             test_cases: vec![
@@ -522,18 +568,33 @@ fn get_regular_challenges() -> Vec<Challenge> {
                     input: "[1, 2] [3, 4]".to_string(),
                     expected: "[[1, 3], [1, 4], [2, 3], [2, 4]]".to_string(),
                     description: "2x2 product".to_string(),
+                    compare: Comparison::Unordered,
                 },
+                TestCase::new("[] [1, 2]", "[]", "empty first"),
+                TestCase::new("[1] [2]", "[[1, 2]]", "single elements"),
+            ],
+            hidden_tests: vec![
                 TestCase {
-                    input: "[] [1, 2]".to_string(),
-                    expected: "[]".to_string(),
-                    description: "empty first".to_string(),
+                    input: "[1, 2, 3] [0]".to_string(),
+                    expected: "[[1, 0], [2, 0], [3, 0]]".to_string(),
+                    description: "hidden test 1".to_string(),
+                    compare: Comparison::Unordered,
                 },
                 TestCase {
-                    input: "[1] [2]".to_string(),
-                    expected: "[[1, 2]]".to_string(),
-                    description: "single elements".to_string(),
+                    input: "[5] [6, 7]".to_string(),
+                    expected: "[[5, 6], [5, 7]]".to_string(),
+                    description: "hidden test 2".to_string(),
+                    compare: Comparison::Unordered,
                 },
             ],
+            reference: Some(Reference {
+                solution: "\\a, b -> concat (map (\\x -> map (\\y -> [x, y]) b) a)".to_string(),
+                inputs: vec![
+                    Gen::list(0, 4, Gen::int(-20, 20)),
+                    Gen::list(0, 4, Gen::int(-20, 20)),
+                ],
+                compare: Comparison::Unordered,
+            }),
         },
         Challenge {
             id: 19,
@@ -542,25 +603,22 @@ fn get_regular_challenges() -> Vec<Challenge> {
             type_signature: "Int -> [Int]".to_string(),
             difficulty: 4,
             par_score: 180,
+            metric: Metric::Chars,
             is_tutorial: false,
             hint: None,
             test_cases: vec![
-                TestCase {
-                    input: "0".to_string(),
-                    expected: "[1]".to_string(),
-                    description: "row 0".to_string(),
-                },
-                TestCase {
-                    input: "4".to_string(),
-                    expected: "[1, 4, 6, 4, 1]".to_string(),
-                    description: "row 4".to_string(),
-                },
-                TestCase {
-                    input: "2".to_string(),
-                    expected: "[1, 2, 1]".to_string(),
-                    description: "row 2".to_string(),
-                },
+                TestCase::new("0", "[1]", "row 0"),
+                TestCase::new("4", "[1, 4, 6, 4, 1]", "row 4"),
+                TestCase::new("2", "[1, 2, 1]", "row 2"),
+            ],
+            hidden_tests: vec![
+                TestCase::new("1", "[1, 1]", "hidden test 1"),
+                TestCase::new("6", "[1, 6, 15, 20, 15, 6, 1]", "hidden test 2"),
             ],
+            reference: Some(Reference::new(
+                "let add = \\a, b -> match a with [] -> [] | x::xs -> match b with [] -> [] | y::ys -> (x + y) :: add xs ys in let row = \\n -> if n == 0 then [1] else (\\r -> add (0 :: r) (r ++ [0])) (row (n - 1)) in row",
+                vec![Gen::int(0, 12)],
+            )),
         },
         Challenge {
             id: 20,
@@ -569,25 +627,25 @@ fn get_regular_challenges() -> Vec<Challenge> {
             type_signature: "[Int] -> [Int] -> [Int]".to_string(),
             difficulty: 4,
             par_score: 170,
+            metric: Metric::Chars,
             is_tutorial: false,
             hint: None,
             test_cases: vec![
-                TestCase {
-                    input: "[1, 3, 5] [2, 4, 6]".to_string(),
-                    expected: "[1, 2, 3, 4, 5, 6]".to_string(),
-                    description: "interleaved".to_string(),
-                },
-                TestCase {
-                    input: "[] [1, 2, 3]".to_string(),
-                    expected: "[1, 2, 3]".to_string(),
-                    description: "empty first".to_string(),
-                },
-                TestCase {
-                    input: "[1, 2, 3] []".to_string(),
-                    expected: "[1, 2, 3]".to_string(),
-                    description: "empty second".to_string(),
-                },
+                TestCase::new("[1, 3, 5] [2, 4, 6]", "[1, 2, 3, 4, 5, 6]", "interleaved"),
+                TestCase::new("[] [1, 2, 3]", "[1, 2, 3]", "empty first"),
+                TestCase::new("[1, 2, 3] []", "[1, 2, 3]", "empty second"),
             ],
+            hidden_tests: vec![
+                TestCase::new("[-5, 0, 5] [-10, 0, 10]", "[-10, -5, 0, 0, 5, 10]", "hidden test 1"),
+                TestCase::new("[1, 1] [1]", "[1, 1, 1]", "hidden test 2"),
+            ],
+            reference: Some(Reference::new(
+                "let m = \\a, b -> match a with [] -> b | x::xs -> match b with [] -> a | y::ys -> if x <= y then x :: m xs b else y :: m a ys in m",
+                vec![
+                    Gen::sorted(Gen::list(0, 8, Gen::int(-20, 20))),
+                    Gen::sorted(Gen::list(0, 8, Gen::int(-20, 20))),
+                ],
+            )),
         },
         Challenge {
             id: 21,
@@ -596,25 +654,22 @@ fn get_regular_challenges() -> Vec<Challenge> {
             type_signature: "[Int] -> [[Int]]".to_string(),
             difficulty: 4,
             par_score: 190,
+            metric: Metric::Chars,
             is_tutorial: false,
             hint: None,
             test_cases: vec![
-                TestCase {
-                    input: "[1, 1, 2, 3, 3, 3, 2]".to_string(),
-                    expected: "[[1, 1], [2], [3, 3, 3], [2]]".to_string(),
-                    description: "group consecutive".to_string(),
-                },
-                TestCase {
-                    input: "[1, 2, 3]".to_string(),
-                    expected: "[[1], [2], [3]]".to_string(),
-                    description: "all different".to_string(),
-                },
-                TestCase {
-                    input: "[]".to_string(),
-                    expected: "[]".to_string(),
-                    description: "empty list".to_string(),
-                },
+                TestCase::new("[1, 1, 2, 3, 3, 3, 2]", "[[1, 1], [2], [3, 3, 3], [2]]", "group consecutive"),
+                TestCase::new("[1, 2, 3]", "[[1], [2], [3]]", "all different"),
+                TestCase::new("[]", "[]", "empty list"),
+            ],
+            hidden_tests: vec![
+                TestCase::new("[4, 4, 5, 4]", "[[4, 4], [5], [4]]", "hidden test 1"),
+                TestCase::new("[9]", "[[9]]", "hidden test 2"),
             ],
+            reference: Some(Reference::new(
+                "foldr (\\x, acc -> match acc with [] -> [[x]] | g::rest -> if head g == x then (x :: g) :: rest else [x] :: acc) []",
+                vec![Gen::list(0, 12, Gen::int(0, 3))],
+            )),
         },
         Challenge {
             id: 22,
@@ -623,25 +678,22 @@ fn get_regular_challenges() -> Vec<Challenge> {
             type_signature: "[Int] -> [[Int]]".to_string(),
             difficulty: 4,
             par_score: 190,
+            metric: Metric::Chars,
             is_tutorial: false,
             hint: None,
             test_cases: vec![
-                TestCase {
-                    input: "[1, 1, 1, 2, 3, 3]".to_string(),
-                    expected: "[[3, 1], [1, 2], [2, 3]]".to_string(),
-                    description: "encode runs".to_string(),
-                },
-                TestCase {
-                    input: "[1, 2, 3]".to_string(),
-                    expected: "[[1, 1], [1, 2], [1, 3]]".to_string(),
-                    description: "no runs".to_string(),
-                },
-                TestCase {
-                    input: "[]".to_string(),
-                    expected: "[]".to_string(),
-                    description: "empty list".to_string(),
-                },
+                TestCase::new("[1, 1, 1, 2, 3, 3]", "[[3, 1], [1, 2], [2, 3]]", "encode runs"),
+                TestCase::new("[1, 2, 3]", "[[1, 1], [1, 2], [1, 3]]", "no runs"),
+                TestCase::new("[]", "[]", "empty list"),
+            ],
+            hidden_tests: vec![
+                TestCase::new("[7, 7, 7, 7, 0]", "[[4, 7], [1, 0]]", "hidden test 1"),
+                TestCase::new("[-1, 2, 2]", "[[1, -1], [2, 2]]", "hidden test 2"),
             ],
+            reference: Some(Reference::new(
+                "\\l -> map (\\g -> [length g, head g]) (foldr (\\x, acc -> match acc with [] -> [[x]] | g::rest -> if head g == x then (x :: g) :: rest else [x] :: acc) [] l)",
+                vec![Gen::list(0, 12, Gen::int(0, 3))],
+            )),
         },
         Challenge {
             id: 23,
@@ -650,25 +702,22 @@ fn get_regular_challenges() -> Vec<Challenge> {
             type_signature: "[Int] -> [Int]".to_string(),
             difficulty: 3,
             par_score: 140,
+            metric: Metric::Chars,
             is_tutorial: false,
             hint: None,
             test_cases: vec![
-                TestCase {
-                    input: "[1, 2, 3, 4]".to_string(),
-                    expected: "[1, 3, 6, 10]".to_string(),
-                    description: "partial sums".to_string(),
-                },
-                TestCase {
-                    input: "[5]".to_string(),
-                    expected: "[5]".to_string(),
-                    description: "single element".to_string(),
-                },
-                TestCase {
-                    input: "[]".to_string(),
-                    expected: "[]".to_string(),
-                    description: "empty list".to_string(),
-                },
+                TestCase::new("[1, 2, 3, 4]", "[1, 3, 6, 10]", "partial sums"),
+                TestCase::new("[5]", "[5]", "single element"),
+                TestCase::new("[]", "[]", "empty list"),
             ],
+            hidden_tests: vec![
+                TestCase::new("[-1, 1, -1, 1]", "[-1, 0, -1, 0]", "hidden test 1"),
+                TestCase::new("[0, 0, 5]", "[0, 0, 5]", "hidden test 2"),
+            ],
+            reference: Some(Reference::new(
+                "\\l -> tail (reverse (fold (\\acc, x -> (head acc + x) :: acc) [0] l))",
+                vec![Gen::list(0, 12, Gen::int(-50, 50))],
+            )),
         },
         Challenge {
             id: 24,
@@ -677,25 +726,22 @@ fn get_regular_challenges() -> Vec<Challenge> {
             type_signature: "[Int] -> Int".to_string(),
             difficulty: 3,
             par_score: 120,
+            metric: Metric::Chars,
             is_tutorial: false,
             hint: None,
             test_cases: vec![
-                TestCase {
-                    input: "[3, 1, 4, 1, 5, 9, 2]".to_string(),
-                    expected: "9".to_string(),
-                    description: "max of list".to_string(),
-                },
-                TestCase {
-                    input: "[-5, -2, -10]".to_string(),
-                    expected: "-2".to_string(),
-                    description: "all negative".to_string(),
-                },
-                TestCase {
-                    input: "[42]".to_string(),
-                    expected: "42".to_string(),
-                    description: "single element".to_string(),
-                },
+                TestCase::new("[3, 1, 4, 1, 5, 9, 2]", "9", "max of list"),
+                TestCase::new("[-5, -2, -10]", "-2", "all negative"),
+                TestCase::new("[42]", "42", "single element"),
+            ],
+            hidden_tests: vec![
+                TestCase::new("[-3, 0, -7]", "0", "hidden test 1"),
+                TestCase::new("[8, 8, 2]", "8", "hidden test 2"),
             ],
+            reference: Some(Reference::new(
+                "\\l -> fold (\\a, x -> if x > a then x else a) (head l) l",
+                vec![Gen::list(1, 12, Gen::int(-100, 100))],
+            )),
         },
         Challenge {
             id: 25,
@@ -704,25 +750,25 @@ fn get_regular_challenges() -> Vec<Challenge> {
             type_signature: "(Int -> Bool) -> [Int] -> Bool".to_string(),
             difficulty: 3,
             par_score: 130,
+            metric: Metric::Chars,
             is_tutorial: false,
             hint: None,
             test_cases: vec![
-                TestCase {
-                    input: "(\\x -> x > 0) [1, 2, 3]".to_string(),
-                    expected: "true".to_string(),
-                    description: "all positive".to_string(),
-                },
-                TestCase {
-                    input: "(\\x -> x > 0) [1, -1, 3]".to_string(),
-                    expected: "false".to_string(),
-                    description: "has negative".to_string(),
-                },
-                TestCase {
-                    input: "(\\x -> x > 0) []".to_string(),
-                    expected: "true".to_string(),
-                    description: "empty list".to_string(),
-                },
+                TestCase::new("(\\x -> x > 0) [1, 2, 3]", "true", "all positive"),
+                TestCase::new("(\\x -> x > 0) [1, -1, 3]", "false", "has negative"),
+                TestCase::new("(\\x -> x > 0) []", "true", "empty list"),
+            ],
+            hidden_tests: vec![
+                TestCase::new("(\\x -> x % 2 == 0) [2, 4, 6]", "true", "hidden test 1"),
+                TestCase::new("(\\x -> x % 2 == 0) [2, 3, 4]", "false", "hidden test 2"),
             ],
+            reference: Some(Reference::new(
+                "\\p, l -> length (filter p l) == length l",
+                vec![
+                    Gen::one_of(PREDICATES),
+                    Gen::list(0, 10, Gen::int(-20, 20)),
+                ],
+            )),
         },
     ]
 }
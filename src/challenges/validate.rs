@@ -0,0 +1,200 @@
+use super::{Challenge, TestCase};
+use crate::lang::{interpreter, parser};
+use crate::lang::types::{self, Type, TypeChecker};
+use crate::runner::{Runner, TestResult, RANDOM_TESTS};
+use anyhow::Result;
+use std::fmt;
+
+/// Random inputs for validation always come from the same seed, so a
+/// problem found once can be found again.
+const VALIDATION_SEED: u64 = 0;
+
+/// What the solution is called when type-checking test inputs.
+const SOLUTION: &str = "solution";
+
+/// Something wrong with a challenge definition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub challenge_id: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "challenge {}: {}", self.challenge_id, self.message)
+    }
+}
+
+/// Check that each challenge's type signature parses, its tests fit the
+/// signature, any builtin that passes the tests fits it too, and its
+/// reference solution has that type, passes every test and fits within par.
+pub fn validate(challenges: &[Challenge]) -> Vec<Problem> {
+    let runner = Runner::new();
+    let mut problems = Vec::new();
+
+    for (i, challenge) in challenges.iter().enumerate() {
+        let mut report = |message: String| problems.push(Problem { challenge_id: challenge.id, message });
+        if challenges[..i].iter().any(|c| c.id == challenge.id) {
+            report("another challenge has the same id".to_string());
+        }
+        for message in validate_challenge(challenge, &runner) {
+            report(message);
+        }
+    }
+
+    problems
+}
+
+fn validate_challenge(challenge: &Challenge, runner: &Runner) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let signature = match parser::parse_type(&challenge.type_signature) {
+        Ok(signature) => Some(signature),
+        Err(e) => {
+            problems.push(format!("type signature {:?} doesn't parse: {}", challenge.type_signature, e));
+            None
+        }
+    };
+
+    if let Some(signature) = &signature {
+        for test in challenge.test_cases.iter().chain(&challenge.hidden_tests) {
+            if let Err(e) = check_test_types(signature, test) {
+                problems.push(format!("test {:?} doesn't fit the type signature: {}", test.description, e));
+            }
+        }
+    }
+
+    if let Some(signature) = &signature {
+        problems.extend(check_builtins(challenge, signature, runner));
+    }
+
+    let Some(reference) = &challenge.reference else {
+        problems.push("no reference solution".to_string());
+        return problems;
+    };
+
+    if let Some(signature) = &signature {
+        if let Err(e) = check_solution_type(&reference.solution, signature) {
+            problems.push(format!("reference solution doesn't fit the type signature: {}", e));
+        }
+    }
+
+    // The reference shows par can be reached
+    match challenge.metric.measure(&reference.solution) {
+        Some(score) if score > challenge.par_score => problems.push(format!(
+            "par score {} is below the reference solution's {} {}",
+            challenge.par_score, score, challenge.metric.unit()
+        )),
+        Some(_) => {}
+        None => problems.push(format!("reference solution can't be measured in {}", challenge.metric.unit())),
+    }
+
+    let mut run = runner.spawn_submission(&reference.solution, challenge, VALIDATION_SEED, RANDOM_TESTS);
+    run.wait();
+    for result in run.results().iter().flatten().filter(|r| !r.passed) {
+        problems.push(format!("reference solution fails {:?}: {}", result.description, failure_reason(result)));
+    }
+
+    problems
+}
+
+/// Type-check a test as the solution applied to its input, with the result
+/// having the type of its expected value.
+fn check_test_types(signature: &Type, test: &TestCase) -> Result<()> {
+    let mut checker = TypeChecker::new();
+    let mut env = types::get_builtin_env();
+    env.insert(SOLUTION.to_string(), signature.clone());
+
+    let applied = if test.input.is_empty() {
+        SOLUTION.to_string()
+    } else {
+        format!("{} {}", SOLUTION, test.input)
+    };
+    let result = checker.infer(&parser::parse(&applied)?, &mut env)?;
+    let expected = checker.infer(&parser::parse(&test.expected)?, &mut env)?;
+    checker.unify(&result, &expected)
+}
+
+fn check_solution_type(code: &str, signature: &Type) -> Result<()> {
+    let mut checker = TypeChecker::new();
+    let ty = checker.infer(&parser::parse(code)?, &mut types::get_builtin_env())?;
+    checker.check_signature(&ty, signature)
+}
+
+/// A builtin that passes a challenge's tests is a solution, so if its type
+/// doesn't fit the signature, the type checker has the builtin wrong.
+fn check_builtins(challenge: &Challenge, signature: &Type, runner: &Runner) -> Vec<String> {
+    let mut names: Vec<String> = interpreter::get_builtin_env().into_keys().collect();
+    names.sort();
+    names.into_iter()
+        .filter(|name| {
+            let mut run = runner.spawn_tests(name, &challenge.test_cases, None);
+            run.wait();
+            run.results().iter().all(|r| r.as_ref().is_some_and(|r| r.passed))
+        })
+        .filter_map(|name| {
+            let e = check_solution_type(&name, signature).err()?;
+            Some(format!("builtin {} passes the tests but doesn't fit the type signature: {}", name, e))
+        })
+        .collect()
+}
+
+fn failure_reason(result: &TestResult) -> String {
+    match &result.error {
+        Some(error) => error.to_string(),
+        None => format!("expected {}, got {}", result.expected, result.actual),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenges::get_all_challenges;
+
+    #[test]
+    fn test_built_in_challenges_are_valid() {
+        let problems = validate(&get_all_challenges());
+        let messages: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+        assert!(problems.is_empty(), "{:#?}", messages);
+    }
+
+    #[test]
+    fn test_reports_broken_challenge() {
+        let double = get_all_challenges().into_iter().find(|c| c.id == 1).unwrap();
+        let mut wrong_type = double.clone();
+        wrong_type.type_signature = "Int -> Bool".to_string();
+        wrong_type.par_score = 5;
+        let mut wrong_expected = double;
+        wrong_expected.test_cases[0].expected = "11".to_string();
+
+        let messages: Vec<String> = validate(&[wrong_type, wrong_expected])
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        let has = |text: &str| messages.iter().any(|m| m.contains(text));
+
+        assert!(has("challenge 1: another challenge has the same id"), "{:#?}", messages);
+        assert!(has("doesn't fit the type signature: Type mismatch: Bool vs Int"), "{:#?}", messages);
+        assert!(has("reference solution doesn't fit the type signature"), "{:#?}", messages);
+        assert!(has("par score 5 is below the reference solution's 7 chars"), "{:#?}", messages);
+        assert!(has("fails \"double 5\": Your solution has type Int -> Int, expected Int -> Bool"), "{:#?}", messages);
+        assert!(has("reference solution fails \"double 5\": expected 11, got 10"), "{:#?}", messages);
+    }
+
+    #[test]
+    fn test_type_signatures_round_trip() {
+        let mut signatures: Vec<String> = get_all_challenges().into_iter().map(|c| c.type_signature).collect();
+        signatures.extend([
+            "(a -> b) -> [a] -> [b]".to_string(),
+            "Map String [Int] -> Set (Map Int Bool)".to_string(),
+            "{ age: Int, name: String, .. } -> a -> a".to_string(),
+        ]);
+        for signature in signatures {
+            assert_eq!(parser::parse_type(&signature).unwrap().to_string(), signature);
+        }
+
+        assert!(parser::parse_type("Int -> Float").is_err());
+        assert!(parser::parse_type("[Int").is_err());
+        assert!(parser::parse_type("{ a: Int, a: Bool }").is_err());
+    }
+}
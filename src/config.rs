@@ -0,0 +1,131 @@
+use crate::storage::Storage;
+use crate::ui::keys::{Action, KeyBinding, Keys};
+use crate::ui::theme::{self, Theme};
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::sync::OnceLock;
+
+const CONFIG_FILE: &str = "config.json";
+
+/// The config file as written, before anything in it is checked.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    /// A built-in theme or one from `themes`.
+    theme: Option<String>,
+    /// Themes of the player's own: colors by role, on top of the default
+    /// theme or the built-in one named by `base`.
+    themes: HashMap<String, HashMap<String, String>>,
+    /// Keys for actions, replacing their default keys.
+    keys: HashMap<Action, Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub theme: Theme,
+    pub keys: Keys,
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Read the config file from the data directory, if there is one, for the
+/// whole game to use. `NO_COLOR` turns colors off whatever the theme.
+pub fn load() -> Result<()> {
+    let path = Storage::data_dir().join(CONFIG_FILE);
+    let json = match fs::read_to_string(&path) {
+        Ok(json) => Some(json),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e).with_context(|| format!("Could not read {}", path.display())),
+    };
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    let config = parse(json.as_deref().unwrap_or("{}"), no_color)
+        .with_context(|| format!("Invalid config file {}", path.display()))?;
+    // Only the first load counts
+    _ = CONFIG.set(config);
+    Ok(())
+}
+
+/// The loaded config, or the defaults if none was.
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+pub fn theme() -> &'static Theme {
+    &get().theme
+}
+
+pub fn keys() -> &'static Keys {
+    &get().keys
+}
+
+fn parse(json: &str, no_color: bool) -> Result<Config> {
+    let file: ConfigFile = serde_json::from_str(json)?;
+
+    let theme = match file.theme.as_deref().unwrap_or("default") {
+        _ if no_color => Theme::monochrome(),
+        name => match (Theme::named(name), file.themes.get(name)) {
+            (_, Some(colors)) => custom_theme(colors).with_context(|| format!("In theme '{}'", name))?,
+            (Some(theme), None) => theme,
+            (None, None) => return Err(anyhow!("Unknown theme '{}'", name)),
+        },
+    };
+
+    let mut keys = Keys::default();
+    for (action, names) in file.keys {
+        let bindings = names.iter()
+            .map(|name| name.parse::<KeyBinding>())
+            .collect::<Result<Vec<_>>>()?;
+        keys.bind(action, bindings);
+    }
+
+    Ok(Config { theme, keys })
+}
+
+fn custom_theme(colors: &HashMap<String, String>) -> Result<Theme> {
+    let mut theme = match colors.get("base") {
+        Some(base) => Theme::named(base).ok_or_else(|| anyhow!("Unknown base theme '{}'", base))?,
+        None => Theme::default(),
+    };
+    for (role, color) in colors.iter().filter(|(role, _)| *role != "base") {
+        theme.set(role, theme::parse_color(color)?)?;
+    }
+    Ok(theme)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use crossterm::style::Color;
+
+    #[test]
+    fn test_parse() {
+        let config = parse("{}", false).unwrap();
+        assert_eq!(config.theme, Theme::default());
+
+        let config = parse(r##"{
+            "theme": "mine",
+            "themes": { "mine": { "base": "high_contrast", "keyword": "#ff0000" } },
+            "keys": { "run": ["f5", "ctrl+r"], "quit": [] }
+        }"##, false).unwrap();
+        assert_eq!(config.theme.keyword, Color::Rgb { r: 255, g: 0, b: 0 });
+        assert_eq!(config.theme.muted, Color::Grey);
+        let f5 = KeyEvent::new(KeyCode::F(5), KeyModifiers::NONE);
+        assert_eq!(config.keys.action(&f5, &[Action::Run]), Some(Action::Run));
+        assert_eq!(config.keys.label(Action::Quit), "(unbound)");
+
+        assert_eq!(parse(r#"{ "theme": "high_contrast" }"#, true).unwrap().theme, Theme::monochrome());
+
+        let error = |json| format!("{:#}", parse(json, false).unwrap_err());
+        assert_eq!(error(r#"{ "theme": "neon" }"#), "Unknown theme 'neon'");
+        assert_eq!(
+            error(r#"{ "theme": "t", "themes": { "t": { "text": "orange" } } }"#),
+            "In theme 't': Unknown color 'orange'"
+        );
+        assert!(error(r#"{ "keys": { "teleport": ["t"] } }"#).contains("unknown variant `teleport`"));
+        assert_eq!(error(r#"{ "keys": { "run": ["ctrl+"] } }"#), "Unknown key 'ctrl+'");
+    }
+}
@@ -0,0 +1,156 @@
+use crate::storage::Storage;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::Cell;
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe, PanicHookInfo};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const REPORT_FILE: &str = "crash_report.txt";
+const RECOVERY_FILE: &str = "recovery.json";
+
+/// Code being edited that hasn't been saved as a solution yet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recovery {
+    pub challenge_id: usize,
+    pub code: String,
+}
+
+static CURRENT: Mutex<Option<Recovery>> = Mutex::new(None);
+
+thread_local! {
+    /// Set while `catch_panic` is running on this thread, so the hook leaves
+    /// panics it is about to handle alone.
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Remember the editor's buffer so a crash can save it.
+pub fn set_current(challenge_id: usize, code: String) {
+    let mut current = CURRENT.lock().unwrap_or_else(|e| e.into_inner());
+    *current = Some(Recovery { challenge_id, code });
+}
+
+pub fn clear_current() {
+    let mut current = CURRENT.lock().unwrap_or_else(|e| e.into_inner());
+    *current = None;
+}
+
+/// Install a panic hook that restores the terminal, saves the code being
+/// edited for recovery and writes a crash report before the usual message.
+pub fn install_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if CATCHING.with(Cell::get) {
+            return;
+        }
+
+        let _ = crate::ui::restore_terminal();
+        let report = write_report(info);
+        default_hook(info);
+
+        match report {
+            Ok(path) => eprintln!("Crash report written to {}", path.display()),
+            Err(e) => eprintln!("Could not write crash report: {}", e),
+        }
+    }));
+}
+
+fn write_report(info: &PanicHookInfo) -> io::Result<PathBuf> {
+    let dir = Storage::data_dir();
+    fs::create_dir_all(&dir)?;
+
+    // The panic may have happened while the lock was held on this thread
+    let current = match CURRENT.try_lock() {
+        Ok(current) => current.clone(),
+        Err(_) => None,
+    };
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let mut report = format!(
+        "less {} crashed (unix time {})\n\n{}\n",
+        env!("CARGO_PKG_VERSION"),
+        timestamp,
+        info
+    );
+
+    match &current {
+        Some(recovery) => {
+            report.push_str(&format!(
+                "\nChallenge: {}\nCode:\n{}\n",
+                recovery.challenge_id, recovery.code
+            ));
+            let json = serde_json::to_string(recovery).map_err(io::Error::other)?;
+            fs::write(dir.join(RECOVERY_FILE), json)?;
+        }
+        None => report.push_str("\nNo challenge was open.\n"),
+    }
+
+    report.push_str(&format!("\nBacktrace:\n{}\n", Backtrace::force_capture()));
+
+    let path = dir.join(REPORT_FILE);
+    fs::write(&path, report)?;
+    Ok(path)
+}
+
+/// The code saved by the last crash, if any. It stays saved until
+/// `discard_recovery`, so declining it doesn't lose it.
+pub fn recovery() -> Option<Recovery> {
+    let json = fs::read_to_string(Storage::data_dir().join(RECOVERY_FILE)).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+/// Forget the code saved by the last crash, once it's been opened.
+pub fn discard_recovery() {
+    fs::remove_file(Storage::data_dir().join(RECOVERY_FILE)).ok();
+}
+
+/// Run `f`, turning a panic into an error message instead of unwinding
+/// further.
+pub fn catch_panic<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    let was_catching = CATCHING.with(|c| c.replace(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING.with(|c| c.set(was_catching));
+
+    result.map_err(|payload| panic_message(payload.as_ref()))
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catch_panic() {
+        assert_eq!(catch_panic(|| 1 + 1), Ok(2));
+        let result: Result<(), String> = catch_panic(|| panic!("boom {}", 1));
+        assert_eq!(result, Err("boom 1".to_string()));
+    }
+
+    #[test]
+    fn test_catch_panic_nests() {
+        let outer = catch_panic(|| {
+            let inner: Result<(), String> = catch_panic(|| panic!("inner"));
+            assert_eq!(inner, Err("inner".to_string()));
+            CATCHING.with(Cell::get)
+        });
+        assert_eq!(outer, Ok(true));
+        assert!(!CATCHING.with(Cell::get));
+    }
+}
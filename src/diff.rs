@@ -0,0 +1,322 @@
+use crate::lang::interpreter::Value;
+use std::fmt;
+use std::ops::Range;
+
+/// A plain copy of a result value. Unlike `Value` it can be sent between
+/// threads, and it can be compared and printed piece by piece.
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub enum Data {
+    Number(i64),
+    Bool(bool),
+    String(String),
+    List(Vec<Data>),
+    Record(Vec<(String, Data)>),
+    Map(Vec<(Data, Data)>),
+    Set(Vec<Data>),
+    /// Functions and anything else with no structure to compare.
+    Opaque(String),
+}
+
+impl From<&Value> for Data {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Number(n) => Data::Number(*n),
+            Value::Bool(b) => Data::Bool(*b),
+            Value::String(s) => Data::String(s.clone()),
+            Value::List(items) => Data::List(items.iter().map(Data::from).collect()),
+            Value::Record(fields) => Data::Record(
+                fields.iter().map(|(name, v)| (name.clone(), Data::from(v))).collect(),
+            ),
+            Value::Map(entries) => Data::Map(
+                entries.iter().map(|(k, v)| (Data::from(k), Data::from(v))).collect(),
+            ),
+            Value::Set(items) => Data::Set(items.iter().map(Data::from).collect()),
+            other => Data::Opaque(other.to_string_repr()),
+        }
+    }
+}
+
+impl Data {
+    /// Print the value the way the interpreter does, along with the char
+    /// range taken up by the part at `path`, if there is one.
+    pub fn render(&self, path: &[Step]) -> (String, Option<Range<usize>>) {
+        let mut out = String::new();
+        let mut highlight = None;
+        self.write(Some(path), &mut out, &mut highlight);
+
+        let to_chars = |byte: usize| out[..byte].chars().count();
+        let highlight = highlight.map(|r: Range<usize>| to_chars(r.start)..to_chars(r.end));
+        (out, highlight)
+    }
+
+    /// `path` is the rest of the highlighted path while this value is on it.
+    fn write(&self, path: Option<&[Step]>, out: &mut String, highlight: &mut Option<Range<usize>>) {
+        if path == Some(&[]) {
+            let start = out.len();
+            self.write(None, out, highlight);
+            *highlight = Some(start..out.len());
+            return;
+        }
+
+        // The rest of the path if its next step is `step`
+        let follow = |step: &Step| match path {
+            Some([first, rest @ ..]) if first == step => Some(rest),
+            _ => None,
+        };
+
+        match self {
+            Data::Number(n) => out.push_str(&n.to_string()),
+            Data::Bool(b) => out.push_str(&b.to_string()),
+            Data::String(s) => {
+                out.push('"');
+                out.push_str(s);
+                out.push('"');
+            }
+            Data::List(items) | Data::Set(items) => {
+                out.push_str(if matches!(self, Data::Set(_)) { "#[" } else { "[" });
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    item.write(follow(&Step::Index(i)), out, highlight);
+                }
+                out.push(']');
+            }
+            Data::Record(fields) if fields.is_empty() => out.push_str("{}"),
+            Data::Record(fields) => {
+                out.push_str("{ ");
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    out.push_str(name);
+                    out.push_str(" = ");
+                    value.write(follow(&Step::Field(name.clone())), out, highlight);
+                }
+                out.push_str(" }");
+            }
+            Data::Map(entries) => {
+                out.push_str("#{");
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    let start = out.len();
+                    key.write(None, out, highlight);
+                    out.push_str(" => ");
+                    value.write(follow(&Step::Key(key.clone())), out, highlight);
+                    if follow(&Step::Index(i)) == Some(&[]) {
+                        *highlight = Some(start..out.len());
+                    }
+                }
+                out.push('}');
+            }
+            Data::Opaque(text) => out.push_str(text),
+        }
+    }
+}
+
+impl fmt::Display for Data {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        self.write(None, &mut out, &mut None);
+        write!(f, "{}", out)
+    }
+}
+
+/// One step from a value into one of its parts.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    /// An element of a list or set, or a whole entry of a map.
+    Index(usize),
+    Field(String),
+    /// The value stored under a key of a map.
+    Key(Data),
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Index(i) => write!(f, "[{}]", i),
+            Step::Field(name) => write!(f, ".{}", name),
+            Step::Key(key) => write!(f, "[{}]", key),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffKind {
+    Value { expected: String, actual: String },
+    Length { expected: usize, actual: usize },
+    MissingField(String),
+    ExtraField(String),
+}
+
+/// A way the actual value differs from the expected one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference {
+    pub path: Vec<Step>,
+    pub kind: DiffKind,
+}
+
+impl Difference {
+    /// The part of each value to highlight: the differing value itself, the
+    /// first element past the end of the shorter list, or the field.
+    pub fn highlight(&self) -> Vec<Step> {
+        let mut path = self.path.clone();
+        match &self.kind {
+            DiffKind::Value { .. } => {}
+            DiffKind::Length { expected, actual } => path.push(Step::Index(*expected.min(actual))),
+            DiffKind::MissingField(name) | DiffKind::ExtraField(name) => {
+                path.push(Step::Field(name.clone()))
+            }
+        }
+        path
+    }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.path.is_empty() {
+            write!(f, "at ")?;
+            for step in &self.path {
+                write!(f, "{}", step)?;
+            }
+            write!(f, ": ")?;
+        }
+
+        match &self.kind {
+            DiffKind::Value { expected, actual } => write!(f, "expected {}, got {}", expected, actual),
+            DiffKind::Length { expected, actual } => {
+                write!(f, "expected {} elements, got {}", expected, actual)
+            }
+            DiffKind::MissingField(name) => write!(f, "missing field {}", name),
+            DiffKind::ExtraField(name) => write!(f, "unexpected field {}", name),
+        }
+    }
+}
+
+/// How `actual` differs from `expected`. Collections are followed into their
+/// first differing element, and a length mismatch is reported alongside it.
+pub fn diff(expected: &Data, actual: &Data) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    diff_at(expected, actual, &mut Vec::new(), &mut differences);
+    differences
+}
+
+fn diff_at(expected: &Data, actual: &Data, path: &mut Vec<Step>, out: &mut Vec<Difference>) {
+    match (expected, actual) {
+        _ if expected == actual => {}
+        (Data::List(e), Data::List(a)) | (Data::Set(e), Data::Set(a)) => {
+            if let Some(i) = (0..e.len().min(a.len())).find(|&i| e[i] != a[i]) {
+                path.push(Step::Index(i));
+                diff_at(&e[i], &a[i], path, out);
+                path.pop();
+            }
+            if e.len() != a.len() {
+                report(out, path, DiffKind::Length { expected: e.len(), actual: a.len() });
+            }
+        }
+        (Data::Record(e), Data::Record(a)) => {
+            let differing = e.iter().find_map(|(name, ev)| {
+                match a.iter().find(|(n, _)| n == name) {
+                    Some((_, av)) if av == ev => None,
+                    Some((_, av)) => Some((name, Some((ev, av)))),
+                    None => Some((name, None)),
+                }
+            });
+            match differing {
+                Some((name, Some((ev, av)))) => {
+                    path.push(Step::Field(name.clone()));
+                    diff_at(ev, av, path, out);
+                    path.pop();
+                }
+                Some((name, None)) => report(out, path, DiffKind::MissingField(name.clone())),
+                None => {
+                    if let Some((name, _)) = a.iter().find(|(n, _)| !e.iter().any(|(m, _)| m == n)) {
+                        report(out, path, DiffKind::ExtraField(name.clone()));
+                    }
+                }
+            }
+        }
+        (Data::Map(e), Data::Map(a)) => {
+            let same_keys = e.len() == a.len() && e.iter().zip(a).all(|((ek, _), (ak, _))| ek == ak);
+            if same_keys {
+                if let Some(((key, ev), (_, av))) = e.iter().zip(a).find(|((_, ev), (_, av))| ev != av) {
+                    path.push(Step::Key(key.clone()));
+                    diff_at(ev, av, path, out);
+                    path.pop();
+                }
+                return;
+            }
+
+            let entry = |(k, v): &(Data, Data)| format!("{} => {}", k, v);
+            if let Some(i) = (0..e.len().min(a.len())).find(|&i| e[i] != a[i]) {
+                path.push(Step::Index(i));
+                report(out, path, DiffKind::Value { expected: entry(&e[i]), actual: entry(&a[i]) });
+                path.pop();
+            }
+            if e.len() != a.len() {
+                report(out, path, DiffKind::Length { expected: e.len(), actual: a.len() });
+            }
+        }
+        _ => report(out, path, DiffKind::Value { expected: expected.to_string(), actual: actual.to_string() }),
+    }
+}
+
+fn report(out: &mut Vec<Difference>, path: &[Step], kind: DiffKind) {
+    out.push(Difference { path: path.to_vec(), kind });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::{interpreter, parser};
+    use std::rc::Rc;
+
+    fn data(code: &str) -> Data {
+        let expr = parser::parse(code).unwrap();
+        let env = Rc::new(interpreter::get_builtin_env());
+        let value = interpreter::Interpreter::new().eval(&expr, &env).unwrap();
+        assert_eq!(Data::from(&value).to_string(), value.to_string_repr());
+        Data::from(&value)
+    }
+
+    fn messages(expected: &str, actual: &str) -> Vec<String> {
+        diff(&data(expected), &data(actual)).iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn test_equal_values_have_no_diff() {
+        assert!(messages("[[1, 2], [3]]", "[[1,2],[3]]").is_empty());
+    }
+
+    #[test]
+    fn test_first_difference_in_nested_list() {
+        assert_eq!(messages("[[1, 2], [3]]", "[[1, 5], [3]]"), vec!["at [0][1]: expected 2, got 5"]);
+        assert_eq!(messages("[1, 2, 3]", "[1, 9]"), vec![
+            "at [1]: expected 2, got 9",
+            "expected 3 elements, got 2",
+        ]);
+        assert_eq!(messages("[[1], [2, 3]]", "[[1], [2]]"), vec!["at [1]: expected 2 elements, got 1"]);
+    }
+
+    #[test]
+    fn test_record_and_map_differences() {
+        assert_eq!(messages("{ a = 1, b = [2] }", "{ a = 1, b = [3] }"), vec!["at .b[0]: expected 2, got 3"]);
+        assert_eq!(messages("{ a = 1, b = 2 }", "{ a = 1 }"), vec!["missing field b"]);
+        assert_eq!(messages("#{1 => \"a\"}", "#{1 => \"b\"}"), vec!["at [1]: expected \"a\", got \"b\""]);
+        assert_eq!(messages("#{1 => 2}", "#{3 => 2}"), vec!["at [0]: expected 1 => 2, got 3 => 2"]);
+    }
+
+    #[test]
+    fn test_render_highlights_path() {
+        let value = data("[[1, 2], [3, 45]]");
+        let (text, range) = value.render(&[Step::Index(1), Step::Index(1)]);
+        let highlighted: String = text.chars().skip(range.clone().unwrap().start).take(range.unwrap().len()).collect();
+        assert_eq!(highlighted, "45");
+
+        let (_, range) = value.render(&[Step::Index(5)]);
+        assert_eq!(range, None);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::lang::ast::Span;
+use crate::lang::error::error_span;
+use crate::lang::lexer::{self, TokenKind};
+use crate::lang::{interpreter, parser};
+use crate::lang::symbols::{Occurrence, Symbol, Symbols};
+use crate::lang::types::{self, generalize, Type, TypeChecker};
+use std::ops::Range;
+
+/// A parse or type error found without running the code.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    /// The code it's about, or `None` for the program as a whole.
+    pub span: Option<Span>,
+}
+
+/// A name that finishes the one being typed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub name: String,
+    /// Its type, if it's known.
+    pub ty: Option<Type>,
+}
+
+/// What parsing and type checking tell about a program, for editors to show
+/// as it's written.
+pub struct Analysis {
+    source: String,
+    /// The program's type, if it has one.
+    ty: Option<Type>,
+    checker: TypeChecker,
+    symbols: Option<Symbols>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Analysis {
+    /// Check `source`, against a challenge's type signature if given.
+    pub fn new(source: &str, signature: Option<&Type>) -> Analysis {
+        let mut checker = TypeChecker::new();
+        let expr = match parser::parse(source) {
+            Ok(expr) => expr,
+            Err(e) => {
+                let diagnostics = vec![Diagnostic { message: e.to_string(), span: error_span(&e) }];
+                return Analysis { source: source.to_string(), ty: None, checker, symbols: None, diagnostics };
+            }
+        };
+
+        let symbols = Symbols::resolve(source, &expr);
+        let mut diagnostics = Vec::new();
+        let ty = match checker.infer(&expr, &mut types::get_builtin_env()) {
+            Ok(ty) => {
+                if let Some(signature) = signature {
+                    if checker.check_signature(&ty, signature).is_err() {
+                        diagnostics.push(Diagnostic {
+                            message: format!("Your solution has type {}, expected {}", generalize(&ty), signature),
+                            span: None,
+                        });
+                    }
+                }
+                Some(generalize(&ty))
+            }
+            Err(e) => {
+                // An undefined name is the one place a type error can be
+                // narrowed down to a single identifier
+                let builtins = types::get_builtin_env();
+                diagnostics.extend(symbols.iter()
+                    .flat_map(|symbols| &symbols.occurrences)
+                    .filter(|o| o.symbol == Symbol::Free && !builtins.contains_key(&o.name))
+                    .map(|o| Diagnostic {
+                        message: format!("Undefined variable: {}", o.name),
+                        span: Some(Span::new(o.span.start, o.span.end)),
+                    }));
+                if diagnostics.is_empty() {
+                    diagnostics.push(Diagnostic { message: e.to_string(), span: error_span(&e) });
+                }
+                None
+            }
+        };
+
+        Analysis { source: source.to_string(), ty, checker, symbols, diagnostics }
+    }
+
+    /// The code that was checked.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The type of the whole program, if it has one.
+    pub fn program_type(&self) -> Option<&Type> {
+        self.ty.as_ref()
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// The name at a byte offset and its type. Names whose type wasn't
+    /// worked out before an error have none.
+    pub fn type_at(&self, offset: usize) -> Option<(&Occurrence, Type)> {
+        let occurrence = self.symbols.as_ref()?.at(offset)?;
+        let ty = match occurrence.symbol {
+            Symbol::Binding(id) | Symbol::Reference(id) => generalize(&self.checker.binding_type(id)?),
+            Symbol::Free => types::get_builtin_env().remove(&occurrence.name)?,
+            Symbol::Field => return None,
+        };
+        Some((occurrence, ty))
+    }
+
+    /// The names that could finish the identifier ending at a byte offset:
+    /// the bindings in scope there, innermost first, then the builtins.
+    /// Returns `None` if no identifier that could be a variable ends there.
+    /// The code doesn't have to type check; names bound after the first
+    /// error just have no type.
+    pub fn completions(&self, offset: usize) -> Option<Vec<Completion>> {
+        let token = lexer::tokenize(&self.source).into_iter()
+            .find(|t| t.kind == TokenKind::Ident && t.span.start < offset && t.span.end == offset)?;
+        let prefix = &self.source[token.span.start..offset];
+
+        let mut completions: Vec<Completion> = Vec::new();
+        if let Some(symbols) = &self.symbols {
+            if symbols.at(offset).is_some_and(|o| matches!(o.symbol, Symbol::Binding(_) | Symbol::Field)) {
+                return None;
+            }
+            completions.extend(symbols.in_scope(offset).into_iter()
+                .map(|binding| {
+                    let ty = match binding.symbol {
+                        Symbol::Binding(id) => self.checker.binding_type(id).map(|ty| generalize(&ty)),
+                        _ => None,
+                    };
+                    Completion { name: binding.name.clone(), ty }
+                }));
+        }
+
+        let mut types = types::get_builtin_env();
+        let mut builtins: Vec<String> = interpreter::get_builtin_env().into_keys().collect();
+        builtins.sort();
+        for name in builtins {
+            // A binding of the same name hides the builtin
+            if !completions.iter().any(|c| c.name == name) {
+                let ty = types.remove(&name);
+                completions.push(Completion { name, ty });
+            }
+        }
+        completions.retain(|c| c.name.starts_with(prefix));
+        Some(completions)
+    }
+
+    /// Where the name at a byte offset is bound.
+    pub fn definition(&self, offset: usize) -> Option<Range<usize>> {
+        let symbols = self.symbols.as_ref()?;
+        let (Symbol::Binding(id) | Symbol::Reference(id)) = symbols.at(offset)?.symbol else {
+            return None;
+        };
+        Some(symbols.binding(id)?.span.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analysis() {
+        let source = "let double = \\x -> x * 2\nin map double";
+        let analysis = Analysis::new(source, None);
+        assert_eq!(analysis.program_type().unwrap().to_string(), "[Int] -> [Int]");
+        let (occurrence, ty) = analysis.type_at(34).unwrap();
+        assert_eq!((occurrence.name.as_str(), ty.to_string()), ("double", "Int -> Int".to_string()));
+        assert_eq!(analysis.type_at(29).unwrap().1.to_string(), "(a -> b) -> [a] -> [b]");
+        assert_eq!(analysis.definition(33), Some(4..10));
+        assert_eq!(analysis.definition(29), None);
+        assert!(analysis.diagnostics().is_empty());
+
+        // Each name that could finish the code, with its type
+        let completions = |source: &str| {
+            let analysis = Analysis::new(source, None);
+            analysis.completions(source.len()).map(|completions| {
+                completions.into_iter()
+                    .map(|c| format!("{} : {}", c.name, c.ty.map_or("?".to_string(), |ty| ty.to_string())))
+                    .collect::<Vec<_>>()
+            })
+        };
+        assert_eq!(
+            completions("let double = \\x -> x * 2\nin \\drop -> d"),
+            Some(vec!["drop : a".to_string(), "double : Int -> Int".to_string()])
+        );
+        assert_eq!(
+            completions("map (fi"),
+            Some(vec!["filter : (a -> Bool) -> [a] -> [a]".to_string()])
+        );
+        assert_eq!(completions("\\xs -> "), None);
+        assert_eq!(completions("\\sum -> su"), Some(vec!["sum : a".to_string()]));
+
+        let diagnostics = |source, signature: Option<&str>| {
+            let signature = signature.map(|s| parser::parse_type(s).unwrap());
+            Analysis::new(source, signature.as_ref()).diagnostics().to_vec()
+        };
+        let undefined = &diagnostics("\\x ->\n  x + y", None)[0];
+        assert_eq!(undefined.message, "Undefined variable: y");
+        assert_eq!(undefined.span, Some(Span::new(12, 13)));
+        let mismatch = &diagnostics("1 + (map (\\x -> x) [1] ++ [true])", None)[0];
+        assert_eq!(mismatch.message, "Type mismatch: Bool vs Int");
+        assert_eq!(mismatch.span, Some(Span::new(5, 32)));
+        assert_eq!(diagnostics("let x = in x", None)[0].span.map(|s| s.start), Some(8));
+        assert_eq!(
+            diagnostics("sum", Some("[Int] -> Bool")),
+            vec![Diagnostic { message: "Your solution has type [Int] -> Int, expected [Int] -> Bool".to_string(), span: None }]
+        );
+    }
+}
@@ -1,4 +1,62 @@
+use crate::lang::pretty;
 use std::fmt;
+use std::rc::Rc;
+
+/// Byte range of a node in the source it was parsed from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// Synthesized nodes have an empty span.
+    pub fn is_empty(&self) -> bool {
+        self.start >= self.end
+    }
+
+    /// 1-based line and column of the start of the span.
+    pub fn line_col(&self, source: &str) -> (usize, usize) {
+        let before = &source[..self.start.min(source.len())];
+        let line = before.matches('\n').count() + 1;
+        let col = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+        (line, col)
+    }
+}
+
+/// A name being bound, in a `let`, a lambda, a pattern or a list
+/// comprehension, with where it is in the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Name {
+    pub text: String,
+    pub span: Span,
+}
+
+impl Name {
+    /// A name that wasn't parsed, as made up by the minifier.
+    pub fn new(text: impl Into<String>) -> Self {
+        Name { text: text.into(), span: Span::default() }
+    }
+
+    pub fn id(&self) -> BindingId {
+        BindingId(self.span.start)
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Identifies a binding by where its name starts in the source, so it
+/// stays the same for copies of the tree and for the tokens of the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BindingId(pub usize);
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
@@ -7,22 +65,39 @@ pub enum Expr {
     Bool(bool),
     String(String),
     List(Vec<Expr>),
+    Record(Vec<(String, Expr)>),
+    Map(Vec<(Expr, Expr)>),
+    Set(Vec<Expr>),
+
+    // Records
+    Field {
+        expr: Box<Expr>,
+        field: String,
+    },
+    RecordUpdate {
+        record: Box<Expr>,
+        fields: Vec<(String, Expr)>,
+    },
 
     // Variables and functions
     Var(String),
+    /// The body is shared with the closures made from it.
     Lambda {
-        params: Vec<String>,
-        body: Box<Expr>,
+        params: Vec<Name>,
+        body: Rc<Expr>,
     },
     App {
         func: Box<Expr>,
         args: Vec<Expr>,
+        span: Span,
     },
 
     // Let binding
+    /// Recursive: the name is in scope in its own value too, which is shared
+    /// with the thunks made from it.
     Let {
-        name: String,
-        value: Box<Expr>,
+        name: Name,
+        value: Rc<Expr>,
         body: Box<Expr>,
     },
 
@@ -30,6 +105,7 @@ pub enum Expr {
     Match {
         expr: Box<Expr>,
         arms: Vec<MatchArm>,
+        span: Span,
     },
 
     // Conditionals
@@ -44,6 +120,7 @@ pub enum Expr {
         op: BinOp,
         left: Box<Expr>,
         right: Box<Expr>,
+        span: Span,
     },
 
     // Unary operations
@@ -61,12 +138,168 @@ pub enum Expr {
     // List comprehension
     ListComp {
         expr: Box<Expr>,
-        var: String,
+        var: Name,
         list: Box<Expr>,
         guards: Vec<Expr>,
     },
 }
 
+impl Expr {
+    /// Number of nodes in the tree, counting patterns but not names.
+    pub fn node_count(&self) -> usize {
+        let children: usize = match self {
+            Expr::Number(_) | Expr::Bool(_) | Expr::String(_) | Expr::Var(_) | Expr::Range { .. } => 0,
+            Expr::List(items) | Expr::Set(items) => items.iter().map(Expr::node_count).sum(),
+            Expr::Record(fields) => fields.iter().map(|(_, e)| e.node_count()).sum(),
+            Expr::Map(entries) => entries.iter().map(|(k, v)| k.node_count() + v.node_count()).sum(),
+            Expr::Field { expr, .. } | Expr::UnOp { expr, .. } => expr.node_count(),
+            Expr::RecordUpdate { record, fields } => {
+                record.node_count() + fields.iter().map(|(_, e)| e.node_count()).sum::<usize>()
+            }
+            Expr::Lambda { body, .. } => body.node_count(),
+            Expr::App { func, args, .. } => func.node_count() + args.iter().map(Expr::node_count).sum::<usize>(),
+            Expr::Let { value, body, .. } => value.node_count() + body.node_count(),
+            Expr::Match { expr, arms, .. } => {
+                expr.node_count() + arms.iter().map(|arm| arm.pattern.node_count() + arm.expr.node_count()).sum::<usize>()
+            }
+            Expr::If { cond, then_branch, else_branch } => {
+                cond.node_count() + then_branch.node_count() + else_branch.node_count()
+            }
+            Expr::BinOp { left, right, .. } => left.node_count() + right.node_count(),
+            Expr::ListComp { expr, list, guards, .. } => {
+                expr.node_count() + list.node_count() + guards.iter().map(Expr::node_count).sum::<usize>()
+            }
+        };
+        1 + children
+    }
+
+    /// How tightly the expression binds, following `grammar.pest`: 0 for
+    /// `let`, lambdas, `match` and `if`, which extend as far right as they
+    /// can, up to `ATOM_PRECEDENCE` for literals, variables and brackets.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Expr::Let { .. } | Expr::Lambda { .. } | Expr::Match { .. } | Expr::If { .. } => 0,
+            Expr::BinOp { op, .. } => op.precedence(),
+            Expr::UnOp { .. } => UNARY_PRECEDENCE,
+            Expr::App { .. } => APP_PRECEDENCE,
+            Expr::Field { .. } => ACCESS_PRECEDENCE,
+            _ => ATOM_PRECEDENCE,
+        }
+    }
+
+    /// Whether two trees are the same apart from their spans, as when one
+    /// was printed and parsed back.
+    pub fn same_shape(&self, other: &Expr) -> bool {
+        without_spans(self.clone()) == without_spans(other.clone())
+    }
+
+    /// Whether the expression ends in a `match`, whose arms would take in
+    /// any `| pattern -> ...` printed after it.
+    pub fn ends_in_match(&self) -> bool {
+        match self {
+            Expr::Match { .. } => true,
+            Expr::Let { body, .. } => body.ends_in_match(),
+            Expr::Lambda { body, .. } => body.ends_in_match(),
+            Expr::If { else_branch, .. } => else_branch.ends_in_match(),
+            _ => false,
+        }
+    }
+}
+
+/// Rebuild an expression with `f` applied to each direct subexpression.
+/// Binding forms are left to the caller when scope matters.
+pub fn map_children(expr: Expr, mut f: impl FnMut(Expr) -> Expr) -> Expr {
+    match expr {
+        Expr::List(items) => Expr::List(items.into_iter().map(&mut f).collect()),
+        Expr::Set(items) => Expr::Set(items.into_iter().map(&mut f).collect()),
+        Expr::Record(fields) => Expr::Record(fields.into_iter().map(|(n, e)| (n, f(e))).collect()),
+        Expr::Map(entries) => Expr::Map(entries.into_iter().map(|(k, v)| (f(k), f(v))).collect()),
+        Expr::Field { expr, field } => Expr::Field { expr: Box::new(f(*expr)), field },
+        Expr::RecordUpdate { record, fields } => Expr::RecordUpdate {
+            record: Box::new(f(*record)),
+            fields: fields.into_iter().map(|(n, e)| (n, f(e))).collect(),
+        },
+        Expr::Lambda { params, body } => Expr::Lambda { params, body: Rc::new(f(Rc::unwrap_or_clone(body))) },
+        Expr::App { func, args, span } => Expr::App {
+            func: Box::new(f(*func)),
+            args: args.into_iter().map(&mut f).collect(),
+            span,
+        },
+        Expr::Let { name, value, body } => Expr::Let {
+            name,
+            value: Rc::new(f(Rc::unwrap_or_clone(value))),
+            body: Box::new(f(*body)),
+        },
+        Expr::Match { expr, arms, span } => Expr::Match {
+            expr: Box::new(f(*expr)),
+            arms: arms
+                .into_iter()
+                .map(|arm| MatchArm { pattern: arm.pattern, expr: f(arm.expr) })
+                .collect(),
+            span,
+        },
+        Expr::If { cond, then_branch, else_branch } => Expr::If {
+            cond: Box::new(f(*cond)),
+            then_branch: Box::new(f(*then_branch)),
+            else_branch: Box::new(f(*else_branch)),
+        },
+        Expr::BinOp { op, left, right, span } => {
+            Expr::BinOp { op, left: Box::new(f(*left)), right: Box::new(f(*right)), span }
+        }
+        Expr::UnOp { op, expr } => Expr::UnOp { op, expr: Box::new(f(*expr)) },
+        Expr::ListComp { expr, var, list, guards } => Expr::ListComp {
+            expr: Box::new(f(*expr)),
+            var,
+            list: Box::new(f(*list)),
+            guards: guards.into_iter().map(&mut f).collect(),
+        },
+        leaf @ (Expr::Number(_) | Expr::Bool(_) | Expr::String(_) | Expr::Var(_) | Expr::Range { .. }) => leaf,
+    }
+}
+
+fn without_spans(expr: Expr) -> Expr {
+    let name = |name: Name| Name::new(name.text);
+    match map_children(expr, without_spans) {
+        Expr::Lambda { params, body } => Expr::Lambda { params: params.into_iter().map(name).collect(), body },
+        Expr::App { func, args, .. } => Expr::App { func, args, span: Span::default() },
+        Expr::Let { name: n, value, body } => Expr::Let { name: name(n), value, body },
+        Expr::Match { expr, arms, .. } => Expr::Match {
+            expr,
+            arms: arms
+                .into_iter()
+                .map(|arm| MatchArm { pattern: pattern_without_spans(arm.pattern), expr: arm.expr })
+                .collect(),
+            span: Span::default(),
+        },
+        Expr::BinOp { op, left, right, .. } => Expr::BinOp { op, left, right, span: Span::default() },
+        Expr::ListComp { expr, var, list, guards } => Expr::ListComp { expr, var: name(var), list, guards },
+        expr => expr,
+    }
+}
+
+fn pattern_without_spans(pattern: Pattern) -> Pattern {
+    match pattern {
+        Pattern::Var(name) => Pattern::Var(Name::new(name.text)),
+        Pattern::List(items) => Pattern::List(items.into_iter().map(pattern_without_spans).collect()),
+        Pattern::Record(fields) => {
+            Pattern::Record(fields.into_iter().map(|(f, p)| (f, pattern_without_spans(p))).collect())
+        }
+        Pattern::Cons { head, tail } => Pattern::Cons {
+            head: Box::new(pattern_without_spans(*head)),
+            tail: Box::new(pattern_without_spans(*tail)),
+        },
+        pattern => pattern,
+    }
+}
+
+/// Precedence of `-x`, whose operand is an application or tighter.
+pub const UNARY_PRECEDENCE: u8 = 11;
+/// Precedence of function application, whose function and arguments are
+/// field accesses or tighter.
+pub const APP_PRECEDENCE: u8 = 12;
+pub const ACCESS_PRECEDENCE: u8 = 13;
+pub const ATOM_PRECEDENCE: u8 = 14;
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct MatchArm {
     pub pattern: Pattern,
@@ -76,17 +309,36 @@ pub struct MatchArm {
 #[derive(Debug, Clone, PartialEq)]
 pub enum Pattern {
     Wildcard,
-    Var(String),
+    Var(Name),
     Number(i64),
     Bool(bool),
     String(String),
     List(Vec<Pattern>),
+    Record(Vec<(String, Pattern)>),
     Cons {
         head: Box<Pattern>,
         tail: Box<Pattern>,
     },
 }
 
+impl Pattern {
+    /// Whether the pattern is the variable `name`, as `{ name }` is short
+    /// for `{ name = name }`.
+    pub fn is_var(&self, name: &str) -> bool {
+        matches!(self, Pattern::Var(var) if var.text == name)
+    }
+
+    pub fn node_count(&self) -> usize {
+        let children: usize = match self {
+            Pattern::List(items) => items.iter().map(Pattern::node_count).sum(),
+            Pattern::Record(fields) => fields.iter().map(|(_, p)| p.node_count()).sum(),
+            Pattern::Cons { head, tail } => head.node_count() + tail.node_count(),
+            _ => 0,
+        };
+        1 + children
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BinOp {
     // Arithmetic
@@ -114,8 +366,67 @@ pub enum BinOp {
     Concat,
 
     // Composition
-    PipeForward,
-    PipeBackward,
+    ComposeForward,
+    ComposeBackward,
+
+    // Application
+    Pipe,
+    Apply,
+}
+
+impl BinOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Mod => "%",
+            BinOp::Pow => "^",
+            BinOp::Eq => "==",
+            BinOp::Neq => "!=",
+            BinOp::Lt => "<",
+            BinOp::Gt => ">",
+            BinOp::Lte => "<=",
+            BinOp::Gte => ">=",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
+            BinOp::Cons => "::",
+            BinOp::Concat => "++",
+            BinOp::ComposeForward => ">>",
+            BinOp::ComposeBackward => "<<",
+            BinOp::Pipe => "|>",
+            BinOp::Apply => "$",
+        }
+    }
+
+    /// From 1 for `$` up to 10 for `^`. Every operator but `$` is
+    /// left-associative.
+    pub fn precedence(self) -> u8 {
+        match self {
+            BinOp::Apply => 1,
+            BinOp::Pipe => 2,
+            BinOp::ComposeForward | BinOp::ComposeBackward => 3,
+            BinOp::Eq | BinOp::Neq | BinOp::Lt | BinOp::Gt | BinOp::Lte | BinOp::Gte => 4,
+            BinOp::And | BinOp::Or => 5,
+            BinOp::Cons => 6,
+            BinOp::Concat => 7,
+            BinOp::Add | BinOp::Sub => 8,
+            BinOp::Mul | BinOp::Div | BinOp::Mod => 9,
+            BinOp::Pow => 10,
+        }
+    }
+
+    /// The precedence the left and right operands need to go without
+    /// parentheses.
+    pub fn operand_precedence(self) -> (u8, u8) {
+        let p = self.precedence();
+        if self == BinOp::Apply {
+            (p + 1, p)
+        } else {
+            (p, p + 1)
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -125,25 +436,12 @@ pub enum UnOp {
 
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Expr::Number(n) => write!(f, "{}", n),
-            Expr::Bool(b) => write!(f, "{}", b),
-            Expr::String(s) => write!(f, "\"{}\"", s),
-            Expr::List(items) => {
-                write!(f, "[")?;
-                for (i, item) in items.iter().enumerate() {
-                    if i > 0 {
-                        write!(f, ", ")?;
-                    }
-                    write!(f, "{}", item)?;
-                }
-                write!(f, "]")
-            }
-            Expr::Var(name) => write!(f, "{}", name),
-            Expr::Lambda { params, .. } => {
-                write!(f, "\\{} -> ...", params.join(", "))
-            }
-            _ => write!(f, "<expr>"),
-        }
+        write!(f, "{}", pretty::pretty(self, pretty::WIDTH))
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", pretty::pretty_pattern(self))
     }
 }
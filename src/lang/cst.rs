@@ -0,0 +1,289 @@
+use crate::lang::lexer::{self, Token, TokenKind};
+use std::fmt;
+
+/// How far a line continued after a moved comment is indented.
+const CONTINUATION_INDENT: &str = "  ";
+
+/// Source code as significant tokens, each with the whitespace and comments
+/// before it. Unlike the syntax tree it loses nothing, so rewrites of the
+/// tree can carry comments over, or strip them on purpose.
+pub struct Cst {
+    source: String,
+    pub tokens: Vec<CstToken>,
+    /// Whitespace and comments after the last token.
+    pub end: Vec<Token>,
+}
+
+pub struct CstToken {
+    /// Whitespace and comments before the token.
+    pub leading: Vec<Token>,
+    pub token: Token,
+}
+
+/// Where a comment belongs, by significant token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    /// At the end of the line of this token.
+    After(usize),
+    /// On its own line before this token, or at the end if it's past the
+    /// last one.
+    Before(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    pub text: String,
+    pub anchor: Anchor,
+}
+
+impl Cst {
+    /// Works on any source, whether or not it parses.
+    pub fn new(source: &str) -> Cst {
+        let mut tokens = Vec::new();
+        let mut trivia = Vec::new();
+        for token in lexer::tokenize(source) {
+            if token.is_trivia() {
+                trivia.push(token);
+            } else {
+                tokens.push(CstToken { leading: std::mem::take(&mut trivia), token });
+            }
+        }
+        Cst { source: source.to_string(), tokens, end: trivia }
+    }
+
+    pub fn text(&self, token: &Token) -> &str {
+        token.text(&self.source)
+    }
+
+    /// The texts of the significant tokens.
+    pub fn token_texts(&self) -> Vec<&str> {
+        self.tokens.iter().map(|t| self.text(&t.token)).collect()
+    }
+
+    /// Every comment, with the token it belongs to. A comment on the same
+    /// line as code goes with the token before it; one on its own line goes
+    /// with the token after it.
+    pub fn comments(&self) -> Vec<Comment> {
+        let mut comments = Vec::new();
+        let trivia = self.tokens.iter().map(|t| &t.leading).chain([&self.end]);
+        for (index, trivia) in trivia.enumerate() {
+            let mut same_line = index > 0;
+            for token in trivia {
+                match token.kind {
+                    TokenKind::Comment => {
+                        let anchor = if same_line { Anchor::After(index - 1) } else { Anchor::Before(index) };
+                        comments.push(Comment { text: self.text(token).to_string(), anchor });
+                        // Whatever follows is on a later line
+                        same_line = false;
+                    }
+                    _ => same_line &= !self.text(token).contains('\n'),
+                }
+            }
+        }
+        comments
+    }
+
+    /// Put this code's comments into `rewritten`, a rewrite of the same
+    /// code without them, such as its formatted form. Tokens are matched up
+    /// by text, and each comment goes next to the token it belonged to, or
+    /// the nearest one that survived the rewrite.
+    pub fn carry_comments(&self, rewritten: &str) -> String {
+        let comments = self.comments();
+        let target = Cst::new(rewritten);
+        if comments.is_empty() {
+            return rewritten.to_string();
+        }
+
+        let matched = align(&self.token_texts(), &target.token_texts());
+        let count = target.tokens.len();
+        let mut before: Vec<Vec<&str>> = vec![Vec::new(); count + 1];
+        let mut after: Vec<Vec<&str>> = vec![Vec::new(); count];
+        for comment in &comments {
+            match comment.anchor {
+                Anchor::After(i) => match matched[..=i].iter().rev().find_map(|&m| m) {
+                    Some(j) => after[j].push(&comment.text),
+                    None => before[0].push(&comment.text),
+                },
+                Anchor::Before(i) => {
+                    let j = matched[i.min(matched.len())..].iter().find_map(|&m| m).unwrap_or(count);
+                    before[j].push(&comment.text);
+                }
+            }
+        }
+
+        let mut out = String::new();
+        let mut break_line = false;
+        for (j, token) in target.tokens.iter().enumerate() {
+            let space: String = token.leading.iter().map(|t| target.text(t)).collect();
+            if break_line && !space.contains('\n') {
+                // A comment was put at the end of what was this token's line
+                out.push('\n');
+                out.push_str(&continuation_indent(&out));
+            } else {
+                out.push_str(&space);
+            }
+            break_line = false;
+
+            if !before[j].is_empty() {
+                let indent = if out.is_empty() || out.ends_with('\n') || space.contains('\n') {
+                    line_indent(&out).to_string()
+                } else {
+                    // Break the line so the comment can go on one of its own
+                    out.truncate(out.trim_end().len());
+                    let indent = continuation_indent(&out);
+                    out.push('\n');
+                    out.push_str(&indent);
+                    indent
+                };
+                for comment in &before[j] {
+                    out.push_str(comment);
+                    out.push('\n');
+                    out.push_str(&indent);
+                }
+            }
+
+            out.push_str(target.text(&token.token));
+
+            for (k, comment) in after[j].iter().enumerate() {
+                if k > 0 {
+                    let indent = line_indent(&out).to_string();
+                    out.push('\n');
+                    out.push_str(&indent);
+                }
+                out.push(' ');
+                out.push_str(comment);
+                break_line = true;
+            }
+        }
+
+        let end: String = target.end.iter().map(|t| target.text(t)).collect();
+        out.push_str(end.trim_end());
+        for comment in &before[count] {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(comment);
+        }
+        if end.ends_with('\n') {
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// The source, exactly as it was.
+impl fmt::Display for Cst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in &self.tokens {
+            for trivia in &token.leading {
+                f.write_str(self.text(trivia))?;
+            }
+            f.write_str(self.text(&token.token))?;
+        }
+        for trivia in &self.end {
+            f.write_str(self.text(trivia))?;
+        }
+        Ok(())
+    }
+}
+
+/// For each of `from`, the index of the same token in `to`, matching as
+/// many as possible in order (a longest common subsequence).
+fn align(from: &[&str], to: &[&str]) -> Vec<Option<usize>> {
+    let mut matched = vec![None; from.len()];
+    align_into(from, to, (0, 0), &mut matched);
+    matched
+}
+
+/// Hirschberg's algorithm, which needs memory in proportion to the
+/// lengths rather than their product: split `from` in half, find where a
+/// longest match crosses into the second half of `to`, and align each
+/// half on its own. `start` is where `from` and `to` are in the whole.
+fn align_into(from: &[&str], to: &[&str], start: (usize, usize), matched: &mut [Option<usize>]) {
+    // Formatting keeps most tokens, so the ends usually match already
+    let prefix = from.iter().zip(to).take_while(|(a, b)| a == b).count();
+    let (from, to) = (&from[prefix..], &to[prefix..]);
+    let suffix = from.iter().rev().zip(to.iter().rev()).take_while(|(a, b)| a == b).count();
+    let (from, to) = (&from[..from.len() - suffix], &to[..to.len() - suffix]);
+    let start = (start.0 + prefix, start.1 + prefix);
+    for i in 0..prefix {
+        matched[start.0 - prefix + i] = Some(start.1 - prefix + i);
+    }
+    for i in 0..suffix {
+        matched[start.0 + from.len() + i] = Some(start.1 + to.len() + i);
+    }
+
+    if from.is_empty() || to.is_empty() {
+        return;
+    }
+    if from.len() == 1 {
+        matched[start.0] = to.iter().position(|t| *t == from[0]).map(|j| start.1 + j);
+        return;
+    }
+
+    let mid = from.len() / 2;
+    let forward = lcs_lengths(from[..mid].iter().copied(), to.iter().copied());
+    let backward = lcs_lengths(from[mid..].iter().rev().copied(), to.iter().rev().copied());
+    let split = (0..=to.len()).max_by_key(|&j| forward[j] + backward[to.len() - j]).unwrap();
+    align_into(&from[..mid], &to[..split], start, matched);
+    align_into(&from[mid..], &to[split..], (start.0 + mid, start.1 + split), matched);
+}
+
+/// The length of the longest match of `from` with each prefix of `to`,
+/// keeping one row of the table at a time.
+fn lcs_lengths<'a>(from: impl Iterator<Item = &'a str>, to: impl Iterator<Item = &'a str> + Clone) -> Vec<usize> {
+    let mut row = vec![0; to.clone().count() + 1];
+    for a in from {
+        // The previous row's value at j - 1
+        let mut diagonal = 0;
+        for (j, b) in to.clone().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if a == b { diagonal + 1 } else { above.max(row[j]) };
+            diagonal = above;
+        }
+    }
+    row
+}
+
+/// The whitespace at the start of the last line.
+fn line_indent(out: &str) -> &str {
+    let line = &out[out.rfind('\n').map_or(0, |i| i + 1)..];
+    &line[..line.len() - line.trim_start().len()]
+}
+
+fn continuation_indent(out: &str) -> String {
+    format!("{}{}", line_indent(out), CONTINUATION_INDENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lossless() {
+        let source = "-- header\nlet x = 1 -- one\n\n  in \"a -- b\"  -- end\n";
+        let cst = Cst::new(source);
+        assert_eq!(cst.to_string(), source);
+        assert_eq!(
+            cst.comments(),
+            vec![
+                Comment { text: "-- header".to_string(), anchor: Anchor::Before(0) },
+                Comment { text: "-- one".to_string(), anchor: Anchor::After(3) },
+                Comment { text: "-- end".to_string(), anchor: Anchor::After(5) },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_carry_comments() {
+        let carry = |source: &str, rewritten: &str| Cst::new(source).carry_comments(rewritten);
+        assert_eq!(carry("-- doubles\n\\x->x*2 -- twice", "\\x -> x * 2"), "-- doubles\n\\x -> x * 2 -- twice");
+        assert_eq!(carry("let x = 1 -- one\nin x", "let x = 1 in x"), "let x = 1 -- one\n  in x");
+        assert_eq!(
+            carry("map\n  -- double it\n  (\\x -> x * 2)", "map (\\x -> x * 2)"),
+            "map\n  -- double it\n  (\\x -> x * 2)"
+        );
+        // Comments stay near the tokens they were next to when others go
+        assert_eq!(carry("(x) -- x\n+ 1\n-- done", "x + 1"), "x -- x\n  + 1\n-- done");
+    }
+}
@@ -0,0 +1,93 @@
+use crate::lang::ast::Span;
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum RuntimeError {
+    #[error("Undefined variable: {0}")]
+    UndefinedVariable(String),
+
+    #[error("No pattern matched {0}")]
+    NonExhaustiveMatch(String),
+
+    #[error("{0}: empty list")]
+    EmptyList(String),
+
+    #[error("{0}")]
+    TypeError(String),
+
+    #[error("{0}")]
+    InvalidArgument(String),
+
+    #[error("Key not found: {0}")]
+    KeyNotFound(String),
+
+    #[error("Division by zero")]
+    DivisionByZero,
+
+    #[error("Integer overflow in {0}")]
+    Overflow(String),
+
+    #[error("Maximum recursion depth exceeded")]
+    DepthExceeded,
+
+    #[error("Evaluation cancelled")]
+    Cancelled,
+}
+
+/// One active function call at the time of an error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub function: String,
+    /// Where the call was written, if it came from source rather than from
+    /// inside a builtin such as `map`.
+    pub call_site: Option<Span>,
+}
+
+/// A runtime error together with where it happened and the calls that led
+/// there, innermost first.
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("{error}")]
+pub struct EvalError {
+    pub error: RuntimeError,
+    pub span: Option<Span>,
+    pub trace: Vec<Frame>,
+}
+
+impl EvalError {
+    /// Attach a location unless a more precise one is already known.
+    pub fn at(mut self, span: Span) -> Self {
+        if self.span.is_none() && !span.is_empty() {
+            self.span = Some(span);
+        }
+        self
+    }
+
+    pub fn in_call(mut self, frame: Frame) -> Self {
+        self.trace.push(frame);
+        self
+    }
+}
+
+impl From<RuntimeError> for EvalError {
+    fn from(error: RuntimeError) -> Self {
+        EvalError {
+            error,
+            span: None,
+            trace: Vec::new(),
+        }
+    }
+}
+
+/// A parse or type error, and the part of the source it's about.
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("{message}")]
+pub struct SourceError {
+    pub message: String,
+    pub span: Span,
+}
+
+/// Where in the source an error from the parser or type checker is, if it
+/// was narrowed down.
+pub fn error_span(error: &anyhow::Error) -> Option<Span> {
+    error.downcast_ref::<SourceError>().map(|e| e.span)
+}
@@ -1,16 +1,30 @@
 use crate::lang::ast::*;
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use im_rc::{OrdMap, OrdSet};
+use std::collections::{BTreeMap, HashMap};
 use std::rc::Rc;
-use anyhow::{anyhow, Result};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::Arc;
+use crate::lang::error::{EvalError, Frame, RuntimeError};
+
+type Result<T> = std::result::Result<T, EvalError>;
 
 const MAX_CALL_DEPTH: usize = 10000;
 
+/// How many range elements to build between cancellation checks.
+const RANGE_CHECK_INTERVAL: i64 = 4096;
+
 #[derive(Debug, Clone)]
 pub enum Value {
     Number(i64),
     Bool(bool),
     String(String),
     List(Vec<Value>),
+    Record(BTreeMap<String, Value>),
+    /// Persistent, so adding to a map shares the rest of it with the
+    /// original instead of copying it.
+    Map(OrdMap<Value, Value>),
+    Set(OrdSet<Value>),
     Function {
         params: Vec<String>,
         body: Rc<Expr>,
@@ -23,19 +37,63 @@ pub enum Value {
     },
 }
 
+/// Equal exactly when `cmp` says so, so maps and sets agree with `==`.
 impl PartialEq for Value {
     fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Value {}
+
+/// Total order used for map keys and set elements. Only data values have a
+/// meaningful order; `check_key` keeps functions out of maps and sets, so
+/// the order of functions, by identity, never shows.
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
         match (self, other) {
-            (Value::Number(a), Value::Number(b)) => a == b,
-            (Value::Bool(a), Value::Bool(b)) => a == b,
-            (Value::String(a), Value::String(b)) => a == b,
-            (Value::List(a), Value::List(b)) => a == b,
-            _ => false,
+            (Value::Number(a), Value::Number(b)) => a.cmp(b),
+            (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            (Value::List(a), Value::List(b)) => a.cmp(b),
+            (Value::Record(a), Value::Record(b)) => a.cmp(b),
+            (Value::Map(a), Value::Map(b)) => a.cmp(b),
+            (Value::Set(a), Value::Set(b)) => a.cmp(b),
+            (
+                Value::Function { params: a, body: a_body, env: a_env },
+                Value::Function { params: b, body: b_body, env: b_env },
+            ) => (Rc::as_ptr(a_body), Rc::as_ptr(a_env), a).cmp(&(Rc::as_ptr(b_body), Rc::as_ptr(b_env), b)),
+            (Value::Builtin(a), Value::Builtin(b)) => a.cmp(b),
+            (Value::Thunk { expr: a, env: a_env }, Value::Thunk { expr: b, env: b_env }) => {
+                (Rc::as_ptr(a), Rc::as_ptr(a_env)).cmp(&(Rc::as_ptr(b), Rc::as_ptr(b_env)))
+            }
+            _ => self.rank().cmp(&other.rank()),
         }
     }
 }
 
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl Value {
+    fn rank(&self) -> u8 {
+        match self {
+            Value::Number(_) => 0,
+            Value::Bool(_) => 1,
+            Value::String(_) => 2,
+            Value::List(_) => 3,
+            Value::Record(_) => 4,
+            Value::Map(_) => 5,
+            Value::Set(_) => 6,
+            Value::Function { .. } => 7,
+            Value::Builtin(_) => 8,
+            Value::Thunk { .. } => 9,
+        }
+    }
+
     pub fn to_string_repr(&self) -> String {
         match self {
             Value::Number(n) => n.to_string(),
@@ -45,6 +103,25 @@ impl Value {
                 let strs: Vec<String> = items.iter().map(|v| v.to_string_repr()).collect();
                 format!("[{}]", strs.join(", "))
             }
+            Value::Record(fields) => {
+                if fields.is_empty() {
+                    return "{}".to_string();
+                }
+                let strs: Vec<String> = fields.iter()
+                    .map(|(name, v)| format!("{} = {}", name, v.to_string_repr()))
+                    .collect();
+                format!("{{ {} }}", strs.join(", "))
+            }
+            Value::Map(entries) => {
+                let strs: Vec<String> = entries.iter()
+                    .map(|(k, v)| format!("{} => {}", k.to_string_repr(), v.to_string_repr()))
+                    .collect();
+                format!("#{{{}}}", strs.join(", "))
+            }
+            Value::Set(items) => {
+                let strs: Vec<String> = items.iter().map(|v| v.to_string_repr()).collect();
+                format!("#[{}]", strs.join(", "))
+            }
             Value::Function { .. } => "<function>".to_string(),
             Value::Builtin(name) => format!("<builtin: {}>", name),
             Value::Thunk { .. } => "<thunk>".to_string(),
@@ -54,18 +131,51 @@ impl Value {
 
 pub type Env = HashMap<String, Value>;
 
+/// Lets another thread stop an evaluation that is taking too long.
+#[derive(Debug, Clone, Default)]
+pub struct CancelFlag(Arc<AtomicBool>);
+
+impl CancelFlag {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, AtomicOrdering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(AtomicOrdering::Relaxed)
+    }
+}
+
 pub struct Interpreter {
     call_depth: usize,
+    cancel: CancelFlag,
 }
 
 impl Interpreter {
     pub fn new() -> Self {
-        Interpreter { call_depth: 0 }
+        Self::with_cancel(CancelFlag::new())
+    }
+
+    /// An interpreter that gives up with `RuntimeError::Cancelled` once
+    /// `cancel` is set.
+    pub fn with_cancel(cancel: CancelFlag) -> Self {
+        Interpreter { call_depth: 0, cancel }
     }
 
     fn check_depth(&self) -> Result<()> {
         if self.call_depth > MAX_CALL_DEPTH {
-            Err(anyhow!("Maximum recursion depth exceeded"))
+            Err(RuntimeError::DepthExceeded.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_cancelled(&self) -> Result<()> {
+        if self.cancel.is_cancelled() {
+            Err(RuntimeError::Cancelled.into())
         } else {
             Ok(())
         }
@@ -73,6 +183,7 @@ impl Interpreter {
 
     pub fn eval(&mut self, expr: &Expr, env: &Rc<Env>) -> Result<Value> {
         self.check_depth()?;
+        self.check_cancelled()?;
         self.call_depth += 1;
 
         let result = match expr {
@@ -85,25 +196,72 @@ impl Interpreter {
                     .collect();
                 Ok(Value::List(values?))
             }
+            Expr::Record(fields) => {
+                let values: Result<BTreeMap<String, Value>> = fields.iter()
+                    .map(|(name, field_expr)| Ok((name.clone(), self.eval(field_expr, env)?)))
+                    .collect();
+                Ok(Value::Record(values?))
+            }
+            Expr::Map(entries) => {
+                let mut map = OrdMap::new();
+                for (key_expr, value_expr) in entries {
+                    let key = check_key(self.eval(key_expr, env)?)?;
+                    let value = self.eval(value_expr, env)?;
+                    map.insert(key, value);
+                }
+                Ok(Value::Map(map))
+            }
+            Expr::Set(items) => {
+                let values: Result<OrdSet<Value>> = items.iter()
+                    .map(|item| check_key(self.eval(item, env)?))
+                    .collect();
+                Ok(Value::Set(values?))
+            }
+            Expr::Field { expr, field } => {
+                match self.eval(expr, env)? {
+                    Value::Record(mut values) => values.remove(field)
+                        .ok_or_else(|| type_error(format!("Record has no field: {}", field))),
+                    _ => Err(type_error(format!("Cannot access field {} of non-record", field))),
+                }
+            }
+            Expr::RecordUpdate { record, fields } => self.eval_record_update(record, fields, env),
             Expr::Var(name) => {
                 env.get(name)
                     .cloned()
-                    .ok_or_else(|| anyhow!("Undefined variable: {}", name))
+                    .ok_or_else(|| RuntimeError::UndefinedVariable(name.clone()).into())
                     .and_then(|v| self.force(v, env))
             }
             Expr::Lambda { params, body } => Ok(Value::Function {
-                params: params.clone(),
-                body: Rc::new((**body).clone()),
+                params: params.iter().map(|p| p.text.clone()).collect(),
+                body: body.clone(),
                 env: env.clone(),
             }),
-            Expr::App { func, args } => self.eval_app(func, args, env),
+            Expr::App { func, args, span } => {
+                let call_site = Some(*span).filter(|s| !s.is_empty());
+                self.eval_app(func, args, env, call_site).map_err(|e| e.at(*span))
+            }
             Expr::Let { name, value, body } => {
-                let thunk = Value::Thunk {
-                    expr: Rc::new((**value).clone()),
+                // Inside its own value the name evaluates this binding again,
+                // so functions can call themselves. Like any use of a let, that
+                // evaluates the value again, which for a lambda only builds a
+                // closure around the shared body.
+                let again = Value::Thunk {
+                    expr: Rc::new(Expr::Let {
+                        name: name.clone(),
+                        value: value.clone(),
+                        body: Box::new(Expr::Var(name.text.clone())),
+                    }),
                     env: env.clone(),
                 };
+                let mut value_env = (**env).clone();
+                value_env.insert(name.text.clone(), again);
+
+                let thunk = Value::Thunk {
+                    expr: value.clone(),
+                    env: Rc::new(value_env),
+                };
                 let mut new_env = (**env).clone();
-                new_env.insert(name.clone(), thunk);
+                new_env.insert(name.text.clone(), thunk);
                 self.eval(body, &Rc::new(new_env))
             }
             Expr::If { cond, then_branch, else_branch } => {
@@ -111,33 +269,46 @@ impl Interpreter {
                 match cond_val {
                     Value::Bool(true) => self.eval(then_branch, env),
                     Value::Bool(false) => self.eval(else_branch, env),
-                    _ => Err(anyhow!("Condition must be a boolean")),
+                    _ => Err(type_error("Condition must be a boolean")),
                 }
             }
-            Expr::BinOp { op, left, right } => self.eval_binop(*op, left, right, env),
+            Expr::BinOp { op, left, right, span } => {
+                self.eval_binop(*op, left, right, env, *span).map_err(|e| e.at(*span))
+            }
             Expr::UnOp { op: UnOp::Neg, expr } => {
                 let val = self.eval(expr, env)?;
                 match val {
-                    Value::Number(n) => Ok(Value::Number(-n)),
-                    _ => Err(anyhow!("Cannot negate non-number")),
+                    Value::Number(n) => n.checked_neg()
+                        .map(Value::Number)
+                        .ok_or_else(|| overflow("negation")),
+                    _ => Err(type_error("Cannot negate non-number")),
                 }
             }
-            Expr::Range { start, end } => {
-                let values: Vec<Value> = (*start..=*end)
-                    .map(Value::Number)
-                    .collect();
-                Ok(Value::List(values))
-            }
+            Expr::Range { start, end } => self.eval_range(*start, *end),
             Expr::ListComp { expr, var, list, guards } => {
-                self.eval_list_comp(expr, var, list, guards, env)
+                self.eval_list_comp(expr, &var.text, list, guards, env)
+            }
+            Expr::Match { expr, arms, span } => {
+                self.eval_match(expr, arms, env).map_err(|e| e.at(*span))
             }
-            Expr::Match { expr, arms } => self.eval_match(expr, arms, env),
         };
 
         self.call_depth -= 1;
         result
     }
 
+    fn eval_range(&self, start: i64, end: i64) -> Result<Value> {
+        let mut values = Vec::new();
+        for n in start..=end {
+            // Huge ranges take a while to build, so let a deadline stop them
+            if n.wrapping_sub(start) % RANGE_CHECK_INTERVAL == 0 {
+                self.check_cancelled()?;
+            }
+            values.push(Value::Number(n));
+        }
+        Ok(Value::List(values))
+    }
+
     fn force(&mut self, value: Value, _env: &Rc<Env>) -> Result<Value> {
         match value {
             Value::Thunk { expr, env } => self.eval(&expr, &env),
@@ -145,8 +316,15 @@ impl Interpreter {
         }
     }
 
-    fn eval_app(&mut self, func_expr: &Expr, args: &[Expr], env: &Rc<Env>) -> Result<Value> {
+    fn eval_app(&mut self, func_expr: &Expr, args: &[Expr], env: &Rc<Env>, call_site: Option<Span>) -> Result<Value> {
         let func = self.eval(func_expr, env)?;
+        let frame = || Frame {
+            function: match func_expr {
+                Expr::Var(name) if !name.starts_with('_') => name.clone(),
+                _ => "<lambda>".to_string(),
+            },
+            call_site,
+        };
 
         match func {
             Value::Function { params, body, env: func_env } => {
@@ -173,7 +351,7 @@ impl Interpreter {
                         let val = self.eval(arg, env)?;
                         new_env.insert(param.clone(), val);
                     }
-                    self.eval(&body, &Rc::new(new_env))
+                    self.eval(&body, &Rc::new(new_env)).map_err(|e| e.in_call(frame()))
                 } else {
                     // Over-application
                     let mut new_env = (*func_env).clone();
@@ -181,27 +359,73 @@ impl Interpreter {
                         let val = self.eval(arg, env)?;
                         new_env.insert(param.clone(), val);
                     }
-                    let result = self.eval(&body, &Rc::new(new_env))?;
-                    let remaining_args = &args[params.len()..];
-
-                    if remaining_args.is_empty() {
-                        Ok(result)
-                    } else {
-                        self.eval_app(&Expr::Var("_result".to_string()), remaining_args,
-                            &Rc::new(vec![("_result".to_string(), result)].into_iter().collect()))
+                    let result = self.eval(&body, &Rc::new(new_env)).map_err(|e| e.in_call(frame()))?;
+                    let remaining_args: Result<Vec<Value>> = args[params.len()..].iter()
+                        .map(|arg| self.eval(arg, env))
+                        .collect();
+                    self.apply_value(result, remaining_args?)
+                }
+            }
+            Value::Builtin(name) => {
+                let arity = builtin_arity(&name);
+                if args.len() < arity {
+                    // Partial application: capture the supplied arguments and
+                    // wait for the rest
+                    let mut new_env = Env::new();
+                    let mut call_args = Vec::new();
+                    for (i, arg) in args.iter().enumerate() {
+                        let captured = format!("_arg{}", i);
+                        new_env.insert(captured.clone(), self.eval(arg, env)?);
+                        call_args.push(Expr::Var(captured));
                     }
+                    let remaining_params: Vec<String> = (args.len()..arity)
+                        .map(|i| format!("_arg{}", i))
+                        .collect();
+                    call_args.extend(remaining_params.iter().cloned().map(Expr::Var));
+                    new_env.insert(name.clone(), Value::Builtin(name.clone()));
+
+                    Ok(Value::Function {
+                        params: remaining_params,
+                        body: Rc::new(Expr::App {
+                            func: Box::new(Expr::Var(name)),
+                            args: call_args,
+                            span: Span::default(),
+                        }),
+                        env: Rc::new(new_env),
+                    })
+                } else if args.len() == arity {
+                    self.eval_builtin(&name, args, env)
+                } else {
+                    // Over-application
+                    let result = self.eval_builtin(&name, &args[..arity], env)?;
+                    let rest: Result<Vec<Value>> = args[arity..].iter()
+                        .map(|arg| self.eval(arg, env))
+                        .collect();
+                    self.apply_value(result, rest?)
                 }
             }
-            Value::Builtin(name) => self.eval_builtin(&name, args, env),
-            _ => Err(anyhow!("Cannot call non-function")),
+            _ => Err(type_error("Cannot call non-function")),
+        }
+    }
+
+    /// Apply an already evaluated function value to evaluated arguments.
+    fn apply_value(&mut self, func: Value, args: Vec<Value>) -> Result<Value> {
+        let mut env = Env::new();
+        let mut arg_exprs = Vec::new();
+        for (i, arg) in args.into_iter().enumerate() {
+            let name = format!("_arg{}", i);
+            env.insert(name.clone(), arg);
+            arg_exprs.push(Expr::Var(name));
         }
+        env.insert("_func".to_string(), func);
+        self.eval_app(&Expr::Var("_func".to_string()), &arg_exprs, &Rc::new(env), None)
     }
 
     fn eval_builtin(&mut self, name: &str, args: &[Expr], env: &Rc<Env>) -> Result<Value> {
         match name {
             "map" => {
                 if args.len() < 2 {
-                    return Err(anyhow!("map requires 2 arguments"));
+                    return Err(type_error("map requires 2 arguments"));
                 }
                 let f = self.eval(&args[0], env)?;
                 let list = self.eval(&args[1], env)?;
@@ -209,19 +433,16 @@ impl Interpreter {
                 match list {
                     Value::List(items) => {
                         let results: Result<Vec<Value>> = items.into_iter()
-                            .map(|item| {
-                                let item_expr = value_to_expr(&item)?;
-                                self.eval_app(&value_to_expr(&f)?, &[item_expr], env)
-                            })
+                            .map(|item| self.apply_value(f.clone(), vec![item]))
                             .collect();
                         Ok(Value::List(results?))
                     }
-                    _ => Err(anyhow!("map: second argument must be a list")),
+                    _ => Err(type_error("map: second argument must be a list")),
                 }
             }
             "filter" => {
                 if args.len() < 2 {
-                    return Err(anyhow!("filter requires 2 arguments"));
+                    return Err(type_error("filter requires 2 arguments"));
                 }
                 let f = self.eval(&args[0], env)?;
                 let list = self.eval(&args[1], env)?;
@@ -230,22 +451,21 @@ impl Interpreter {
                     Value::List(items) => {
                         let mut results = Vec::new();
                         for item in items {
-                            let item_expr = value_to_expr(&item)?;
-                            let pred = self.eval_app(&value_to_expr(&f)?, &[item_expr], env)?;
+                            let pred = self.apply_value(f.clone(), vec![item.clone()])?;
                             match pred {
                                 Value::Bool(true) => results.push(item),
                                 Value::Bool(false) => {},
-                                _ => return Err(anyhow!("filter: predicate must return bool")),
+                                _ => return Err(type_error("filter: predicate must return bool")),
                             }
                         }
                         Ok(Value::List(results))
                     }
-                    _ => Err(anyhow!("filter: second argument must be a list")),
+                    _ => Err(type_error("filter: second argument must be a list")),
                 }
             }
             "fold" | "foldl" => {
                 if args.len() < 3 {
-                    return Err(anyhow!("{} requires 3 arguments", name));
+                    return Err(type_error(format!("{} requires 3 arguments", name)));
                 }
                 let f = self.eval(&args[0], env)?;
                 let mut acc = self.eval(&args[1], env)?;
@@ -254,18 +474,16 @@ impl Interpreter {
                 match list {
                     Value::List(items) => {
                         for item in items {
-                            let acc_expr = value_to_expr(&acc)?;
-                            let item_expr = value_to_expr(&item)?;
-                            acc = self.eval_app(&value_to_expr(&f)?, &[acc_expr, item_expr], env)?;
+                            acc = self.apply_value(f.clone(), vec![acc, item])?;
                         }
                         Ok(acc)
                     }
-                    _ => Err(anyhow!("{}: third argument must be a list", name)),
+                    _ => Err(type_error(format!("{}: third argument must be a list", name))),
                 }
             }
             "foldr" => {
                 if args.len() < 3 {
-                    return Err(anyhow!("foldr requires 3 arguments"));
+                    return Err(type_error("foldr requires 3 arguments"));
                 }
                 let f = self.eval(&args[0], env)?;
                 let mut acc = self.eval(&args[1], env)?;
@@ -274,18 +492,16 @@ impl Interpreter {
                 match list {
                     Value::List(items) => {
                         for item in items.into_iter().rev() {
-                            let item_expr = value_to_expr(&item)?;
-                            let acc_expr = value_to_expr(&acc)?;
-                            acc = self.eval_app(&value_to_expr(&f)?, &[item_expr, acc_expr], env)?;
+                            acc = self.apply_value(f.clone(), vec![item, acc])?;
                         }
                         Ok(acc)
                     }
-                    _ => Err(anyhow!("foldr: third argument must be a list")),
+                    _ => Err(type_error("foldr: third argument must be a list")),
                 }
             }
             "zip" => {
                 if args.len() < 2 {
-                    return Err(anyhow!("zip requires 2 arguments"));
+                    return Err(type_error("zip requires 2 arguments"));
                 }
                 let list1 = self.eval(&args[0], env)?;
                 let list2 = self.eval(&args[1], env)?;
@@ -293,17 +509,17 @@ impl Interpreter {
                 match (list1, list2) {
                     (Value::List(items1), Value::List(items2)) => {
                         let results: Vec<Value> = items1.into_iter()
-                            .zip(items2.into_iter())
+                            .zip(items2)
                             .map(|(a, b)| Value::List(vec![a, b]))
                             .collect();
                         Ok(Value::List(results))
                     }
-                    _ => Err(anyhow!("zip: both arguments must be lists")),
+                    _ => Err(type_error("zip: both arguments must be lists")),
                 }
             }
             "take" => {
                 if args.len() < 2 {
-                    return Err(anyhow!("take requires 2 arguments"));
+                    return Err(type_error("take requires 2 arguments"));
                 }
                 let n = self.eval(&args[0], env)?;
                 let list = self.eval(&args[1], env)?;
@@ -313,12 +529,12 @@ impl Interpreter {
                         let n = n.max(0) as usize;
                         Ok(Value::List(items.into_iter().take(n).collect()))
                     }
-                    _ => Err(anyhow!("take: invalid arguments")),
+                    _ => Err(type_error("take: invalid arguments")),
                 }
             }
             "drop" => {
                 if args.len() < 2 {
-                    return Err(anyhow!("drop requires 2 arguments"));
+                    return Err(type_error("drop requires 2 arguments"));
                 }
                 let n = self.eval(&args[0], env)?;
                 let list = self.eval(&args[1], env)?;
@@ -328,12 +544,12 @@ impl Interpreter {
                         let n = n.max(0) as usize;
                         Ok(Value::List(items.into_iter().skip(n).collect()))
                     }
-                    _ => Err(anyhow!("drop: invalid arguments")),
+                    _ => Err(type_error("drop: invalid arguments")),
                 }
             }
             "reverse" => {
                 if args.is_empty() {
-                    return Err(anyhow!("reverse requires 1 argument"));
+                    return Err(type_error("reverse requires 1 argument"));
                 }
                 let list = self.eval(&args[0], env)?;
 
@@ -342,12 +558,12 @@ impl Interpreter {
                         items.reverse();
                         Ok(Value::List(items))
                     }
-                    _ => Err(anyhow!("reverse: argument must be a list")),
+                    _ => Err(type_error("reverse: argument must be a list")),
                 }
             }
             "sort" => {
                 if args.is_empty() {
-                    return Err(anyhow!("sort requires 1 argument"));
+                    return Err(type_error("sort requires 1 argument"));
                 }
                 let list = self.eval(&args[0], env)?;
 
@@ -356,29 +572,29 @@ impl Interpreter {
                         let mut nums: Vec<i64> = items.iter()
                             .map(|v| match v {
                                 Value::Number(n) => Ok(*n),
-                                _ => Err(anyhow!("sort: list must contain only numbers")),
+                                _ => Err(type_error("sort: list must contain only numbers")),
                             })
                             .collect::<Result<Vec<_>>>()?;
                         nums.sort();
                         Ok(Value::List(nums.into_iter().map(Value::Number).collect()))
                     }
-                    _ => Err(anyhow!("sort: argument must be a list")),
+                    _ => Err(type_error("sort: argument must be a list")),
                 }
             }
             "length" => {
                 if args.is_empty() {
-                    return Err(anyhow!("length requires 1 argument"));
+                    return Err(type_error("length requires 1 argument"));
                 }
                 let list = self.eval(&args[0], env)?;
 
                 match list {
                     Value::List(items) => Ok(Value::Number(items.len() as i64)),
-                    _ => Err(anyhow!("length: argument must be a list")),
+                    _ => Err(type_error("length: argument must be a list")),
                 }
             }
             "head" => {
                 if args.is_empty() {
-                    return Err(anyhow!("head requires 1 argument"));
+                    return Err(type_error("head requires 1 argument"));
                 }
                 let list = self.eval(&args[0], env)?;
 
@@ -386,73 +602,75 @@ impl Interpreter {
                     Value::List(items) => {
                         items.first()
                             .cloned()
-                            .ok_or_else(|| anyhow!("head: empty list"))
+                            .ok_or_else(|| RuntimeError::EmptyList("head".to_string()).into())
                     }
-                    _ => Err(anyhow!("head: argument must be a list")),
+                    _ => Err(type_error("head: argument must be a list")),
                 }
             }
             "tail" => {
                 if args.is_empty() {
-                    return Err(anyhow!("tail requires 1 argument"));
+                    return Err(type_error("tail requires 1 argument"));
                 }
                 let list = self.eval(&args[0], env)?;
 
                 match list {
                     Value::List(items) => {
                         if items.is_empty() {
-                            Err(anyhow!("tail: empty list"))
+                            Err(RuntimeError::EmptyList("tail".to_string()).into())
                         } else {
                             Ok(Value::List(items[1..].to_vec()))
                         }
                     }
-                    _ => Err(anyhow!("tail: argument must be a list")),
+                    _ => Err(type_error("tail: argument must be a list")),
                 }
             }
             "sum" => {
                 if args.is_empty() {
-                    return Err(anyhow!("sum requires 1 argument"));
+                    return Err(type_error("sum requires 1 argument"));
                 }
                 let list = self.eval(&args[0], env)?;
 
                 match list {
                     Value::List(items) => {
-                        let sum: i64 = items.iter()
-                            .map(|v| match v {
-                                Value::Number(n) => Ok(*n),
-                                _ => Err(anyhow!("sum: list must contain only numbers")),
-                            })
-                            .collect::<Result<Vec<_>>>()?
-                            .into_iter()
-                            .sum();
+                        let mut sum: i64 = 0;
+                        for item in &items {
+                            match item {
+                                Value::Number(n) => {
+                                    sum = sum.checked_add(*n).ok_or_else(|| overflow("sum"))?;
+                                }
+                                _ => return Err(type_error("sum: list must contain only numbers")),
+                            }
+                        }
                         Ok(Value::Number(sum))
                     }
-                    _ => Err(anyhow!("sum: argument must be a list")),
+                    _ => Err(type_error("sum: argument must be a list")),
                 }
             }
             "product" => {
                 if args.is_empty() {
-                    return Err(anyhow!("product requires 1 argument"));
+                    return Err(type_error("product requires 1 argument"));
                 }
                 let list = self.eval(&args[0], env)?;
 
                 match list {
                     Value::List(items) => {
-                        let product: i64 = items.iter()
-                            .map(|v| match v {
-                                Value::Number(n) => Ok(*n),
-                                _ => Err(anyhow!("product: list must contain only numbers")),
-                            })
-                            .collect::<Result<Vec<_>>>()?
-                            .into_iter()
-                            .product();
+                        let mut product: i64 = 1;
+                        for item in &items {
+                            match item {
+                                Value::Number(n) => {
+                                    product = product.checked_mul(*n).ok_or_else(|| overflow("product"))?;
+                                }
+                                _ => return Err(type_error("product: list must contain only numbers")),
+                            }
+                        }
                         Ok(Value::Number(product))
                     }
-                    _ => Err(anyhow!("product: argument must be a list")),
+                    _ => Err(type_error("product: argument must be a list")),
                 }
             }
             "concat" => {
                 if args.is_empty() {
-                    return Err(anyhow!("concat requires 1 argument"));
+                    return Err(type_error("concat requires 1 argument"));
                 }
                 let list = self.eval(&args[0], env)?;
 
@@ -462,71 +680,248 @@ impl Interpreter {
                         for item in items {
                             match item {
                                 Value::List(inner) => result.extend(inner),
-                                _ => return Err(anyhow!("concat: must be a list of lists")),
+                                _ => return Err(type_error("concat: must be a list of lists")),
                             }
                         }
                         Ok(Value::List(result))
                     }
-                    _ => Err(anyhow!("concat: argument must be a list")),
+                    _ => Err(type_error("concat: argument must be a list")),
                 }
             }
             "elem" => {
                 if args.len() < 2 {
-                    return Err(anyhow!("elem requires 2 arguments"));
+                    return Err(type_error("elem requires 2 arguments"));
                 }
                 let item = self.eval(&args[0], env)?;
                 let list = self.eval(&args[1], env)?;
 
                 match list {
                     Value::List(items) => Ok(Value::Bool(items.contains(&item))),
-                    _ => Err(anyhow!("elem: second argument must be a list")),
+                    _ => Err(type_error("elem: second argument must be a list")),
+                }
+            }
+            "fromList" => {
+                if args.is_empty() {
+                    return Err(type_error("fromList requires 1 argument"));
+                }
+                let list = self.eval(&args[0], env)?;
+
+                match list {
+                    Value::List(items) => {
+                        let mut map = OrdMap::new();
+                        for item in items {
+                            let (key, value) = entry_fields(item)?;
+                            map.insert(check_key(key)?, value);
+                        }
+                        Ok(Value::Map(map))
+                    }
+                    _ => Err(type_error("fromList: argument must be a list")),
+                }
+            }
+            "toList" => {
+                if args.is_empty() {
+                    return Err(type_error("toList requires 1 argument"));
+                }
+                let map = self.eval(&args[0], env)?;
+
+                match map {
+                    Value::Map(entries) => {
+                        let items = entries.iter()
+                            .map(|(k, v)| {
+                                let mut fields = BTreeMap::new();
+                                fields.insert("key".to_string(), k.clone());
+                                fields.insert("value".to_string(), v.clone());
+                                Value::Record(fields)
+                            })
+                            .collect();
+                        Ok(Value::List(items))
+                    }
+                    _ => Err(type_error("toList: argument must be a map")),
+                }
+            }
+            "insert" => {
+                if args.len() < 3 {
+                    return Err(type_error("insert requires 3 arguments"));
+                }
+                let key = check_key(self.eval(&args[0], env)?)?;
+                let value = self.eval(&args[1], env)?;
+                let map = self.eval(&args[2], env)?;
+
+                match map {
+                    Value::Map(mut entries) => {
+                        entries.insert(key, value);
+                        Ok(Value::Map(entries))
+                    }
+                    _ => Err(type_error("insert: third argument must be a map")),
+                }
+            }
+            "insertWith" => {
+                if args.len() < 4 {
+                    return Err(type_error("insertWith requires 4 arguments"));
+                }
+                let f = self.eval(&args[0], env)?;
+                let key = check_key(self.eval(&args[1], env)?)?;
+                let value = self.eval(&args[2], env)?;
+                let map = self.eval(&args[3], env)?;
+
+                match map {
+                    Value::Map(mut entries) => {
+                        // Like Haskell's insertWith: f new old
+                        let combined = match entries.get(&key) {
+                            Some(old) => self.apply_value(f, vec![value, old.clone()])?,
+                            None => value,
+                        };
+                        entries.insert(key, combined);
+                        Ok(Value::Map(entries))
+                    }
+                    _ => Err(type_error("insertWith: fourth argument must be a map")),
+                }
+            }
+            "lookup" => {
+                if args.len() < 2 {
+                    return Err(type_error("lookup requires 2 arguments"));
+                }
+                let key = self.eval(&args[0], env)?;
+                let map = self.eval(&args[1], env)?;
+
+                match map {
+                    Value::Map(entries) => entries.get(&key)
+                        .cloned()
+                        .ok_or_else(|| RuntimeError::KeyNotFound(key.to_string_repr()).into()),
+                    _ => Err(type_error("lookup: second argument must be a map")),
+                }
+            }
+            "keys" | "values" => {
+                if args.is_empty() {
+                    return Err(type_error(format!("{} requires 1 argument", name)));
+                }
+                let map = self.eval(&args[0], env)?;
+
+                match map {
+                    Value::Map(entries) => {
+                        let items = if name == "keys" {
+                            entries.keys().cloned().collect()
+                        } else {
+                            entries.values().cloned().collect()
+                        };
+                        Ok(Value::List(items))
+                    }
+                    _ => Err(type_error(format!("{}: argument must be a map", name))),
+                }
+            }
+            "set" => {
+                if args.is_empty() {
+                    return Err(type_error("set requires 1 argument"));
+                }
+                let list = self.eval(&args[0], env)?;
+
+                match list {
+                    Value::List(items) => {
+                        let values: Result<OrdSet<Value>> = items.into_iter().map(check_key).collect();
+                        Ok(Value::Set(values?))
+                    }
+                    _ => Err(type_error("set: argument must be a list")),
+                }
+            }
+            "add" => {
+                if args.len() < 2 {
+                    return Err(type_error("add requires 2 arguments"));
+                }
+                let item = check_key(self.eval(&args[0], env)?)?;
+                let set = self.eval(&args[1], env)?;
+
+                match set {
+                    Value::Set(mut items) => {
+                        items.insert(item);
+                        Ok(Value::Set(items))
+                    }
+                    _ => Err(type_error("add: second argument must be a set")),
+                }
+            }
+            "member" => {
+                if args.len() < 2 {
+                    return Err(type_error("member requires 2 arguments"));
+                }
+                let item = self.eval(&args[0], env)?;
+                let set = self.eval(&args[1], env)?;
+
+                match set {
+                    Value::Set(items) => Ok(Value::Bool(items.contains(&item))),
+                    _ => Err(type_error("member: second argument must be a set")),
+                }
+            }
+            "elems" => {
+                if args.is_empty() {
+                    return Err(type_error("elems requires 1 argument"));
+                }
+                let set = self.eval(&args[0], env)?;
+
+                match set {
+                    Value::Set(items) => Ok(Value::List(items.iter().cloned().collect())),
+                    _ => Err(type_error("elems: argument must be a set")),
                 }
             }
-            _ => Err(anyhow!("Unknown builtin: {}", name)),
+            _ => Err(type_error(format!("Unknown builtin: {}", name))),
         }
     }
 
-    fn eval_binop(&mut self, op: BinOp, left: &Expr, right: &Expr, env: &Rc<Env>) -> Result<Value> {
+    fn eval_binop(&mut self, op: BinOp, left: &Expr, right: &Expr, env: &Rc<Env>, span: Span) -> Result<Value> {
         match op {
-            BinOp::PipeForward => {
-                // left >> right means right(left)
-                let left_val = self.eval(left, env)?;
-                let left_expr = value_to_expr(&left_val)?;
-                self.eval_app(right, &[left_expr], env)
+            BinOp::ComposeForward => {
+                // f >> g means \x -> g (f x)
+                let f = self.eval(left, env)?;
+                let g = self.eval(right, env)?;
+                Ok(compose(f, g))
             }
-            BinOp::PipeBackward => {
-                // left << right means left(right)
-                let right_val = self.eval(right, env)?;
-                let right_expr = value_to_expr(&right_val)?;
-                self.eval_app(left, &[right_expr], env)
+            BinOp::ComposeBackward => {
+                // f << g means \x -> f (g x)
+                let f = self.eval(left, env)?;
+                let g = self.eval(right, env)?;
+                Ok(compose(g, f))
+            }
+            BinOp::Pipe => {
+                // x |> f means f x
+                self.eval_app(right, std::slice::from_ref(left), env, Some(span))
+            }
+            BinOp::Apply => {
+                // f $ x means f x
+                self.eval_app(left, std::slice::from_ref(right), env, Some(span))
             }
             _ => {
                 let left_val = self.eval(left, env)?;
                 let right_val = self.eval(right, env)?;
 
                 match op {
-                    BinOp::Add => binary_arith(left_val, right_val, |a, b| Ok(a + b)),
-                    BinOp::Sub => binary_arith(left_val, right_val, |a, b| Ok(a - b)),
-                    BinOp::Mul => binary_arith(left_val, right_val, |a, b| Ok(a * b)),
+                    BinOp::Add => binary_arith(left_val, right_val, |a, b| {
+                        a.checked_add(b).ok_or_else(|| overflow("+"))
+                    }),
+                    BinOp::Sub => binary_arith(left_val, right_val, |a, b| {
+                        a.checked_sub(b).ok_or_else(|| overflow("-"))
+                    }),
+                    BinOp::Mul => binary_arith(left_val, right_val, |a, b| {
+                        a.checked_mul(b).ok_or_else(|| overflow("*"))
+                    }),
                     BinOp::Div => binary_arith(left_val, right_val, |a, b| {
                         if b == 0 {
-                            Err(anyhow!("Division by zero"))
+                            Err(RuntimeError::DivisionByZero.into())
                         } else {
-                            Ok(a / b)
+                            a.checked_div(b).ok_or_else(|| overflow("/"))
                         }
                     }),
                     BinOp::Mod => binary_arith(left_val, right_val, |a, b| {
                         if b == 0 {
-                            Err(anyhow!("Modulo by zero"))
+                            Err(RuntimeError::DivisionByZero.into())
                         } else {
-                            Ok(a % b)
+                            a.checked_rem(b).ok_or_else(|| overflow("%"))
                         }
                     }),
                     BinOp::Pow => binary_arith(left_val, right_val, |a, b| {
                         if b < 0 {
-                            Err(anyhow!("Negative exponent not supported"))
+                            Err(RuntimeError::InvalidArgument("Negative exponent not supported".to_string()).into())
                         } else {
-                            Ok(a.pow(b as u32))
+                            u32::try_from(b).ok()
+                                .and_then(|b| a.checked_pow(b))
+                                .ok_or_else(|| overflow("^"))
                         }
                     }),
                     BinOp::Eq => Ok(Value::Bool(left_val == right_val)),
@@ -542,7 +937,7 @@ impl Interpreter {
                             items.insert(0, item);
                             Ok(Value::List(items))
                         }
-                        _ => Err(anyhow!(":: requires element and list")),
+                        _ => Err(type_error(":: requires element and list")),
                     }
                     BinOp::Concat => match (left_val, right_val) {
                         (Value::List(mut a), Value::List(b)) => {
@@ -553,7 +948,7 @@ impl Interpreter {
                             a.push_str(&b);
                             Ok(Value::String(a))
                         }
-                        _ => Err(anyhow!("++ requires two lists or two strings")),
+                        _ => Err(type_error("++ requires two lists or two strings")),
                     }
                     _ => unreachable!(),
                 }
@@ -561,6 +956,22 @@ impl Interpreter {
         }
     }
 
+    fn eval_record_update(&mut self, record: &Expr, fields: &[(String, Expr)], env: &Rc<Env>) -> Result<Value> {
+        match self.eval(record, env)? {
+            Value::Record(mut values) => {
+                for (name, field_expr) in fields {
+                    if !values.contains_key(name) {
+                        return Err(type_error(format!("Record has no field: {}", name)));
+                    }
+                    let value = self.eval(field_expr, env)?;
+                    values.insert(name.clone(), value);
+                }
+                Ok(Value::Record(values))
+            }
+            _ => Err(type_error("Cannot update non-record")),
+        }
+    }
+
     fn eval_list_comp(&mut self, expr: &Expr, var: &str, list_expr: &Expr, guards: &[Expr], env: &Rc<Env>) -> Result<Value> {
         let list_val = self.eval(list_expr, env)?;
 
@@ -582,7 +993,7 @@ impl Interpreter {
                                 break;
                             }
                             Value::Bool(true) => {}
-                            _ => return Err(anyhow!("Guard must be boolean")),
+                            _ => return Err(type_error("Guard must be boolean")),
                         }
                     }
 
@@ -594,7 +1005,7 @@ impl Interpreter {
 
                 Ok(Value::List(results))
             }
-            _ => Err(anyhow!("List comprehension requires a list")),
+            _ => Err(type_error("List comprehension requires a list")),
         }
     }
 
@@ -608,14 +1019,14 @@ impl Interpreter {
             }
         }
 
-        Err(anyhow!("No pattern matched"))
+        Err(RuntimeError::NonExhaustiveMatch(val.to_string_repr()).into())
     }
 
     fn match_pattern(&self, pattern: &Pattern, value: &Value, env: &mut Env) -> Result<bool> {
         match (pattern, value) {
             (Pattern::Wildcard, _) => Ok(true),
             (Pattern::Var(name), val) => {
-                env.insert(name.clone(), val.clone());
+                env.insert(name.text.clone(), val.clone());
                 Ok(true)
             }
             (Pattern::Number(n), Value::Number(m)) => Ok(n == m),
@@ -632,6 +1043,15 @@ impl Interpreter {
                 }
                 Ok(true)
             }
+            (Pattern::Record(fields), Value::Record(values)) => {
+                for (name, p) in fields {
+                    match values.get(name) {
+                        Some(v) if self.match_pattern(p, v, env)? => {}
+                        _ => return Ok(false),
+                    }
+                }
+                Ok(true)
+            }
             (Pattern::Cons { head, tail }, Value::List(values)) => {
                 if values.is_empty() {
                     return Ok(false);
@@ -653,7 +1073,7 @@ where
 {
     match (left, right) {
         (Value::Number(a), Value::Number(b)) => Ok(Value::Number(f(a, b)?)),
-        _ => Err(anyhow!("Arithmetic operation requires numbers")),
+        _ => Err(type_error("Arithmetic operation requires numbers")),
     }
 }
 
@@ -663,7 +1083,7 @@ where
 {
     match (left, right) {
         (Value::Number(a), Value::Number(b)) => Ok(Value::Bool(f(a, b))),
-        _ => Err(anyhow!("Comparison requires numbers")),
+        _ => Err(type_error("Comparison requires numbers")),
     }
 }
 
@@ -673,25 +1093,80 @@ where
 {
     match (left, right) {
         (Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(f(a, b))),
-        _ => Err(anyhow!("Boolean operation requires booleans")),
+        _ => Err(type_error("Boolean operation requires booleans")),
     }
 }
 
-fn value_to_expr(value: &Value) -> Result<Expr> {
+/// Build the closure `\x -> second (first x)`.
+fn compose(first: Value, second: Value) -> Value {
+    let mut env = Env::new();
+    env.insert("_first".to_string(), first);
+    env.insert("_second".to_string(), second);
+
+    Value::Function {
+        params: vec!["_x".to_string()],
+        body: Rc::new(Expr::App {
+            func: Box::new(Expr::Var("_second".to_string())),
+            args: vec![Expr::App {
+                func: Box::new(Expr::Var("_first".to_string())),
+                args: vec![Expr::Var("_x".to_string())],
+                span: Span::default(),
+            }],
+            span: Span::default(),
+        }),
+        env: Rc::new(env),
+    }
+}
+
+fn type_error(message: impl Into<String>) -> EvalError {
+    RuntimeError::TypeError(message.into()).into()
+}
+
+fn overflow(operation: &str) -> EvalError {
+    RuntimeError::Overflow(operation.to_string()).into()
+}
+
+/// Reject values that cannot be ordered, so they never end up as map keys
+/// or set elements.
+fn check_key(value: Value) -> Result<Value> {
+    if is_orderable(&value) {
+        Ok(value)
+    } else {
+        Err(type_error("Functions cannot be used as map keys or set elements"))
+    }
+}
+
+fn is_orderable(value: &Value) -> bool {
     match value {
-        Value::Number(n) => Ok(Expr::Number(*n)),
-        Value::Bool(b) => Ok(Expr::Bool(*b)),
-        Value::String(s) => Ok(Expr::String(s.clone())),
-        Value::List(items) => {
-            let exprs: Result<Vec<Expr>> = items.iter().map(value_to_expr).collect();
-            Ok(Expr::List(exprs?))
+        Value::Function { .. } | Value::Builtin(_) | Value::Thunk { .. } => false,
+        Value::List(items) => items.iter().all(is_orderable),
+        Value::Record(fields) => fields.values().all(is_orderable),
+        Value::Map(entries) => entries.iter().all(|(key, value)| is_orderable(key) && is_orderable(value)),
+        Value::Set(items) => items.iter().all(is_orderable),
+        Value::Number(_) | Value::Bool(_) | Value::String(_) => true,
+    }
+}
+
+/// Split a `{ key = k, value = v }` record into its parts.
+fn entry_fields(entry: Value) -> Result<(Value, Value)> {
+    match entry {
+        Value::Record(mut fields) => {
+            match (fields.remove("key"), fields.remove("value")) {
+                (Some(key), Some(value)) => Ok((key, value)),
+                _ => Err(type_error("fromList: entries must have key and value fields")),
+            }
         }
-        Value::Function { params, body, env: _ } => Ok(Expr::Lambda {
-            params: params.clone(),
-            body: Box::new((**body).clone()),
-        }),
-        Value::Builtin(name) => Ok(Expr::Var(name.clone())),
-        _ => Err(anyhow!("Cannot convert value to expression")),
+        _ => Err(type_error("fromList: entries must be records")),
+    }
+}
+
+fn builtin_arity(name: &str) -> usize {
+    match name {
+        "insertWith" => 4,
+        "fold" | "foldl" | "foldr" | "insert" => 3,
+        "map" | "filter" | "zip" | "take" | "drop" | "elem" => 2,
+        "lookup" | "add" | "member" => 2,
+        _ => 1,
     }
 }
 
@@ -701,7 +1176,9 @@ pub fn get_builtin_env() -> Env {
         "map", "filter", "fold", "foldl", "foldr",
         "zip", "take", "drop", "reverse", "sort",
         "length", "head", "tail", "sum", "product",
-        "concat", "elem"
+        "concat", "elem",
+        "fromList", "toList", "insert", "insertWith", "lookup",
+        "keys", "values", "set", "add", "member", "elems"
     ];
 
     for name in builtins {
@@ -710,3 +1187,234 @@ pub fn get_builtin_env() -> Env {
 
     env
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::{parser, types};
+
+    fn run(code: &str) -> (String, String) {
+        let expr = parser::parse(code).unwrap();
+        let ty = types::TypeChecker::new()
+            .infer(&expr, &mut types::get_builtin_env())
+            .unwrap();
+        let value = Interpreter::new()
+            .eval(&expr, &Rc::new(get_builtin_env()))
+            .unwrap();
+        (value.to_string_repr(), ty.to_string())
+    }
+
+    #[test]
+    fn test_compose_forward() {
+        assert_eq!(run("((\\x -> x * 2) >> (\\x -> x + 1)) 5"), ("11".to_string(), "Int".to_string()));
+        assert_eq!(run("(filter (\\x -> x > 0) >> map (\\x -> x * 2) >> sum) [1, -2, 3]").0, "8");
+    }
+
+    #[test]
+    fn test_compose_backward() {
+        assert_eq!(run("((\\x -> x * 2) << (\\x -> x + 1)) 5").0, "12");
+        assert_eq!(run("(sum << reverse) [1, 2, 3]").0, "6");
+    }
+
+    #[test]
+    fn test_compose_is_a_function() {
+        let (_, ty) = run("map (\\x -> x + 1) >> sum");
+        assert_eq!(ty, "[Int] -> Int");
+    }
+
+    #[test]
+    fn test_pipe() {
+        assert_eq!(run("[1, 2, 3] |> map (\\x -> x * 2) |> sum"), ("12".to_string(), "Int".to_string()));
+        assert_eq!(run("let n = 10 in 1..3 |> map (\\x -> x + n)").0, "[11, 12, 13]");
+    }
+
+    #[test]
+    fn test_apply() {
+        assert_eq!(run("sum $ map (\\x -> x * x) $ take 2 [3, 4, 5]"), ("25".to_string(), "Int".to_string()));
+        assert_eq!(run("head $ [1, 2] ++ [3]").0, "1");
+    }
+
+    #[test]
+    fn test_precedence() {
+        // `$` binds looser than `|>`, which binds looser than `>>`
+        assert_eq!(run("reverse $ [3, 1, 2] |> sort >> take 2").0, "[2, 1]");
+        assert_eq!(run("1 <= 2 |> (\\b -> if b then 1 else 0)").0, "1");
+    }
+
+    #[test]
+    fn test_record_literal_and_access() {
+        assert_eq!(
+            run("{ name = \"a\", age = 3 }"),
+            ("{ age = 3, name = \"a\" }".to_string(), "{ age: Int, name: String }".to_string())
+        );
+        assert_eq!(run("let r = { name = \"a\", age = 3 } in r.age + 1").0, "4");
+    }
+
+    #[test]
+    fn test_record_update() {
+        assert_eq!(run("let r = { name = \"a\", age = 3 } in { r | age = 4 }").0, "{ age = 4, name = \"a\" }");
+    }
+
+    #[test]
+    fn test_record_field_access_is_structural() {
+        let (value, ty) = run("map (\\p -> p.age) [{ name = \"a\", age = 3 }, { name = \"b\", age = 5 }]");
+        assert_eq!(value, "[3, 5]");
+        assert_eq!(ty, "[Int]");
+
+        let (_, ty) = run("\\p -> p.age + 1");
+        assert!(ty.ends_with("{ age: Int, .. } -> Int"), "{}", ty);
+    }
+
+    #[test]
+    fn test_record_pattern() {
+        assert_eq!(run("match { x = 1, y = 2 } with { x = 0 } -> 0 | { x, y = b } -> x + b").0, "3");
+        assert_eq!(run("match [1] with _ -> 0").0, "0");
+    }
+
+    #[test]
+    fn test_recursive_let() {
+        assert_eq!(
+            run("let fact = \\n -> if n == 0 then 1 else n * fact (n - 1) in fact 5"),
+            ("120".to_string(), "Int".to_string())
+        );
+        assert_eq!(run("let ones = \\n -> if n == 0 then [] else 1 :: ones (n - 1) in ones 3").0, "[1, 1, 1]");
+    }
+
+    #[test]
+    fn test_foldr_and_occurs_check() {
+        assert_eq!(run("foldr (\\x, acc -> x :: acc) [] [1, 2, 3]").0, "[1, 2, 3]");
+        assert_eq!(run("foldr (\\x, acc -> acc - x) 0 [1, 2]").0, "-3");
+
+        let expr = parser::parse("\\x -> x x").unwrap();
+        let result = types::TypeChecker::new().infer(&expr, &mut types::get_builtin_env());
+        assert!(result.unwrap_err().to_string().contains("Infinite type"));
+    }
+
+    #[test]
+    fn test_record_type_errors() {
+        let check = |code: &str| {
+            let expr = parser::parse(code).unwrap();
+            types::TypeChecker::new().infer(&expr, &mut types::get_builtin_env())
+        };
+        assert!(check("{ a = 1 }.b").is_err());
+        assert!(check("{ { a = 1 } | a = true }").is_err());
+        assert!(check("[{ a = 1 }, { b = 1 }]").is_err());
+        assert!(parser::parse("{ a = 1, a = 2 }").is_err());
+    }
+
+    #[test]
+    fn test_open_record_signature_is_generic() {
+        // Each use of `f` can pass a record with other fields
+        let mut env = types::get_builtin_env();
+        env.insert("f".to_string(), parser::parse_type("{ a : Int, .. } -> Int").unwrap());
+        let expr = parser::parse("f { a = 1, b = true } + f { a = 2, b = 3 }").unwrap();
+        assert!(types::TypeChecker::new().infer(&expr, &mut env).is_ok());
+    }
+
+    #[test]
+    fn test_map_literal_and_lookup() {
+        let (value, ty) = run("#{2 => \"b\", 1 => \"a\"}");
+        assert_eq!(value, "#{1 => \"a\", 2 => \"b\"}");
+        assert_eq!(ty, "Map Int String");
+        assert_eq!(run("lookup 2 (insert 2 \"c\" #{2 => \"b\"})").0, "\"c\"");
+        assert_eq!(run("keys (fromList [{ key = 3, value = true }, { key = 1, value = false }])").0, "[1, 3]");
+    }
+
+    #[test]
+    fn test_map_counting() {
+        let (value, ty) = run("fold (\\m, x -> insertWith (\\a, b -> a + b) x 1 m) #{} [3, 1, 3, 3]");
+        assert_eq!(value, "#{1 => 1, 3 => 3}");
+        assert_eq!(ty, "Map Int Int");
+        assert_eq!(run("toList #{1 => 2}").0, "[{ key = 1, value = 2 }]");
+    }
+
+    #[test]
+    fn test_map_is_persistent() {
+        assert_eq!(run("let m = #{1 => 1} in let n = insert 2 2 m in [length (keys m), length (keys n)]").0, "[1, 2]");
+        // The accumulator is shared through the environment on every step
+        assert_eq!(run("length (keys (fold (\\m, x -> insert x x m) #{} (1..20000)))").0, "20000");
+    }
+
+    #[test]
+    fn test_sets() {
+        let (value, ty) = run("set [3, 1, 3, 2]");
+        assert_eq!(value, "#[1, 2, 3]");
+        assert_eq!(ty, "Set Int");
+        assert_eq!(run("member 4 (add 4 #[1, 2])").0, "true");
+        assert_eq!(run("elems (set [\"b\", \"a\", \"b\"])").0, "[\"a\", \"b\"]");
+        assert_eq!(run("set [[2, 1], [1, 2], [2, 1]] == #[[1, 2], [2, 1]]").0, "true");
+        // Functions nested in maps can't be ordered either
+        assert!(matches!(run_err("#[#{1 => length}, #{1 => reverse}]").error, RuntimeError::TypeError(_)));
+        assert_eq!(run("(\\x -> x) == (\\x -> x)").0, "false");
+    }
+
+    #[test]
+    fn test_lookup_missing_key() {
+        let expr = parser::parse("lookup 5 #{1 => 1}").unwrap();
+        let result = Interpreter::new().eval(&expr, &Rc::new(get_builtin_env()));
+        assert!(result.is_err());
+    }
+
+    fn run_err(code: &str) -> EvalError {
+        let expr = parser::parse(code).unwrap();
+        Interpreter::new()
+            .eval(&expr, &Rc::new(get_builtin_env()))
+            .unwrap_err()
+    }
+
+    #[test]
+    fn test_runtime_error_kinds() {
+        assert_eq!(run_err("1 / 0").error, RuntimeError::DivisionByZero);
+        assert_eq!(run_err("head []").error, RuntimeError::EmptyList("head".to_string()));
+        assert_eq!(run_err("nope 1").error, RuntimeError::UndefinedVariable("nope".to_string()));
+        assert_eq!(run_err("match 3 with 1 -> 0").error, RuntimeError::NonExhaustiveMatch("3".to_string()));
+        assert!(matches!(run_err("1 + true").error, RuntimeError::TypeError(_)));
+    }
+
+    #[test]
+    fn test_runtime_error_span() {
+        let code = "let f = \\x -> 10 / x in\nf 1 + f 0";
+        let err = run_err(code);
+        let span = err.span.unwrap();
+        assert_eq!(&code[span.start..span.end], "10 / x");
+        assert_eq!(span.line_col(code), (1, 15));
+    }
+
+    #[test]
+    fn test_runtime_error_trace() {
+        let code = "let f = \\x -> head x in\nlet g = \\y -> f y in\ng []";
+        let err = run_err(code);
+        let trace: Vec<(String, Option<(usize, usize)>)> = err.trace.iter()
+            .map(|frame| (frame.function.clone(), frame.call_site.map(|s| s.line_col(code))))
+            .collect();
+        assert_eq!(trace, vec![
+            ("f".to_string(), Some((2, 15))),
+            ("g".to_string(), Some((3, 1))),
+        ]);
+
+        let err = run_err("map (\\x -> 1 / x) [1, 0]");
+        assert_eq!(err.trace[0].function, "<lambda>");
+        assert_eq!(err.trace[0].call_site, None);
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let overflows = [
+            "2 ^ 64",
+            "product 1..30",
+            "(0 - 9223372036854775807 - 1) / (0 - 1)",
+            "(0 - 9223372036854775807 - 1) % (0 - 1)",
+            "9223372036854775807 + 1",
+            "0 - 9223372036854775807 - 2",
+            "4611686018427387904 * 2",
+            "sum [9223372036854775807, 1]",
+            "-(0 - 9223372036854775807 - 1)",
+            "2 ^ 4294967296",
+        ];
+        for code in overflows {
+            assert!(matches!(run_err(code).error, RuntimeError::Overflow(_)), "{}", code);
+        }
+        assert_eq!(run("2 ^ 62").0, "4611686018427387904");
+        assert_eq!(run("product 1..20").0, "2432902008176640000");
+    }
+}
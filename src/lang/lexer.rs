@@ -0,0 +1,87 @@
+use std::ops::Range;
+
+const KEYWORDS: &[&str] = &["let", "in", "match", "with", "if", "then", "else", "true", "false"];
+
+/// Operators of more than one character, matched before single characters.
+const OPERATORS: &[&str] = &[
+    "->", "<-", "=>", "::", "++", "==", "!=", "<=", ">=", "&&", "||", "|>", ">>", "<<", "..", "#{", "#[",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Whitespace,
+    /// A `--` comment, up to the end of the line.
+    Comment,
+    Number,
+    /// A string literal with its quotes; unterminated ones run to the end.
+    String,
+    Ident,
+    Keyword,
+    Operator,
+    /// Brackets, braces, parentheses and commas.
+    Punct,
+    /// A character the language doesn't use.
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    /// Byte range in the source.
+    pub span: Range<usize>,
+}
+
+impl Token {
+    pub fn text<'a>(&self, source: &'a str) -> &'a str {
+        &source[self.span.clone()]
+    }
+
+    /// Whitespace and comments, which don't affect what the code means.
+    pub fn is_trivia(&self) -> bool {
+        matches!(self.kind, TokenKind::Whitespace | TokenKind::Comment)
+    }
+}
+
+/// Split source code into tokens. Every byte belongs to exactly one token,
+/// so the tokens can be joined back into the source, and nothing is
+/// rejected: this works on code that doesn't parse.
+pub fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while let Some(c) = source[pos..].chars().next() {
+        let rest = &source[pos..];
+        let (kind, len) = if c.is_whitespace() {
+            (TokenKind::Whitespace, prefix_len(rest, char::is_whitespace))
+        } else if rest.starts_with("--") {
+            (TokenKind::Comment, rest.find('\n').unwrap_or(rest.len()))
+        } else if c.is_ascii_digit() {
+            (TokenKind::Number, prefix_len(rest, |c| c.is_ascii_digit()))
+        } else if c == '"' {
+            let len = rest[1..].find('"').map_or(rest.len(), |end| end + 2);
+            (TokenKind::String, len)
+        } else if c.is_alphabetic() || c == '_' {
+            let len = prefix_len(rest, |c| c.is_alphanumeric() || c == '_');
+            let kind = if KEYWORDS.contains(&&rest[..len]) { TokenKind::Keyword } else { TokenKind::Ident };
+            (kind, len)
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            (TokenKind::Operator, op.len())
+        } else if "()[]{},".contains(c) {
+            (TokenKind::Punct, 1)
+        } else if "+-*/%^<>=|\\$.:;!&#".contains(c) {
+            (TokenKind::Operator, 1)
+        } else {
+            (TokenKind::Unknown, c.len_utf8())
+        };
+
+        tokens.push(Token { kind, span: pos..pos + len });
+        pos += len;
+    }
+
+    tokens
+}
+
+/// Length in bytes of the longest prefix whose chars all match.
+fn prefix_len(s: &str, matches: impl Fn(char) -> bool) -> usize {
+    s.find(|c| !matches(c)).unwrap_or(s.len())
+}
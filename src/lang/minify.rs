@@ -0,0 +1,581 @@
+use crate::lang::ast::*;
+use crate::lang::parser;
+use anyhow::Result;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+const KEYWORDS: &[&str] = &["let", "in", "match", "with", "if", "then", "else", "true", "false"];
+
+/// Rewrite source code as the shortest equivalent program this can find:
+/// bound names become single letters, lambdas that only pass their
+/// arguments on are eta-reduced, and the result is printed without
+/// comments, unneeded whitespace or redundant parentheses.
+pub fn minify(source: &str) -> Result<String> {
+    let expr = parser::parse(source)?;
+    Ok(print(&minify_expr(expr)))
+}
+
+pub fn minify_expr(expr: Expr) -> Expr {
+    let expr = Simplifier { strict_lets: Vec::new() }.simplify(expr);
+    let mut renamer = Renamer {
+        free: free_vars(&expr),
+        scope: Vec::new(),
+    };
+    renamer.rename(expr)
+}
+
+/// Print an expression back to source as compactly as it will parse.
+pub fn print(expr: &Expr) -> String {
+    let mut printer = Printer { out: String::new() };
+    printer.expr(expr, 0);
+    printer.out
+}
+
+/// Rewrites that keep what the program means while making it shorter.
+struct Simplifier {
+    /// Names of the `let`s whose values are being simplified, outside any
+    /// lambda. Evaluating the value evaluates these names again.
+    strict_lets: Vec<String>,
+}
+
+impl Simplifier {
+    fn simplify(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::Lambda { params, body } => {
+                // The body only runs when the lambda is called
+                let strict = std::mem::take(&mut self.strict_lets);
+                let body = self.simplify(Rc::unwrap_or_clone(body));
+                self.strict_lets = strict;
+                self.eta_reduce(params, body)
+            }
+            Expr::Let { name, value, body } => {
+                self.strict_lets.push(name.text.clone());
+                let value = self.simplify(Rc::unwrap_or_clone(value));
+                self.strict_lets.pop();
+                let body = self.simplify(*body);
+                Expr::Let { name, value: Rc::new(value), body: Box::new(body) }
+            }
+            Expr::App { func, args, span } => {
+                let func = self.simplify(*func);
+                let args: Vec<Expr> = args.into_iter().map(|arg| self.simplify(arg)).collect();
+                match func {
+                    // `(f a) b` is `f a b`
+                    Expr::App { func: inner, args: mut first, span } => {
+                        first.extend(args);
+                        Expr::App { func: inner, args: first, span }
+                    }
+                    func => Expr::App { func: Box::new(func), args, span },
+                }
+            }
+            Expr::UnOp { op: UnOp::Neg, expr } => match self.simplify(*expr) {
+                Expr::Number(n) if n != i64::MIN => Expr::Number(-n),
+                expr => Expr::UnOp { op: UnOp::Neg, expr: Box::new(expr) },
+            },
+            expr => map_children(expr, |e| self.simplify(e)),
+        }
+    }
+
+    /// `\x, y -> f a x y` is `f a`, as long as `f a` can be evaluated early
+    /// without running anything: `f` and `a` are names, literals or lambdas,
+    /// and none is a `let` being defined, which would loop forever.
+    fn eta_reduce(&self, mut params: Vec<Name>, body: Expr) -> Expr {
+        let Expr::App { func, mut args, span } = body else {
+            return Expr::Lambda { params, body: Rc::new(body) };
+        };
+
+        let cheap = |e: &Expr| match e {
+            Expr::Var(name) => !self.strict_lets.contains(name),
+            Expr::Number(_) | Expr::Bool(_) | Expr::String(_) | Expr::Lambda { .. } => true,
+            _ => false,
+        };
+        let mut dropped = 0;
+        while dropped < params.len().min(args.len()) {
+            let param = &params[params.len() - 1 - dropped];
+            if args[args.len() - 1 - dropped] != Expr::Var(param.text.clone()) {
+                break;
+            }
+            dropped += 1;
+        }
+        // The dropped parameters can't be used anywhere else
+        while dropped > 0 {
+            let kept = &args[..args.len() - dropped];
+            let dropped_params = &params[params.len() - dropped..];
+            let mut used = free_vars(&func);
+            kept.iter().for_each(|arg| used.extend(free_vars(arg)));
+            let distinct = dropped_params.iter().enumerate().all(|(i, p)| dropped_params[..i].iter().all(|q| q.text != p.text));
+            if distinct && dropped_params.iter().all(|p| !used.contains(&p.text)) && cheap(&func) && kept.iter().all(cheap) {
+                break;
+            }
+            dropped -= 1;
+        }
+        if dropped == 0 {
+            return Expr::Lambda { params, body: Rc::new(Expr::App { func, args, span }) };
+        }
+
+        args.truncate(args.len() - dropped);
+        params.truncate(params.len() - dropped);
+        let body = if args.is_empty() { *func } else { Expr::App { func, args, span } };
+        if params.is_empty() {
+            body
+        } else {
+            Expr::Lambda { params, body: Rc::new(body) }
+        }
+    }
+}
+
+/// Renames every bound variable to the shortest name that doesn't clash.
+struct Renamer {
+    /// Names used but not bound, such as builtins.
+    free: HashSet<String>,
+    /// Bindings in scope, innermost last, as (original, new) names.
+    scope: Vec<(String, String)>,
+}
+
+impl Renamer {
+    fn rename(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::Var(name) => Expr::Var(self.lookup(&name)),
+            Expr::Lambda { params, body } => {
+                let depth = self.scope.len();
+                let params = params.into_iter().map(|p| self.bind(p)).collect();
+                let body = self.rename(Rc::unwrap_or_clone(body));
+                self.scope.truncate(depth);
+                Expr::Lambda { params, body: Rc::new(body) }
+            }
+            Expr::Let { name, value, body } => {
+                let depth = self.scope.len();
+                let name = self.bind(name);
+                let value = self.rename(Rc::unwrap_or_clone(value));
+                let body = self.rename(*body);
+                self.scope.truncate(depth);
+                Expr::Let { name, value: Rc::new(value), body: Box::new(body) }
+            }
+            Expr::Match { expr, arms, span } => {
+                let expr = self.rename(*expr);
+                let arms = arms
+                    .into_iter()
+                    .map(|arm| {
+                        let depth = self.scope.len();
+                        let pattern = self.rename_pattern(arm.pattern);
+                        let expr = self.rename(arm.expr);
+                        self.scope.truncate(depth);
+                        MatchArm { pattern, expr }
+                    })
+                    .collect();
+                Expr::Match { expr: Box::new(expr), arms, span }
+            }
+            Expr::ListComp { expr, var, list, guards } => {
+                // The list is outside the variable's scope
+                let list = self.rename(*list);
+                let depth = self.scope.len();
+                let var = self.bind(var);
+                let expr = self.rename(*expr);
+                let guards = guards.into_iter().map(|g| self.rename(g)).collect();
+                self.scope.truncate(depth);
+                Expr::ListComp { expr: Box::new(expr), var, list: Box::new(list), guards }
+            }
+            expr => map_children(expr, |e| self.rename(e)),
+        }
+    }
+
+    fn rename_pattern(&mut self, pattern: Pattern) -> Pattern {
+        match pattern {
+            Pattern::Var(name) => Pattern::Var(self.bind(name)),
+            Pattern::List(items) => Pattern::List(items.into_iter().map(|p| self.rename_pattern(p)).collect()),
+            Pattern::Record(fields) => Pattern::Record(
+                fields
+                    .into_iter()
+                    .map(|(field, pattern)| match pattern {
+                        // `{ age }` is shorter than `{ age = a }` if it can stay
+                        Pattern::Var(name) if name.text == field && !self.taken(&field) => {
+                            self.scope.push((field.clone(), field.clone()));
+                            (field, Pattern::Var(name))
+                        }
+                        pattern => (field, self.rename_pattern(pattern)),
+                    })
+                    .collect(),
+            ),
+            Pattern::Cons { head, tail } => Pattern::Cons {
+                head: Box::new(self.rename_pattern(*head)),
+                tail: Box::new(self.rename_pattern(*tail)),
+            },
+            pattern => pattern,
+        }
+    }
+
+    fn lookup(&self, name: &str) -> String {
+        self.scope
+            .iter()
+            .rev()
+            .find(|(original, _)| original == name)
+            .map_or_else(|| name.to_string(), |(_, new)| new.clone())
+    }
+
+    /// Bring `name` into scope under a new name and return that.
+    fn bind(&mut self, name: Name) -> Name {
+        let new = short_names().find(|n| !self.taken(n)).unwrap();
+        self.scope.push((name.text, new.clone()));
+        Name { text: new, span: name.span }
+    }
+
+    /// Whether a new binding called `name` would hide one that's needed.
+    fn taken(&self, name: &str) -> bool {
+        self.free.contains(name) || self.scope.iter().any(|(_, new)| new == name)
+    }
+}
+
+/// a..z, A..Z, then two letters and so on, leaving out keywords.
+fn short_names() -> impl Iterator<Item = String> {
+    let letters: Vec<char> = ('a'..='z').chain('A'..='Z').collect();
+    (1..).flat_map(move |len| {
+        let letters = letters.clone();
+        let count = letters.len().pow(len as u32);
+        (0..count).map(move |mut i| {
+            let mut name = String::new();
+            for _ in 0..len {
+                name.insert(0, letters[i % letters.len()]);
+                i /= letters.len();
+            }
+            name
+        })
+    })
+    .filter(|name| !KEYWORDS.contains(&name.as_str()))
+}
+
+/// Call `f` on each direct subexpression.
+fn for_each_child(expr: &Expr, mut f: impl FnMut(&Expr)) {
+    match expr {
+        Expr::List(items) | Expr::Set(items) => items.iter().for_each(f),
+        Expr::Record(fields) => fields.iter().for_each(|(_, e)| f(e)),
+        Expr::Map(entries) => entries.iter().for_each(|(k, v)| {
+            f(k);
+            f(v);
+        }),
+        Expr::Field { expr, .. } | Expr::UnOp { expr, .. } => f(expr),
+        Expr::RecordUpdate { record, fields } => {
+            f(record);
+            fields.iter().for_each(|(_, e)| f(e));
+        }
+        Expr::Lambda { body, .. } => f(body),
+        Expr::App { func, args, .. } => {
+            f(func);
+            args.iter().for_each(f);
+        }
+        Expr::Let { value, body, .. } => {
+            f(value);
+            f(body);
+        }
+        Expr::Match { expr, arms, .. } => {
+            f(expr);
+            arms.iter().for_each(|arm| f(&arm.expr));
+        }
+        Expr::If { cond, then_branch, else_branch } => {
+            f(cond);
+            f(then_branch);
+            f(else_branch);
+        }
+        Expr::BinOp { left, right, .. } => {
+            f(left);
+            f(right);
+        }
+        Expr::ListComp { expr, list, guards, .. } => {
+            f(expr);
+            f(list);
+            guards.iter().for_each(f);
+        }
+        Expr::Number(_) | Expr::Bool(_) | Expr::String(_) | Expr::Var(_) | Expr::Range { .. } => {}
+    }
+}
+
+/// Names an expression uses without binding them.
+pub fn free_vars(expr: &Expr) -> HashSet<String> {
+    let mut free = HashSet::new();
+    collect_free(expr, &mut Vec::new(), &mut free);
+    free
+}
+
+fn collect_free(expr: &Expr, bound: &mut Vec<String>, free: &mut HashSet<String>) {
+    let depth = bound.len();
+    match expr {
+        Expr::Var(name) => {
+            if !bound.contains(name) {
+                free.insert(name.clone());
+            }
+        }
+        Expr::Lambda { params, body } => {
+            bound.extend(params.iter().map(|p| p.text.clone()));
+            collect_free(body, bound, free);
+        }
+        Expr::Let { name, value, body } => {
+            bound.push(name.text.clone());
+            collect_free(value, bound, free);
+            collect_free(body, bound, free);
+        }
+        Expr::Match { expr, arms, .. } => {
+            collect_free(expr, bound, free);
+            for arm in arms {
+                pattern_vars(&arm.pattern, bound);
+                collect_free(&arm.expr, bound, free);
+                bound.truncate(depth);
+            }
+        }
+        Expr::ListComp { expr, var, list, guards } => {
+            collect_free(list, bound, free);
+            bound.push(var.text.clone());
+            collect_free(expr, bound, free);
+            for guard in guards {
+                collect_free(guard, bound, free);
+            }
+        }
+        _ => for_each_child(expr, |e| collect_free(e, bound, free)),
+    }
+    bound.truncate(depth);
+}
+
+fn pattern_vars(pattern: &Pattern, vars: &mut Vec<String>) {
+    match pattern {
+        Pattern::Var(name) => vars.push(name.text.clone()),
+        Pattern::List(items) => items.iter().for_each(|p| pattern_vars(p, vars)),
+        Pattern::Record(fields) => fields.iter().for_each(|(_, p)| pattern_vars(p, vars)),
+        Pattern::Cons { head, tail } => {
+            pattern_vars(head, vars);
+            pattern_vars(tail, vars);
+        }
+        _ => {}
+    }
+}
+
+struct Printer {
+    out: String,
+}
+
+impl Printer {
+    /// Append a token, with a space only where it would otherwise run into
+    /// the one before: two words, or a `-` that would start a comment or a
+    /// negative number.
+    fn token(&mut self, token: &str) {
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+        if let (Some(last), Some(first)) = (self.out.chars().last(), token.chars().next()) {
+            if (is_word(last) && is_word(first)) || (last == '-' && (first == '-' || first.is_ascii_digit())) {
+                self.out.push(' ');
+            }
+        }
+        self.out.push_str(token);
+    }
+
+    /// Print `expr`, in parentheses if it binds less tightly than `min`.
+    fn expr(&mut self, expr: &Expr, min: u8) {
+        if expr.precedence() < min {
+            self.token("(");
+            self.expr(expr, 0);
+            self.token(")");
+            return;
+        }
+
+        match expr {
+            Expr::Number(n) => self.token(&n.to_string()),
+            Expr::Bool(b) => self.token(&b.to_string()),
+            Expr::String(s) => self.token(&format!("\"{}\"", s)),
+            Expr::Var(name) => self.token(name),
+            Expr::Range { start, end } => self.token(&format!("{}..{}", start, end)),
+            Expr::List(items) => self.list("[", items, "]"),
+            Expr::Set(items) => self.list("#[", items, "]"),
+            Expr::Record(fields) => {
+                self.token("{");
+                self.fields(fields);
+                self.token("}");
+            }
+            Expr::Map(entries) => {
+                self.token("#{");
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        self.token(",");
+                    }
+                    self.expr(key, 0);
+                    self.token("=>");
+                    self.expr(value, 0);
+                }
+                self.token("}");
+            }
+            Expr::RecordUpdate { record, fields } => {
+                self.token("{");
+                self.expr(record, 0);
+                self.token("|");
+                self.fields(fields);
+                self.token("}");
+            }
+            Expr::Field { expr, field } => {
+                self.expr(expr, ACCESS_PRECEDENCE);
+                self.token(".");
+                self.token(field);
+            }
+            Expr::ListComp { expr, var, list, guards } => {
+                self.token("[");
+                self.expr(expr, 0);
+                self.token("|");
+                self.token(&var.text);
+                self.token("<-");
+                self.expr(list, 0);
+                for guard in guards {
+                    self.token(",");
+                    self.expr(guard, 0);
+                }
+                self.token("]");
+            }
+            Expr::Lambda { params, body } => {
+                self.token("\\");
+                self.token(&params.iter().map(|p| p.text.as_str()).collect::<Vec<_>>().join(","));
+                self.token("->");
+                self.expr(body, 0);
+            }
+            Expr::App { func, args, .. } => {
+                self.expr(func, ACCESS_PRECEDENCE);
+                for arg in args {
+                    self.expr(arg, ACCESS_PRECEDENCE);
+                }
+            }
+            Expr::Let { name, value, body } => {
+                self.token("let");
+                self.token(&name.text);
+                self.token("=");
+                self.expr(value, 0);
+                self.token("in");
+                self.expr(body, 0);
+            }
+            Expr::Match { expr, arms, .. } => {
+                self.token("match");
+                self.expr(expr, 0);
+                self.token("with");
+                for (i, arm) in arms.iter().enumerate() {
+                    if i > 0 {
+                        self.token("|");
+                    }
+                    self.pattern(&arm.pattern);
+                    self.token("->");
+                    let last = i + 1 == arms.len();
+                    let min = if !last && arm.expr.ends_in_match() { 1 } else { 0 };
+                    self.expr(&arm.expr, min);
+                }
+            }
+            Expr::If { cond, then_branch, else_branch } => {
+                self.token("if");
+                self.expr(cond, 0);
+                self.token("then");
+                self.expr(then_branch, 0);
+                self.token("else");
+                self.expr(else_branch, 0);
+            }
+            Expr::BinOp { op, left, right, .. } => {
+                let (left_min, right_min) = op.operand_precedence();
+                self.expr(left, left_min);
+                self.token(op.symbol());
+                self.expr(right, right_min);
+            }
+            Expr::UnOp { op: UnOp::Neg, expr } => {
+                self.token("-");
+                self.expr(expr, APP_PRECEDENCE);
+            }
+        }
+    }
+
+    fn list(&mut self, open: &str, items: &[Expr], close: &str) {
+        self.token(open);
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.token(",");
+            }
+            self.expr(item, 0);
+        }
+        self.token(close);
+    }
+
+    fn fields(&mut self, fields: &[(String, Expr)]) {
+        for (i, (name, value)) in fields.iter().enumerate() {
+            if i > 0 {
+                self.token(",");
+            }
+            self.token(name);
+            self.token("=");
+            self.expr(value, 0);
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Wildcard => self.token("_"),
+            Pattern::Var(name) => self.token(&name.text),
+            Pattern::Number(n) => self.token(&n.to_string()),
+            Pattern::Bool(b) => self.token(&b.to_string()),
+            Pattern::String(s) => self.token(&format!("\"{}\"", s)),
+            Pattern::List(items) => {
+                self.token("[");
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        self.token(",");
+                    }
+                    self.pattern(item);
+                }
+                self.token("]");
+            }
+            Pattern::Record(fields) => {
+                self.token("{");
+                for (i, (field, pattern)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        self.token(",");
+                    }
+                    self.token(field);
+                    if !pattern.is_var(field) {
+                        self.token("=");
+                        self.pattern(pattern);
+                    }
+                }
+                self.token("}");
+            }
+            Pattern::Cons { head, tail } => {
+                self.pattern(head);
+                self.token("::");
+                self.pattern(tail);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenges::get_all_challenges;
+    use crate::runner::{Runner, RANDOM_TESTS};
+
+    #[test]
+    fn test_minify() {
+        assert_eq!(minify("\\list -> map (\\x -> double x) list").unwrap(), "map double");
+        assert_eq!(minify("\\l -> filter (\\x -> x > 0) l -- positives").unwrap(), "filter(\\a->a>0)");
+        assert_eq!(minify("let total = sum in (total [1, 2]) * (3 + 4)").unwrap(), "let a=sum in a[1,2]*(3+4)");
+        assert_eq!(minify("\\x -> x - 2  -- two less").unwrap(), "\\a->a- 2");
+        assert_eq!(minify("(f 1) (-2) - (-3)").unwrap(), "f 1-2- -3");
+        assert_eq!(minify("\\n -> match n with 0 -> (match n with _ -> 1) | x -> x").unwrap(),
+            "\\a->match a with 0->(match a with _->1)|b->b");
+        // A name the program uses unbound can't be taken
+        assert_eq!(minify("\\b -> a b 1").unwrap(), "\\b->a b 1");
+        // Eta-reducing a recursive definition would make it loop
+        assert_eq!(minify("let loop = \\x -> loop x in loop").unwrap(), "let a=\\b->a b in a");
+        assert_eq!(minify("\\p -> match p with {age} -> age").unwrap(), "\\a->match a with{age}->age");
+    }
+
+    #[test]
+    fn test_minified_solutions_evaluate_identically() {
+        // Submitting checks random inputs against the original reference
+        let runner = Runner::new();
+        for challenge in get_all_challenges() {
+            let Some(reference) = &challenge.reference else { continue };
+            let minified = minify(&reference.solution).unwrap();
+            assert!(minified.len() <= reference.solution.len(), "{}", minified);
+
+            let mut run = runner.spawn_submission(&minified, &challenge, 0, RANDOM_TESTS);
+            run.wait();
+            for result in run.results().iter().flatten() {
+                assert!(result.passed, "challenge {}: {} fails {:?}", challenge.id, minified, result.description);
+            }
+        }
+    }
+}
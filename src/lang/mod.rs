@@ -1,4 +1,11 @@
+pub mod analysis;
 pub mod ast;
+pub mod cst;
+pub mod error;
+pub mod lexer;
+pub mod minify;
 pub mod parser;
+pub mod pretty;
+pub mod symbols;
 pub mod types;
 pub mod interpreter;
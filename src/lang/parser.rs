@@ -1,7 +1,12 @@
+use pest::error::InputLocation;
 use pest::Parser;
 use pest_derive::Parser;
 use crate::lang::ast::*;
+use crate::lang::error::SourceError;
+use crate::lang::types::{Type, GENERIC_VAR_BASE};
 use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+use std::rc::Rc;
 
 #[derive(Parser)]
 #[grammar = "lang/grammar.pest"]
@@ -9,7 +14,13 @@ pub struct LangParser;
 
 pub fn parse(input: &str) -> Result<Expr> {
     let mut pairs = LangParser::parse(Rule::program, input)
-        .map_err(|e| anyhow!("Parse error: {}", e))?;
+        .map_err(|e| {
+            let (start, end) = match e.location {
+                InputLocation::Pos(pos) => (pos, pos),
+                InputLocation::Span(span) => span,
+            };
+            SourceError { message: format!("Parse error: {}", e), span: Span::new(start, end) }
+        })?;
 
     let program = pairs.next().unwrap();
     let expr_pair = program.into_inner().next().unwrap();
@@ -27,7 +38,9 @@ fn parse_expr(pair: pest::iterators::Pair<Rule>) -> Result<Expr> {
         Rule::lambda => parse_lambda(pair),
         Rule::match_expr => parse_match(pair),
         Rule::if_expr => parse_if(pair),
-        Rule::binary_expr => parse_binary(pair),
+        Rule::apply_expr => parse_apply(pair),
+        Rule::pipe_expr => parse_pipe(pair),
+        Rule::compose_expr => parse_compose(pair),
         Rule::comp_expr => parse_comp(pair),
         Rule::logic_expr => parse_logic(pair),
         Rule::cons_expr => parse_cons(pair),
@@ -37,6 +50,7 @@ fn parse_expr(pair: pest::iterators::Pair<Rule>) -> Result<Expr> {
         Rule::pow_expr => parse_pow(pair),
         Rule::unary_expr => parse_unary(pair),
         Rule::app_expr => parse_app(pair),
+        Rule::access_expr => parse_access(pair),
         Rule::primary => parse_primary(pair),
         _ => Err(anyhow!("Unexpected rule: {:?}", pair.as_rule())),
     }
@@ -44,13 +58,13 @@ fn parse_expr(pair: pest::iterators::Pair<Rule>) -> Result<Expr> {
 
 fn parse_let(pair: pest::iterators::Pair<Rule>) -> Result<Expr> {
     let mut inner = pair.into_inner();
-    let name = inner.next().unwrap().as_str().to_string();
+    let name = name_of(&inner.next().unwrap());
     let value = parse_expr(inner.next().unwrap())?;
     let body = parse_expr(inner.next().unwrap())?;
 
     Ok(Expr::Let {
         name,
-        value: Box::new(value),
+        value: Rc::new(value),
         body: Box::new(body),
     })
 }
@@ -58,19 +72,35 @@ fn parse_let(pair: pest::iterators::Pair<Rule>) -> Result<Expr> {
 fn parse_lambda(pair: pest::iterators::Pair<Rule>) -> Result<Expr> {
     let mut inner = pair.into_inner();
     let param_list = inner.next().unwrap();
-    let params: Vec<String> = param_list
+    let params: Vec<Name> = param_list
         .into_inner()
-        .map(|p| p.as_str().to_string())
+        .map(|p| name_of(&p))
         .collect();
     let body = parse_expr(inner.next().unwrap())?;
 
     Ok(Expr::Lambda {
         params,
-        body: Box::new(body),
+        body: Rc::new(body),
     })
 }
 
+fn name_of(pair: &pest::iterators::Pair<Rule>) -> Name {
+    let span = pair.as_span();
+    Name { text: span.as_str().to_string(), span: Span::new(span.start(), span.end()) }
+}
+
+fn span_of(pair: &pest::iterators::Pair<Rule>) -> Span {
+    let start = pair.as_span().start();
+    Span::new(start, end_of(pair))
+}
+
+/// End of a pair's text, not counting whitespace the grammar skipped after it.
+fn end_of(pair: &pest::iterators::Pair<Rule>) -> usize {
+    pair.as_span().start() + pair.as_str().trim_end().len()
+}
+
 fn parse_match(pair: pest::iterators::Pair<Rule>) -> Result<Expr> {
+    let span = span_of(&pair);
     let mut inner = pair.into_inner();
     let expr = parse_expr(inner.next().unwrap())?;
     let match_arms = inner.next().unwrap();
@@ -88,6 +118,7 @@ fn parse_match(pair: pest::iterators::Pair<Rule>) -> Result<Expr> {
     Ok(Expr::Match {
         expr: Box::new(expr),
         arms: arms?,
+        span,
     })
 }
 
@@ -98,11 +129,10 @@ fn parse_pattern(pair: pest::iterators::Pair<Rule>) -> Result<Pattern> {
             parse_pattern(inner)
         }
         Rule::ident => {
-            let name = pair.as_str();
-            if name == "_" {
+            if pair.as_str() == "_" {
                 Ok(Pattern::Wildcard)
             } else {
-                Ok(Pattern::Var(name.to_string()))
+                Ok(Pattern::Var(name_of(&pair)))
             }
         }
         Rule::number => {
@@ -124,12 +154,30 @@ fn parse_pattern(pair: pest::iterators::Pair<Rule>) -> Result<Pattern> {
                 .collect();
             Ok(Pattern::List(patterns?))
         }
+        Rule::wildcard => Ok(Pattern::Wildcard),
+        Rule::record_pattern => {
+            let fields: Result<Vec<(String, Pattern)>> = pair
+                .into_inner()
+                .map(|field_pair| {
+                    let mut field_inner = field_pair.into_inner();
+                    let field = name_of(&field_inner.next().unwrap());
+                    let name = field.text.clone();
+                    // `{ age }` is shorthand for `{ age = age }`
+                    let pattern = match field_inner.next() {
+                        Some(p) => parse_pattern(p)?,
+                        None => Pattern::Var(field),
+                    };
+                    Ok((name, pattern))
+                })
+                .collect();
+            Ok(Pattern::Record(fields?))
+        }
         Rule::cons_pattern => {
             let mut inner = pair.into_inner();
-            let head_name = inner.next().unwrap().as_str().to_string();
+            let head = name_of(&inner.next().unwrap());
             let tail = parse_pattern(inner.next().unwrap())?;
             Ok(Pattern::Cons {
-                head: Box::new(Pattern::Var(head_name)),
+                head: Box::new(Pattern::Var(head)),
                 tail: Box::new(tail),
             })
         }
@@ -154,6 +202,7 @@ fn parse_binary_op<F>(pair: pest::iterators::Pair<Rule>, op_parser: F) -> Result
 where
     F: Fn(&str) -> Option<BinOp>,
 {
+    let start = pair.as_span().start();
     let mut inner = pair.into_inner();
     let mut left = parse_expr(inner.next().unwrap())?;
 
@@ -161,22 +210,55 @@ where
         let op_str = op_pair.as_str();
         let op = op_parser(op_str)
             .ok_or_else(|| anyhow!("Unknown operator: {}", op_str))?;
-        let right = parse_expr(inner.next().unwrap())?;
+        let right_pair = inner.next().unwrap();
+        let span = Span::new(start, end_of(&right_pair));
+        let right = parse_expr(right_pair)?;
 
         left = Expr::BinOp {
             op,
             left: Box::new(left),
             right: Box::new(right),
+            span,
         };
     }
 
     Ok(left)
 }
 
-fn parse_binary(pair: pest::iterators::Pair<Rule>) -> Result<Expr> {
+fn parse_apply(pair: pest::iterators::Pair<Rule>) -> Result<Expr> {
+    // `$` is right-associative: f $ g $ x == f (g x)
+    let end = end_of(&pair);
+    let operands: Result<Vec<(usize, Expr)>> = pair
+        .into_inner()
+        .filter(|p| p.as_rule() != Rule::apply_op)
+        .map(|p| Ok((p.as_span().start(), parse_expr(p)?)))
+        .collect();
+    let mut operands = operands?;
+
+    let mut right = operands.pop().unwrap().1;
+    while let Some((start, left)) = operands.pop() {
+        right = Expr::BinOp {
+            op: BinOp::Apply,
+            left: Box::new(left),
+            right: Box::new(right),
+            span: Span::new(start, end),
+        };
+    }
+
+    Ok(right)
+}
+
+fn parse_pipe(pair: pest::iterators::Pair<Rule>) -> Result<Expr> {
+    parse_binary_op(pair, |s| match s {
+        "|>" => Some(BinOp::Pipe),
+        _ => None,
+    })
+}
+
+fn parse_compose(pair: pest::iterators::Pair<Rule>) -> Result<Expr> {
     parse_binary_op(pair, |s| match s {
-        ">>" => Some(BinOp::PipeForward),
-        "<<" => Some(BinOp::PipeBackward),
+        ">>" => Some(BinOp::ComposeForward),
+        "<<" => Some(BinOp::ComposeBackward),
         _ => None,
     })
 }
@@ -256,6 +338,7 @@ fn parse_unary(pair: pest::iterators::Pair<Rule>) -> Result<Expr> {
 }
 
 fn parse_app(pair: pest::iterators::Pair<Rule>) -> Result<Expr> {
+    let span = span_of(&pair);
     let mut inner = pair.into_inner();
     let func = parse_expr(inner.next().unwrap())?;
 
@@ -268,8 +351,43 @@ fn parse_app(pair: pest::iterators::Pair<Rule>) -> Result<Expr> {
         Ok(Expr::App {
             func: Box::new(func),
             args,
+            span,
+        })
+    }
+}
+
+fn parse_access(pair: pest::iterators::Pair<Rule>) -> Result<Expr> {
+    let mut inner = pair.into_inner();
+    let mut expr = parse_expr(inner.next().unwrap())?;
+
+    for field in inner {
+        expr = Expr::Field {
+            expr: Box::new(expr),
+            field: field.as_str().to_string(),
+        };
+    }
+
+    Ok(expr)
+}
+
+fn parse_fields(pairs: pest::iterators::Pairs<Rule>) -> Result<Vec<(String, Expr)>> {
+    pairs
+        .map(|field_pair| {
+            let mut field_inner = field_pair.into_inner();
+            let name = field_inner.next().unwrap().as_str().to_string();
+            let value = parse_expr(field_inner.next().unwrap())?;
+            Ok((name, value))
         })
+        .collect()
+}
+
+fn check_duplicate_fields(fields: &[(String, Expr)]) -> Result<()> {
+    for (i, (name, _)) in fields.iter().enumerate() {
+        if fields[..i].iter().any(|(other, _)| other == name) {
+            return Err(anyhow!("Duplicate record field: {}", name));
+        }
     }
+    Ok(())
 }
 
 fn parse_primary(pair: pest::iterators::Pair<Rule>) -> Result<Expr> {
@@ -294,6 +412,37 @@ fn parse_primary(pair: pest::iterators::Pair<Rule>) -> Result<Expr> {
             let exprs: Result<Vec<Expr>> = inner.into_inner().map(parse_expr).collect();
             Ok(Expr::List(exprs?))
         }
+        Rule::record => {
+            let fields = parse_fields(inner.into_inner())?;
+            check_duplicate_fields(&fields)?;
+            Ok(Expr::Record(fields))
+        }
+        Rule::map_lit => {
+            let entries: Result<Vec<(Expr, Expr)>> = inner
+                .into_inner()
+                .map(|entry| {
+                    let mut entry_inner = entry.into_inner();
+                    let key = parse_expr(entry_inner.next().unwrap())?;
+                    let value = parse_expr(entry_inner.next().unwrap())?;
+                    Ok((key, value))
+                })
+                .collect();
+            Ok(Expr::Map(entries?))
+        }
+        Rule::set_lit => {
+            let exprs: Result<Vec<Expr>> = inner.into_inner().map(parse_expr).collect();
+            Ok(Expr::Set(exprs?))
+        }
+        Rule::record_update => {
+            let mut update_inner = inner.into_inner();
+            let record = parse_expr(update_inner.next().unwrap())?;
+            let fields = parse_fields(update_inner)?;
+            check_duplicate_fields(&fields)?;
+            Ok(Expr::RecordUpdate {
+                record: Box::new(record),
+                fields,
+            })
+        }
         Rule::range => {
             let mut range_inner = inner.into_inner();
             let start: i64 = range_inner.next().unwrap().as_str().parse()?;
@@ -303,7 +452,7 @@ fn parse_primary(pair: pest::iterators::Pair<Rule>) -> Result<Expr> {
         Rule::list_comp => {
             let mut comp_inner = inner.into_inner();
             let expr = parse_expr(comp_inner.next().unwrap())?;
-            let var = comp_inner.next().unwrap().as_str().to_string();
+            let var = name_of(&comp_inner.next().unwrap());
             let list = parse_expr(comp_inner.next().unwrap())?;
 
             let guards: Result<Vec<Expr>> = comp_inner
@@ -320,3 +469,77 @@ fn parse_primary(pair: pest::iterators::Pair<Rule>) -> Result<Expr> {
         _ => Err(anyhow!("Unexpected primary: {:?}", inner.as_rule())),
     }
 }
+
+/// Parse a type signature such as `(a -> Bool) -> [a] -> [a]`. Lowercase
+/// names are type variables, generic like the ones in builtin types.
+pub fn parse_type(input: &str) -> Result<Type> {
+    let mut pairs = LangParser::parse(Rule::type_signature, input)
+        .map_err(|e| anyhow!("Parse error: {}", e))?;
+
+    let signature = pairs.next().unwrap();
+    // Named variables come first, in order, so they print as the same letters
+    let mut vars: Vec<String> = Vec::new();
+    for var in signature.clone().into_inner().flatten().filter(|p| p.as_rule() == Rule::type_var) {
+        if !vars.iter().any(|v| v == var.as_str()) {
+            vars.push(var.as_str().to_string());
+        }
+    }
+    parse_type_expr(signature.into_inner().next().unwrap(), &mut vars)
+}
+
+/// `vars` holds the type variable names, then a blank entry for each open
+/// record's row variable.
+fn parse_type_expr(pair: pest::iterators::Pair<Rule>, vars: &mut Vec<String>) -> Result<Type> {
+    match pair.as_rule() {
+        Rule::type_expr => {
+            let mut types = pair.into_inner()
+                .map(|p| parse_type_expr(p, vars))
+                .collect::<Result<Vec<Type>>>()?;
+            let ret = types.pop().unwrap();
+            if types.is_empty() {
+                Ok(ret)
+            } else {
+                Ok(Type::Function(types, Box::new(ret)))
+            }
+        }
+        Rule::type_app | Rule::type_atom => parse_type_expr(pair.into_inner().next().unwrap(), vars),
+        Rule::map_type => {
+            let mut inner = pair.into_inner();
+            let key = parse_type_expr(inner.next().unwrap(), vars)?;
+            let value = parse_type_expr(inner.next().unwrap(), vars)?;
+            Ok(Type::Map(Box::new(key), Box::new(value)))
+        }
+        Rule::set_type => Ok(Type::Set(Box::new(parse_type_expr(pair.into_inner().next().unwrap(), vars)?))),
+        Rule::list_type => Ok(Type::List(Box::new(parse_type_expr(pair.into_inner().next().unwrap(), vars)?))),
+        Rule::record_type => {
+            let mut fields = BTreeMap::new();
+            let mut rest = None;
+            for field in pair.into_inner() {
+                if field.as_rule() == Rule::open_row {
+                    // Each open record gets a row variable of its own
+                    rest = Some(GENERIC_VAR_BASE + vars.len());
+                    vars.push(String::new());
+                    continue;
+                }
+                let mut inner = field.into_inner();
+                let name = inner.next().unwrap().as_str().to_string();
+                let ty = parse_type_expr(inner.next().unwrap(), vars)?;
+                if fields.insert(name.clone(), ty).is_some() {
+                    return Err(anyhow!("Duplicate field in record type: {}", name));
+                }
+            }
+            Ok(Type::Record(fields, rest))
+        }
+        Rule::type_name => match pair.as_str() {
+            "Int" => Ok(Type::Int),
+            "Bool" => Ok(Type::Bool),
+            "String" => Ok(Type::String),
+            name => Err(anyhow!("Unknown type: {}", name)),
+        },
+        Rule::type_var => {
+            let index = vars.iter().position(|v| v == pair.as_str()).unwrap();
+            Ok(Type::Var(GENERIC_VAR_BASE + index))
+        }
+        rule => Err(anyhow!("Unexpected type: {:?}", rule)),
+    }
+}
@@ -0,0 +1,389 @@
+use crate::lang::ast::*;
+use crate::lang::cst::Cst;
+use crate::lang::parser;
+use anyhow::Result;
+
+/// Line width code is formatted to unless there's less room.
+pub const WIDTH: usize = 80;
+
+const INDENT: usize = 2;
+
+/// Parse source code and print it back in the canonical layout, keeping
+/// its comments next to the code they were next to unless told to strip
+/// them.
+pub fn format_source(source: &str, width: usize, keep_comments: bool) -> Result<String> {
+    let formatted = pretty(&parser::parse(source)?, width);
+    if keep_comments {
+        Ok(Cst::new(source).carry_comments(&formatted))
+    } else {
+        Ok(formatted)
+    }
+}
+
+/// Print an expression as source that parses back to the same tree,
+/// breaking lines where it doesn't fit in `width` columns.
+pub fn pretty(expr: &Expr, width: usize) -> String {
+    render(&expr_doc(expr, 0), width)
+}
+
+pub fn pretty_pattern(pattern: &Pattern) -> String {
+    render(&pattern_doc(pattern), usize::MAX)
+}
+
+/// A layout to be fitted to a line width. Each group is printed on one line
+/// if it fits, and otherwise breaks at each of its own `Line`s.
+enum Doc {
+    Text(String),
+    /// A space, or a new line when its group breaks.
+    Line,
+    /// Nothing, or a new line when its group breaks.
+    SoftLine,
+    /// Indent lines that break inside it.
+    Nest(Box<Doc>),
+    Group(Box<Doc>),
+    Concat(Vec<Doc>),
+}
+
+fn text(s: impl Into<String>) -> Doc {
+    Doc::Text(s.into())
+}
+
+fn nest(docs: Vec<Doc>) -> Doc {
+    Doc::Nest(Box::new(Doc::Concat(docs)))
+}
+
+fn group(docs: Vec<Doc>) -> Doc {
+    Doc::Group(Box::new(Doc::Concat(docs)))
+}
+
+fn expr_doc(expr: &Expr, min: u8) -> Doc {
+    if expr.precedence() < min {
+        return Doc::Concat(vec![text("("), expr_doc(expr, 0), text(")")]);
+    }
+
+    match expr {
+        Expr::Number(n) => text(n.to_string()),
+        Expr::Bool(b) => text(b.to_string()),
+        Expr::String(s) => text(format!("\"{}\"", s)),
+        Expr::Var(name) => text(name),
+        Expr::Range { start, end } => text(format!("{}..{}", start, end)),
+        Expr::List(items) => brackets("[", items.iter().map(|e| expr_doc(e, 0)).collect(), "]"),
+        Expr::Set(items) => brackets("#[", items.iter().map(|e| expr_doc(e, 0)).collect(), "]"),
+        Expr::Map(entries) => {
+            let entries = entries
+                .iter()
+                .map(|(k, v)| Doc::Concat(vec![expr_doc(k, 0), text(" => "), expr_doc(v, 0)]))
+                .collect();
+            brackets("#{", entries, "}")
+        }
+        Expr::Record(fields) if fields.is_empty() => text("{}"),
+        Expr::Record(fields) => group(vec![text("{"), nest(field_docs(fields)), Doc::Line, text("}")]),
+        Expr::RecordUpdate { record, fields } => group(vec![
+            text("{ "),
+            expr_doc(record, 0),
+            text(" |"),
+            nest(field_docs(fields)),
+            Doc::Line,
+            text("}"),
+        ]),
+        Expr::Field { expr, field } => Doc::Concat(vec![expr_doc(expr, ACCESS_PRECEDENCE), text("."), text(field)]),
+        Expr::ListComp { expr, var, list, guards } => {
+            let mut inner = vec![
+                Doc::SoftLine,
+                expr_doc(expr, 0),
+                Doc::Line,
+                text(format!("| {} <- ", var)),
+                expr_doc(list, 0),
+            ];
+            for guard in guards {
+                inner.extend([text(","), Doc::Line, expr_doc(guard, 0)]);
+            }
+            group(vec![text("["), nest(inner), Doc::SoftLine, text("]")])
+        }
+        Expr::Lambda { params, body } => group(vec![
+            text(format!("\\{} ->", params.iter().map(|p| p.text.as_str()).collect::<Vec<_>>().join(", "))),
+            nest(vec![Doc::Line, expr_doc(body, 0)]),
+        ]),
+        Expr::App { func, args, .. } => {
+            let mut inner = Vec::new();
+            for arg in args {
+                inner.extend([Doc::Line, expr_doc(arg, ACCESS_PRECEDENCE)]);
+            }
+            group(vec![expr_doc(func, ACCESS_PRECEDENCE), nest(inner)])
+        }
+        Expr::Let { name, value, body } => group(vec![
+            // A function starts on the same line and indents its body
+            match **value {
+                Expr::Lambda { .. } => Doc::Concat(vec![text(format!("let {} = ", name)), expr_doc(value, 0)]),
+                _ => group(vec![text(format!("let {} =", name)), nest(vec![Doc::Line, expr_doc(value, 0)])]),
+            },
+            text(" in"),
+            Doc::Line,
+            expr_doc(body, 0),
+        ]),
+        Expr::Match { expr, arms, .. } => {
+            let mut inner = Vec::new();
+            for (i, arm) in arms.iter().enumerate() {
+                inner.push(Doc::Line);
+                if i > 0 {
+                    inner.push(text("| "));
+                }
+                // A match inside an arm would take the arms after it
+                let last = i + 1 == arms.len();
+                let min = if !last && arm.expr.ends_in_match() { 1 } else { 0 };
+                inner.push(group(vec![
+                    pattern_doc(&arm.pattern),
+                    text(" ->"),
+                    nest(vec![Doc::Line, expr_doc(&arm.expr, min)]),
+                ]));
+            }
+            group(vec![text("match "), expr_doc(expr, 0), text(" with"), nest(inner)])
+        }
+        Expr::If { cond, then_branch, else_branch } => group(vec![
+            text("if "),
+            expr_doc(cond, 0),
+            text(" then"),
+            nest(vec![Doc::Line, expr_doc(then_branch, 0)]),
+            Doc::Line,
+            text("else"),
+            nest(vec![Doc::Line, expr_doc(else_branch, 0)]),
+        ]),
+        Expr::BinOp { op, left, right, .. } => {
+            let (left_min, right_min) = op.operand_precedence();
+            group(vec![
+                expr_doc(left, left_min),
+                nest(vec![Doc::Line, text(format!("{} ", op.symbol())), expr_doc(right, right_min)]),
+            ])
+        }
+        Expr::UnOp { op: UnOp::Neg, expr } => Doc::Concat(vec![text("-"), expr_doc(expr, APP_PRECEDENCE)]),
+    }
+}
+
+/// Comma-separated items, broken one per line if they don't fit.
+fn brackets(open: &str, items: Vec<Doc>, close: &str) -> Doc {
+    if items.is_empty() {
+        return text(format!("{}{}", open, close));
+    }
+    let mut inner = vec![Doc::SoftLine];
+    for (i, item) in items.into_iter().enumerate() {
+        if i > 0 {
+            inner.extend([text(","), Doc::Line]);
+        }
+        inner.push(item);
+    }
+    group(vec![text(open), nest(inner), Doc::SoftLine, text(close)])
+}
+
+/// `name = value` for each field, each after a line break or space.
+fn field_docs(fields: &[(String, Expr)]) -> Vec<Doc> {
+    let mut docs = Vec::new();
+    for (i, (name, value)) in fields.iter().enumerate() {
+        if i > 0 {
+            docs.push(text(","));
+        }
+        docs.extend([Doc::Line, text(format!("{} = ", name)), expr_doc(value, 0)]);
+    }
+    docs
+}
+
+fn pattern_doc(pattern: &Pattern) -> Doc {
+    match pattern {
+        Pattern::Wildcard => text("_"),
+        Pattern::Var(name) => text(&name.text),
+        Pattern::Number(n) => text(n.to_string()),
+        Pattern::Bool(b) => text(b.to_string()),
+        Pattern::String(s) => text(format!("\"{}\"", s)),
+        Pattern::List(items) => brackets("[", items.iter().map(pattern_doc).collect(), "]"),
+        Pattern::Record(fields) => {
+            let mut docs = vec![text("{ ")];
+            for (i, (field, pattern)) in fields.iter().enumerate() {
+                if i > 0 {
+                    docs.push(text(", "));
+                }
+                docs.push(text(field));
+                // `{ age }` is shorthand for `{ age = age }`
+                if !pattern.is_var(field) {
+                    docs.extend([text(" = "), pattern_doc(pattern)]);
+                }
+            }
+            docs.push(text(" }"));
+            Doc::Concat(docs)
+        }
+        Pattern::Cons { head, tail } => Doc::Concat(vec![pattern_doc(head), text("::"), pattern_doc(tail)]),
+    }
+}
+
+/// Lay out a document, choosing for each group, outermost first, whether it
+/// fits on the rest of the line.
+fn render(doc: &Doc, width: usize) -> String {
+    let mut out = String::new();
+    let mut column = 0;
+    // (indent, flat, doc), next to print last
+    let mut stack = vec![(0, false, doc)];
+
+    while let Some((indent, flat, doc)) = stack.pop() {
+        match doc {
+            Doc::Text(s) => {
+                out.push_str(s);
+                column += s.chars().count();
+            }
+            Doc::Line | Doc::SoftLine if flat => {
+                if let Doc::Line = doc {
+                    out.push(' ');
+                    column += 1;
+                }
+            }
+            Doc::Line | Doc::SoftLine => {
+                out.push('\n');
+                out.push_str(&" ".repeat(indent));
+                column = indent;
+            }
+            Doc::Nest(inner) => stack.push((indent + INDENT, flat, inner)),
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|d| (indent, flat, d))),
+            Doc::Group(inner) => {
+                let fits = flat || fits(width.saturating_sub(column), inner, &stack);
+                stack.push((indent, fits, inner));
+            }
+        }
+    }
+
+    out
+}
+
+/// Whether `doc` fits in `room` columns printed flat, along with whatever
+/// follows it up to the next place the line can break.
+fn fits(room: usize, doc: &Doc, rest: &[(usize, bool, &Doc)]) -> bool {
+    let mut room = room as isize;
+    let mut pending = vec![(true, doc)];
+    let mut rest = rest.iter().rev();
+
+    loop {
+        let (flat, doc) = match pending.pop() {
+            Some(next) => next,
+            None => match rest.next() {
+                Some(&(_, flat, doc)) => (flat, doc),
+                None => return true,
+            },
+        };
+        match doc {
+            Doc::Text(s) => room -= s.chars().count() as isize,
+            Doc::Line if flat => room -= 1,
+            Doc::SoftLine if flat => {}
+            Doc::Line | Doc::SoftLine => return true,
+            Doc::Nest(inner) | Doc::Group(inner) => pending.push((flat, inner)),
+            Doc::Concat(docs) => pending.extend(docs.iter().rev().map(|d| (flat, d))),
+        }
+        if room < 0 {
+            return false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenges::get_all_challenges;
+
+    const NAMES: &[&str] = &["x", "xs", "acc", "go", "sum"];
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(format_source("\\x->x*2  -- double", WIDTH, true).unwrap(), "\\x -> x * 2 -- double");
+        assert_eq!(format_source("\\x->x*2  -- double", WIDTH, false).unwrap(), "\\x -> x * 2");
+        assert_eq!(format_source("{a=1,b=[1,2]}.a", WIDTH, true).unwrap(), "{ a = 1, b = [1, 2] }.a");
+        assert_eq!(format_source("f (-2) -2 - (a - b)", WIDTH, true).unwrap(), "f (-2) -2 - (a - b)");
+
+        let code = "let go = \\l -> match l with [] -> 0 | h::t -> h + go t in go [1, 2, 3]";
+        assert_eq!(format_source(code, WIDTH, true).unwrap(), code);
+        assert_eq!(
+            format_source(code, 30, true).unwrap(),
+            "let go = \\l ->\n  match l with\n    [] -> 0\n    | h::t -> h + go t in\ngo [1, 2, 3]"
+        );
+        assert_eq!(Expr::Var("x".to_string()).to_string(), "x");
+    }
+
+    /// A random tree of the shapes the parser produces.
+    fn random_expr(rng: &mut fastrand::Rng, depth: usize) -> Expr {
+        let name = |rng: &mut fastrand::Rng| Name::new(NAMES[rng.usize(..NAMES.len())]);
+        let children = |rng: &mut fastrand::Rng, count: usize| -> Vec<Expr> {
+            (0..count).map(|_| random_expr(rng, depth - 1)).collect()
+        };
+        if depth == 0 {
+            return match rng.usize(..4) {
+                0 => Expr::Number(rng.i64(0..100)),
+                1 => Expr::Bool(rng.bool()),
+                2 => Expr::String("a b".to_string()),
+                _ => Expr::Var(name(rng).text),
+            };
+        }
+        let boxed = |rng: &mut fastrand::Rng| Box::new(random_expr(rng, depth - 1));
+        let ops = [BinOp::Add, BinOp::Sub, BinOp::Pow, BinOp::Cons, BinOp::Lt, BinOp::And, BinOp::Pipe, BinOp::Apply];
+
+        match rng.usize(..14) {
+            0 => {
+                let count = rng.usize(..3);
+                Expr::List(children(rng, count))
+            }
+            1 => Expr::Record(vec![("a".to_string(), random_expr(rng, depth - 1)), ("b".to_string(), random_expr(rng, depth - 1))]),
+            2 => Expr::Field { expr: boxed(rng), field: "a".to_string() },
+            3 => Expr::Lambda { params: vec![name(rng), name(rng)], body: boxed(rng).into() },
+            4 | 5 => {
+                let count = rng.usize(1..3);
+                let mut args = children(rng, count);
+                // Negative numbers are only literals as arguments
+                if rng.bool() {
+                    args.push(Expr::Number(-rng.i64(1..10)));
+                }
+                Expr::App { func: boxed(rng), args, span: Span::default() }
+            }
+            6 => Expr::Let { name: name(rng), value: boxed(rng).into(), body: boxed(rng) },
+            7 => Expr::Match {
+                expr: boxed(rng),
+                arms: vec![
+                    MatchArm { pattern: Pattern::List(Vec::new()), expr: random_expr(rng, depth - 1) },
+                    MatchArm {
+                        pattern: Pattern::Cons {
+                            head: Box::new(Pattern::Var(Name::new("h"))),
+                            tail: Box::new(Pattern::Record(vec![("a".to_string(), Pattern::Var(Name::new("a")))])),
+                        },
+                        expr: random_expr(rng, depth - 1),
+                    },
+                    MatchArm { pattern: Pattern::Number(-1), expr: random_expr(rng, depth - 1) },
+                ],
+                span: Span::default(),
+            },
+            8 => Expr::If { cond: boxed(rng), then_branch: boxed(rng), else_branch: boxed(rng) },
+            9 | 10 => Expr::BinOp {
+                op: ops[rng.usize(..ops.len())],
+                left: boxed(rng),
+                right: boxed(rng),
+                span: Span::default(),
+            },
+            11 => Expr::UnOp { op: UnOp::Neg, expr: boxed(rng) },
+            12 => {
+                let count = rng.usize(..2);
+                Expr::ListComp { expr: boxed(rng), var: name(rng), list: boxed(rng), guards: children(rng, count) }
+            }
+            _ => Expr::Map(vec![(random_expr(rng, depth - 1), random_expr(rng, depth - 1))]),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = fastrand::Rng::with_seed(0);
+        for _ in 0..500 {
+            let expr = random_expr(&mut rng, 4);
+            for width in [0, 30, WIDTH] {
+                let printed = pretty(&expr, width);
+                let parsed = parser::parse(&printed).unwrap_or_else(|e| panic!("{}\n{}", printed, e));
+                assert!(parsed.same_shape(&expr), "{}", printed);
+            }
+        }
+
+        for challenge in get_all_challenges() {
+            let Some(reference) = challenge.reference else { continue };
+            let expr = parser::parse(&reference.solution).unwrap();
+            assert!(parser::parse(&expr.to_string()).unwrap().same_shape(&expr));
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use crate::lang::ast::{BindingId, Expr, Name, Pattern};
+use crate::lang::lexer::{self, Token, TokenKind};
+use std::ops::Range;
+
+/// What an identifier in the source is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Symbol {
+    /// A name being bound.
+    Binding(BindingId),
+    /// A use of a name bound in the program.
+    Reference(BindingId),
+    /// A use of a name the program doesn't bind: a builtin, or a mistake.
+    Free,
+    /// A record field.
+    Field,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Occurrence {
+    pub name: String,
+    /// Byte range in the source.
+    pub span: Range<usize>,
+    pub symbol: Symbol,
+}
+
+/// Every identifier in a program, with the binding it refers to.
+pub struct Symbols {
+    pub occurrences: Vec<Occurrence>,
+    /// The bindings in scope at each occurrence, innermost last.
+    scopes: Vec<Vec<BindingId>>,
+}
+
+impl Symbols {
+    /// Match the identifiers in `source` with the names in `expr`, its
+    /// syntax tree. Returns `None` if they don't line up, which only happens
+    /// if `expr` didn't come from `source`.
+    pub fn resolve(source: &str, expr: &Expr) -> Option<Symbols> {
+        let tokens = lexer::tokenize(source).into_iter().filter(|t| !t.is_trivia()).collect();
+        let mut resolver = Resolver { source, tokens, next: 0, scope: Vec::new(), occurrences: Vec::new(), scopes: Vec::new() };
+        resolver.expr(expr)?;
+        Some(Symbols { occurrences: resolver.occurrences, scopes: resolver.scopes })
+    }
+
+    /// The identifier at a byte offset, counting the offset just past its
+    /// end, where the cursor is after typing it.
+    pub fn at(&self, offset: usize) -> Option<&Occurrence> {
+        self.occurrences.iter().find(|o| o.span.start <= offset && offset <= o.span.end)
+    }
+
+    /// Where a name is bound.
+    pub fn binding(&self, id: BindingId) -> Option<&Occurrence> {
+        self.occurrences.iter().find(|o| o.symbol == Symbol::Binding(id))
+    }
+
+    /// The bindings that can be used where the identifier at a byte offset
+    /// is, innermost first and without the ones shadowed there.
+    pub fn in_scope(&self, offset: usize) -> Vec<&Occurrence> {
+        let Some(index) = self.occurrences.iter().position(|o| o.span.start <= offset && offset <= o.span.end) else {
+            return Vec::new();
+        };
+        let mut bindings: Vec<&Occurrence> = Vec::new();
+        for &id in self.scopes[index].iter().rev() {
+            if let Some(binding) = self.binding(id) {
+                if !bindings.iter().any(|b| b.name == binding.name) {
+                    bindings.push(binding);
+                }
+            }
+        }
+        bindings
+    }
+}
+
+/// Walks the tree in source order, taking the identifier tokens as it
+/// meets their names.
+struct Resolver<'a> {
+    source: &'a str,
+    /// Significant tokens.
+    tokens: Vec<Token>,
+    next: usize,
+    /// Names in scope, innermost last.
+    scope: Vec<(&'a str, BindingId)>,
+    occurrences: Vec<Occurrence>,
+    scopes: Vec<Vec<BindingId>>,
+}
+
+impl<'a> Resolver<'a> {
+    fn next_ident(&self) -> Option<usize> {
+        (self.next..self.tokens.len()).find(|&i| self.tokens[i].kind == TokenKind::Ident)
+    }
+
+    /// Move past the next identifier, which has to be `name`.
+    fn skip(&mut self, name: &str) -> Option<Range<usize>> {
+        let index = self.next_ident()?;
+        let token = &self.tokens[index];
+        if token.text(self.source) != name {
+            return None;
+        }
+        self.next = index + 1;
+        Some(token.span.clone())
+    }
+
+    fn take(&mut self, name: &str, symbol: Symbol) -> Option<()> {
+        let span = self.skip(name)?;
+        self.occurrences.push(Occurrence { name: name.to_string(), span, symbol });
+        self.scopes.push(self.scope.iter().map(|&(_, id)| id).collect());
+        Some(())
+    }
+
+    fn bind(&mut self, name: &'a Name) -> Option<()> {
+        let id = name.id();
+        self.take(&name.text, Symbol::Binding(id))?;
+        self.scope.push((&name.text, id));
+        Some(())
+    }
+
+    fn lookup(&self, name: &str) -> Symbol {
+        match self.scope.iter().rev().find(|(n, _)| *n == name) {
+            Some(&(_, id)) => Symbol::Reference(id),
+            None => Symbol::Free,
+        }
+    }
+
+    fn exprs(&mut self, exprs: impl IntoIterator<Item = &'a Expr>) -> Option<()> {
+        exprs.into_iter().try_for_each(|e| self.expr(e))
+    }
+
+    fn fields(&mut self, fields: &'a [(String, Expr)]) -> Option<()> {
+        for (name, value) in fields {
+            self.take(name, Symbol::Field)?;
+            self.expr(value)?;
+        }
+        Some(())
+    }
+
+    fn expr(&mut self, expr: &'a Expr) -> Option<()> {
+        let outer = self.scope.len();
+        match expr {
+            Expr::Number(_) | Expr::Bool(_) | Expr::String(_) | Expr::Range { .. } => {}
+            Expr::List(items) | Expr::Set(items) => self.exprs(items)?,
+            Expr::Record(fields) => self.fields(fields)?,
+            Expr::Map(entries) => self.exprs(entries.iter().flat_map(|(k, v)| [k, v]))?,
+            Expr::Field { expr, field } => {
+                self.expr(expr)?;
+                self.take(field, Symbol::Field)?;
+            }
+            Expr::RecordUpdate { record, fields } => {
+                self.expr(record)?;
+                self.fields(fields)?;
+            }
+            Expr::Var(name) => self.take(name, self.lookup(name))?,
+            Expr::Lambda { params, body } => {
+                for param in params {
+                    self.bind(param)?;
+                }
+                self.expr(body)?;
+            }
+            Expr::App { func, args, .. } => {
+                self.expr(func)?;
+                self.exprs(args)?;
+            }
+            Expr::Let { name, value, body } => {
+                self.bind(name)?;
+                self.expr(value)?;
+                self.expr(body)?;
+            }
+            Expr::Match { expr, arms, .. } => {
+                self.expr(expr)?;
+                for arm in arms {
+                    self.pattern(&arm.pattern)?;
+                    self.expr(&arm.expr)?;
+                    self.scope.truncate(outer);
+                }
+            }
+            Expr::If { cond, then_branch, else_branch } => {
+                self.exprs([&**cond, then_branch, else_branch])?;
+            }
+            Expr::BinOp { left, right, .. } => self.exprs([&**left, right])?,
+            Expr::UnOp { expr, .. } => self.expr(expr)?,
+            Expr::ListComp { expr, var, list, guards } => {
+                // `[expr | var <- list, guards]`: the variable is in scope
+                // before it's written, but not in the list it comes from
+                let id = var.id();
+                self.scope.push((&var.text, id));
+                self.expr(expr)?;
+                self.take(&var.text, Symbol::Binding(id))?;
+                self.scope.truncate(outer);
+                self.expr(list)?;
+                self.scope.push((&var.text, id));
+                self.exprs(guards)?;
+            }
+        }
+        self.scope.truncate(outer);
+        Some(())
+    }
+
+    fn pattern(&mut self, pattern: &'a Pattern) -> Option<()> {
+        match pattern {
+            Pattern::Number(_) | Pattern::Bool(_) | Pattern::String(_) => {}
+            // The lexer reads `_` as an identifier
+            Pattern::Wildcard => _ = self.skip("_")?,
+            Pattern::Var(name) => self.bind(name)?,
+            Pattern::List(items) => items.iter().try_for_each(|p| self.pattern(p))?,
+            Pattern::Record(fields) => {
+                for (field, pattern) in fields {
+                    // `{ age }` binds the field's own name
+                    let index = self.next_ident()?;
+                    let shorthand = self.tokens.get(index + 1).is_none_or(|t| t.text(self.source) != "=");
+                    match pattern {
+                        Pattern::Var(name) if shorthand => self.bind(name)?,
+                        _ => {
+                            self.take(field, Symbol::Field)?;
+                            self.pattern(pattern)?;
+                        }
+                    }
+                }
+            }
+            Pattern::Cons { head, tail } => {
+                self.pattern(head)?;
+                self.pattern(tail)?;
+            }
+        }
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::parser;
+    use crate::lang::types::{self, TypeChecker};
+
+    /// Each identifier, and the text of the one it refers to, if any.
+    fn resolve(source: &str) -> Vec<(&str, Option<usize>)> {
+        let expr = parser::parse(source).unwrap();
+        let symbols = Symbols::resolve(source, &expr).unwrap();
+        symbols.occurrences.iter()
+            .map(|o| {
+                let target = match o.symbol {
+                    Symbol::Binding(id) | Symbol::Reference(id) => symbols.binding(id).map(|b| b.span.start),
+                    Symbol::Free | Symbol::Field => None,
+                };
+                (&source[o.span.clone()], target)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(
+            resolve("let f = \\x -> f x in map f"),
+            vec![("f", Some(4)), ("x", Some(9)), ("f", Some(4)), ("x", Some(9)), ("map", None), ("f", Some(4))]
+        );
+        // A later binding of the same name shadows the earlier one
+        assert_eq!(
+            resolve("\\x -> match x with [] -> x | x :: xs -> x + sum xs"),
+            vec![
+                ("x", Some(1)), ("x", Some(1)), ("x", Some(1)),
+                ("x", Some(29)), ("xs", Some(34)), ("x", Some(29)), ("sum", None), ("xs", Some(34)),
+            ]
+        );
+        assert_eq!(
+            resolve("\\xs -> [y * 2 | y <- xs, y > 0]"),
+            vec![("xs", Some(1)), ("y", Some(16)), ("y", Some(16)), ("xs", Some(1)), ("y", Some(16))]
+        );
+        assert_eq!(
+            resolve("\\p -> match p with { age, name = n } -> { n = n, age = age + p.age }"),
+            vec![
+                ("p", Some(1)), ("p", Some(1)), ("age", Some(21)), ("name", None), ("n", Some(33)),
+                ("n", None), ("n", Some(33)), ("age", None), ("age", Some(21)), ("p", Some(1)), ("age", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_in_scope() {
+        let source = "let f = \\x -> let y = x in y + z in \\y -> f y";
+        let expr = parser::parse(source).unwrap();
+        let symbols = Symbols::resolve(source, &expr).unwrap();
+        let names = |offset| symbols.in_scope(offset).iter().map(|o| o.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names(32), vec!["y", "x", "f"]);
+        assert_eq!(names(45), vec!["y", "f"]);
+        assert_eq!(names(36), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_bindings_outlive_the_tree() {
+        // Bindings are identified by position, so a copy of the tree gives
+        // types to the same bindings
+        let source = "let inc = \\x -> x + 1 in inc 2";
+        let expr = parser::parse(source).unwrap();
+        let symbols = Symbols::resolve(source, &expr).unwrap();
+        let mut checker = TypeChecker::new();
+        checker.infer(&expr.clone(), &mut types::get_builtin_env()).unwrap();
+        drop(expr);
+        let types: Vec<String> = symbols.occurrences.iter()
+            .filter_map(|o| match o.symbol {
+                Symbol::Binding(id) => checker.binding_type(id).map(|ty| ty.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(types, vec!["Int -> Int", "Int"]);
+    }
+
+    #[test]
+    fn test_reference_solutions_resolve() {
+        for challenge in crate::challenges::get_all_challenges() {
+            let Some(reference) = challenge.reference else { continue };
+            let expr = parser::parse(&reference.solution).unwrap();
+            assert!(Symbols::resolve(&reference.solution, &expr).is_some(), "{}", reference.solution);
+        }
+    }
+}
@@ -1,6 +1,7 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use crate::lang::ast::*;
+use crate::lang::error::SourceError;
 use anyhow::{anyhow, Result};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -10,8 +11,12 @@ pub enum Type {
     String,
     List(Box<Type>),
     Function(Vec<Type>, Box<Type>),
+    /// Structural record type. When the second field is a row variable the
+    /// record is open: it may have more fields than the ones listed.
+    Record(BTreeMap<String, Type>, Option<usize>),
+    Map(Box<Type>, Box<Type>),
+    Set(Box<Type>),
     Var(usize),
-    Unknown,
 }
 
 impl fmt::Display for Type {
@@ -25,24 +30,66 @@ impl fmt::Display for Type {
                 if args.is_empty() {
                     write!(f, "() -> {}", ret)
                 } else {
+                    // Arrows group to the right, so only function arguments
+                    // need parentheses
                     write!(f, "{} -> {}",
                         args.iter()
-                            .map(|t| t.to_string())
+                            .map(|t| match t {
+                                Type::Function(..) => format!("({})", t),
+                                _ => t.to_string(),
+                            })
                             .collect::<Vec<_>>()
                             .join(" -> "),
                         ret
                     )
                 }
             }
+            Type::Record(fields, rest) => {
+                let mut parts: Vec<String> = fields.iter()
+                    .map(|(name, t)| format!("{}: {}", name, t))
+                    .collect();
+                if rest.is_some() {
+                    parts.push("..".to_string());
+                }
+                if parts.is_empty() {
+                    write!(f, "{{}}")
+                } else {
+                    write!(f, "{{ {} }}", parts.join(", "))
+                }
+            }
+            Type::Map(k, v) => write!(f, "Map {} {}", TypeArg(k), TypeArg(v)),
+            Type::Set(t) => write!(f, "Set {}", TypeArg(t)),
+            // Generic variables appear in builtin and declared types, where
+            // they're written as letters
+            Type::Var(n) if *n >= GENERIC_VAR_BASE && *n - GENERIC_VAR_BASE < 26 => {
+                write!(f, "{}", (b'a' + (*n - GENERIC_VAR_BASE) as u8) as char)
+            }
             Type::Var(n) => write!(f, "t{}", n),
-            Type::Unknown => write!(f, "?"),
         }
     }
 }
 
+/// Displays a type in argument position, parenthesized unless it is atomic.
+struct TypeArg<'a>(&'a Type);
+
+impl fmt::Display for TypeArg<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Type::Function(..) | Type::Map(..) | Type::Set(_) => write!(f, "({})", self.0),
+            t => write!(f, "{}", t),
+        }
+    }
+}
+
+/// Type variables at or above this index are generic: every use of a name
+/// bound to such a type gets its own fresh copy of them.
+pub const GENERIC_VAR_BASE: usize = 1_000_000;
+
 pub struct TypeChecker {
     next_var: usize,
     substitutions: HashMap<usize, Type>,
+    /// The type given to each name bound in the tree being checked.
+    bindings: HashMap<BindingId, Type>,
 }
 
 impl TypeChecker {
@@ -50,9 +97,21 @@ impl TypeChecker {
         TypeChecker {
             next_var: 0,
             substitutions: HashMap::new(),
+            bindings: HashMap::new(),
         }
     }
 
+    /// The type of a bound name, as far as it's known, even if checking
+    /// stopped at an error.
+    pub fn binding_type(&self, id: BindingId) -> Option<Type> {
+        self.bindings.get(&id).map(|ty| self.apply(ty))
+    }
+
+    fn bind(&mut self, name: &Name, ty: &Type, env: &mut HashMap<String, Type>) {
+        self.bindings.insert(name.id(), ty.clone());
+        env.insert(name.text.clone(), ty.clone());
+    }
+
     fn fresh_var(&mut self) -> Type {
         let var = Type::Var(self.next_var);
         self.next_var += 1;
@@ -69,15 +128,109 @@ impl TypeChecker {
                 }
             }
             Type::List(t) => Type::List(Box::new(self.apply(t))),
+            Type::Map(k, v) => Type::Map(Box::new(self.apply(k)), Box::new(self.apply(v))),
+            Type::Set(t) => Type::Set(Box::new(self.apply(t))),
             Type::Function(args, ret) => {
                 let args = args.iter().map(|t| self.apply(t)).collect();
                 Type::Function(args, Box::new(self.apply(ret)))
             }
+            Type::Record(fields, rest) => {
+                let mut fields: BTreeMap<String, Type> = fields.iter()
+                    .map(|(name, t)| (name.clone(), self.apply(t)))
+                    .collect();
+                let mut rest = *rest;
+                // Follow the row variable, pulling in the fields it resolved to
+                while let Some(Type::Record(more, more_rest)) = rest.and_then(|r| self.substitutions.get(&r)) {
+                    for (name, t) in more {
+                        fields.insert(name.clone(), self.apply(t));
+                    }
+                    rest = *more_rest;
+                }
+                Type::Record(fields, rest)
+            }
             _ => ty.clone(),
         }
     }
 
-    fn unify(&mut self, t1: &Type, t2: &Type) -> Result<()> {
+    fn unify_records(
+        &mut self,
+        (fields1, rest1): (&BTreeMap<String, Type>, Option<usize>),
+        (fields2, rest2): (&BTreeMap<String, Type>, Option<usize>),
+    ) -> Result<()> {
+        for (name, t1) in fields1 {
+            if let Some(t2) = fields2.get(name) {
+                self.unify(t1, t2)?;
+            }
+        }
+
+        let only1: BTreeMap<String, Type> = fields1.iter()
+            .filter(|(name, _)| !fields2.contains_key(*name))
+            .map(|(name, t)| (name.clone(), t.clone()))
+            .collect();
+        let only2: BTreeMap<String, Type> = fields2.iter()
+            .filter(|(name, _)| !fields1.contains_key(*name))
+            .map(|(name, t)| (name.clone(), t.clone()))
+            .collect();
+
+        let missing = |fields: &BTreeMap<String, Type>| {
+            anyhow!("Record has no field: {}", fields.keys().next().unwrap())
+        };
+
+        match (rest1, rest2) {
+            (None, None) => {
+                if let Some(field) = only1.keys().chain(only2.keys()).next() {
+                    return Err(anyhow!("Record has no field: {}", field));
+                }
+                Ok(())
+            }
+            (Some(r1), None) => {
+                if !only1.is_empty() {
+                    return Err(missing(&only1));
+                }
+                self.substitutions.insert(r1, Type::Record(only2, None));
+                Ok(())
+            }
+            (None, Some(r2)) => {
+                if !only2.is_empty() {
+                    return Err(missing(&only2));
+                }
+                self.substitutions.insert(r2, Type::Record(only1, None));
+                Ok(())
+            }
+            (Some(r1), Some(r2)) if r1 == r2 => {
+                if let Some(field) = only1.keys().chain(only2.keys()).next() {
+                    return Err(anyhow!("Record has no field: {}", field));
+                }
+                Ok(())
+            }
+            (Some(r1), Some(r2)) => {
+                let rest = self.next_var;
+                self.next_var += 1;
+                self.substitutions.insert(r1, Type::Record(only2, Some(rest)));
+                self.substitutions.insert(r2, Type::Record(only1, Some(rest)));
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether type variable `n` appears in `ty`, which must already have
+    /// substitutions applied.
+    fn occurs(&self, n: usize, ty: &Type) -> bool {
+        match ty {
+            Type::Var(m) => *m == n,
+            Type::List(t) | Type::Set(t) => self.occurs(n, t),
+            Type::Map(k, v) => self.occurs(n, k) || self.occurs(n, v),
+            Type::Function(args, ret) => {
+                args.iter().any(|t| self.occurs(n, t)) || self.occurs(n, ret)
+            }
+            Type::Record(fields, rest) => {
+                fields.values().any(|t| self.occurs(n, t)) || *rest == Some(n)
+            }
+            Type::Int | Type::Bool | Type::String => false,
+        }
+    }
+
+    pub fn unify(&mut self, t1: &Type, t2: &Type) -> Result<()> {
         let t1 = self.apply(t1);
         let t2 = self.apply(t2);
 
@@ -86,14 +239,25 @@ impl TypeChecker {
             (Type::Bool, Type::Bool) => Ok(()),
             (Type::String, Type::String) => Ok(()),
             (Type::List(a), Type::List(b)) => self.unify(a, b),
+            (Type::Map(k1, v1), Type::Map(k2, v2)) => {
+                self.unify(k1, k2)?;
+                self.unify(v1, v2)
+            }
+            (Type::Set(a), Type::Set(b)) => self.unify(a, b),
             (Type::Function(args1, ret1), Type::Function(args2, ret2)) => {
-                if args1.len() != args2.len() {
-                    return Err(anyhow!("Function arity mismatch"));
-                }
-                for (a1, a2) in args1.iter().zip(args2.iter()) {
+                // Functions are curried, so `a -> b -> c` unifies with
+                // `a -> (b -> c)`: match the common prefix of arguments and
+                // unify whatever is left over.
+                let n = args1.len().min(args2.len());
+                for (a1, a2) in args1[..n].iter().zip(args2[..n].iter()) {
                     self.unify(a1, a2)?;
                 }
-                self.unify(ret1, ret2)
+                let rest1 = curry(&args1[n..], ret1);
+                let rest2 = curry(&args2[n..], ret2);
+                self.unify(&rest1, &rest2)
+            }
+            (Type::Record(fields1, rest1), Type::Record(fields2, rest2)) => {
+                self.unify_records((fields1, *rest1), (fields2, *rest2))
             }
             (Type::Var(n), t) | (t, Type::Var(n)) => {
                 if let Type::Var(m) = t {
@@ -101,15 +265,33 @@ impl TypeChecker {
                         return Ok(());
                     }
                 }
+                if self.occurs(*n, t) {
+                    return Err(anyhow!("Infinite type: {} = {}", Type::Var(*n), t));
+                }
                 self.substitutions.insert(*n, t.clone());
                 Ok(())
             }
-            (Type::Unknown, _) | (_, Type::Unknown) => Ok(()),
             _ => Err(anyhow!("Type mismatch: {} vs {}", t1, t2)),
         }
     }
 
+    /// The type of `expr`. Errors are a `SourceError` where the innermost
+    /// expression with a span that contains them is known.
     pub fn infer(&mut self, expr: &Expr, env: &mut HashMap<String, Type>) -> Result<Type> {
+        self.infer_expr(expr, env).map_err(|error| {
+            let span = match expr {
+                Expr::App { span, .. } | Expr::Match { span, .. } | Expr::BinOp { span, .. } => *span,
+                _ => return error,
+            };
+            if error.is::<SourceError>() || span.is_empty() {
+                error
+            } else {
+                SourceError { message: error.to_string(), span }.into()
+            }
+        })
+    }
+
+    fn infer_expr(&mut self, expr: &Expr, env: &mut HashMap<String, Type>) -> Result<Type> {
         match expr {
             Expr::Number(_) => Ok(Type::Int),
             Expr::Bool(_) => Ok(Type::Bool),
@@ -126,23 +308,67 @@ impl TypeChecker {
                     Ok(Type::List(Box::new(self.apply(&elem_ty))))
                 }
             }
+            Expr::Record(fields) => {
+                let mut field_types = BTreeMap::new();
+                for (name, value) in fields {
+                    field_types.insert(name.clone(), self.infer(value, env)?);
+                }
+                Ok(Type::Record(field_types, None))
+            }
+            Expr::Map(entries) => {
+                let key_ty = self.fresh_var();
+                let value_ty = self.fresh_var();
+                for (key, value) in entries {
+                    let ty = self.infer(key, env)?;
+                    self.unify(&key_ty, &ty)?;
+                    let ty = self.infer(value, env)?;
+                    self.unify(&value_ty, &ty)?;
+                }
+                Ok(self.apply(&Type::Map(Box::new(key_ty), Box::new(value_ty))))
+            }
+            Expr::Set(items) => {
+                let elem_ty = self.fresh_var();
+                for item in items {
+                    let ty = self.infer(item, env)?;
+                    self.unify(&elem_ty, &ty)?;
+                }
+                Ok(Type::Set(Box::new(self.apply(&elem_ty))))
+            }
+            Expr::Field { expr, field } => {
+                let record_ty = self.infer(expr, env)?;
+                let field_ty = self.fresh_var();
+                let expected = self.open_record(vec![(field.clone(), field_ty.clone())]);
+                self.unify(&record_ty, &expected)?;
+                Ok(self.apply(&field_ty))
+            }
+            Expr::RecordUpdate { record, fields } => {
+                let record_ty = self.infer(record, env)?;
+                let mut field_types = Vec::new();
+                for (name, value) in fields {
+                    field_types.push((name.clone(), self.infer(value, env)?));
+                }
+                let expected = self.open_record(field_types);
+                self.unify(&record_ty, &expected)?;
+                Ok(self.apply(&record_ty))
+            }
             Expr::Var(name) => {
-                env.get(name)
+                let ty = env.get(name)
                     .cloned()
-                    .ok_or_else(|| anyhow!("Undefined variable: {}", name))
+                    .ok_or_else(|| anyhow!("Undefined variable: {}", name))?;
+                Ok(self.instantiate(&ty, &mut HashMap::new()))
             }
             Expr::Lambda { params, body } => {
                 let param_types: Vec<Type> = params.iter().map(|_| self.fresh_var()).collect();
 
                 let mut new_env = env.clone();
                 for (param, ty) in params.iter().zip(param_types.iter()) {
-                    new_env.insert(param.clone(), ty.clone());
+                    self.bind(param, ty, &mut new_env);
                 }
 
                 let ret_ty = self.infer(body, &mut new_env)?;
-                Ok(Type::Function(param_types, Box::new(ret_ty)))
+                Ok(self.apply(&Type::Function(param_types, Box::new(ret_ty))))
             }
-            Expr::App { func, args } => {
+            Expr::App { func, args, .. } => {
                 let func_ty = self.infer(func, env)?;
                 let arg_types: Result<Vec<Type>> = args.iter().map(|arg| self.infer(arg, env)).collect();
                 let arg_types = arg_types?;
@@ -154,9 +380,16 @@ impl TypeChecker {
                 Ok(self.apply(&ret_ty))
             }
             Expr::Let { name, value, body } => {
-                let value_ty = self.infer(value, env)?;
+                // Bind the name first so the value can refer to itself
+                let self_ty = self.fresh_var();
+                let mut value_env = env.clone();
+                self.bind(name, &self_ty, &mut value_env);
+                let value_ty = self.infer(value, &mut value_env)?;
+                self.unify(&self_ty, &value_ty)?;
+                let value_ty = self.apply(&value_ty);
+
                 let mut new_env = env.clone();
-                new_env.insert(name.clone(), value_ty);
+                new_env.insert(name.text.clone(), value_ty);
                 self.infer(body, &mut new_env)
             }
             Expr::If { cond, then_branch, else_branch } => {
@@ -169,7 +402,7 @@ impl TypeChecker {
 
                 Ok(self.apply(&then_ty))
             }
-            Expr::BinOp { op, left, right } => {
+            Expr::BinOp { op, left, right, .. } => {
                 let left_ty = self.infer(left, env)?;
                 let right_ty = self.infer(right, env)?;
 
@@ -202,15 +435,29 @@ impl TypeChecker {
                         self.unify(&right_ty, &list_ty)?;
                         Ok(self.apply(&list_ty))
                     }
-                    BinOp::PipeForward => {
-                        // left >> right means right(left)
+                    BinOp::ComposeForward | BinOp::ComposeBackward => {
+                        // f >> g :: a -> c  where f :: a -> b, g :: b -> c
+                        let (first_ty, second_ty) = if *op == BinOp::ComposeForward {
+                            (&left_ty, &right_ty)
+                        } else {
+                            (&right_ty, &left_ty)
+                        };
+                        let a = self.fresh_var();
+                        let b = self.fresh_var();
+                        let c = self.fresh_var();
+                        self.unify(first_ty, &Type::Function(vec![a.clone()], Box::new(b.clone())))?;
+                        self.unify(second_ty, &Type::Function(vec![b], Box::new(c.clone())))?;
+                        Ok(self.apply(&Type::Function(vec![a], Box::new(c))))
+                    }
+                    BinOp::Pipe => {
+                        // x |> f means f x
                         let ret_ty = self.fresh_var();
                         let func_ty = Type::Function(vec![left_ty.clone()], Box::new(ret_ty.clone()));
                         self.unify(&right_ty, &func_ty)?;
                         Ok(self.apply(&ret_ty))
                     }
-                    BinOp::PipeBackward => {
-                        // left << right means left(right)
+                    BinOp::Apply => {
+                        // f $ x means f x
                         let ret_ty = self.fresh_var();
                         let func_ty = Type::Function(vec![right_ty.clone()], Box::new(ret_ty.clone()));
                         self.unify(&left_ty, &func_ty)?;
@@ -230,7 +477,7 @@ impl TypeChecker {
                 self.unify(&list_ty, &Type::List(Box::new(elem_ty.clone())))?;
 
                 let mut new_env = env.clone();
-                new_env.insert(var.clone(), self.apply(&elem_ty));
+                self.bind(var, &self.apply(&elem_ty), &mut new_env);
 
                 for guard in guards {
                     let guard_ty = self.infer(guard, &mut new_env)?;
@@ -240,7 +487,7 @@ impl TypeChecker {
                 let result_elem_ty = self.infer(expr, &mut new_env)?;
                 Ok(Type::List(Box::new(result_elem_ty)))
             }
-            Expr::Match { expr, arms } => {
+            Expr::Match { expr, arms, .. } => {
                 let expr_ty = self.infer(expr, env)?;
 
                 if arms.is_empty() {
@@ -266,11 +513,62 @@ impl TypeChecker {
         }
     }
 
+    /// A record type with at least the given fields.
+    fn open_record(&mut self, fields: Vec<(String, Type)>) -> Type {
+        let rest = self.next_var;
+        self.next_var += 1;
+        Type::Record(fields.into_iter().collect(), Some(rest))
+    }
+
+    /// Unify `ty` with a declared signature, giving the signature's type
+    /// variables fresh copies first.
+    pub fn check_signature(&mut self, ty: &Type, signature: &Type) -> Result<()> {
+        let expected = self.instantiate(signature, &mut HashMap::new());
+        self.unify(ty, &expected)
+    }
+
+    /// Replace generic type variables with fresh ones, consistently within
+    /// a single type.
+    fn instantiate(&mut self, ty: &Type, mapping: &mut HashMap<usize, Type>) -> Type {
+        match ty {
+            Type::Var(n) if *n >= GENERIC_VAR_BASE => mapping
+                .entry(*n)
+                .or_insert_with(|| {
+                    let var = Type::Var(self.next_var);
+                    self.next_var += 1;
+                    var
+                })
+                .clone(),
+            Type::List(t) => Type::List(Box::new(self.instantiate(t, mapping))),
+            Type::Map(k, v) => Type::Map(
+                Box::new(self.instantiate(k, mapping)),
+                Box::new(self.instantiate(v, mapping)),
+            ),
+            Type::Set(t) => Type::Set(Box::new(self.instantiate(t, mapping))),
+            Type::Record(fields, rest) => {
+                let fields = fields.iter()
+                    .map(|(name, t)| (name.clone(), self.instantiate(t, mapping)))
+                    .collect();
+                // The row variable is generic too, so each use gets its own
+                let rest = rest.map(|r| match self.instantiate(&Type::Var(r), mapping) {
+                    Type::Var(fresh) => fresh,
+                    _ => r,
+                });
+                Type::Record(fields, rest)
+            }
+            Type::Function(args, ret) => {
+                let args = args.iter().map(|t| self.instantiate(t, mapping)).collect();
+                Type::Function(args, Box::new(self.instantiate(ret, mapping)))
+            }
+            _ => ty.clone(),
+        }
+    }
+
     fn check_pattern(&mut self, pattern: &Pattern, ty: &Type, env: &mut HashMap<String, Type>) -> Result<()> {
         match pattern {
             Pattern::Wildcard => Ok(()),
             Pattern::Var(name) => {
-                env.insert(name.clone(), ty.clone());
+                self.bind(name, ty, env);
                 Ok(())
             }
             Pattern::Number(_) => self.unify(ty, &Type::Int),
@@ -284,6 +582,17 @@ impl TypeChecker {
                 }
                 Ok(())
             }
+            Pattern::Record(fields) => {
+                let field_types: Vec<(String, Type)> = fields.iter()
+                    .map(|(name, _)| (name.clone(), self.fresh_var()))
+                    .collect();
+                let expected = self.open_record(field_types.clone());
+                self.unify(ty, &expected)?;
+                for ((_, p), (_, field_ty)) in fields.iter().zip(field_types.iter()) {
+                    self.check_pattern(p, &self.apply(field_ty), env)?;
+                }
+                Ok(())
+            }
             Pattern::Cons { head, tail } => {
                 let elem_ty = self.fresh_var();
                 let list_ty = Type::List(Box::new(elem_ty.clone()));
@@ -297,11 +606,50 @@ impl TypeChecker {
     }
 }
 
+/// Build `args -> ret`, or just `ret` when there are no arguments left.
+fn curry(args: &[Type], ret: &Type) -> Type {
+    if args.is_empty() {
+        ret.clone()
+    } else {
+        Type::Function(args.to_vec(), Box::new(ret.clone()))
+    }
+}
+
+/// Rename the type variables of an inferred type to generic ones, numbered
+/// in order of appearance, so it prints as `a -> a` rather than `t7 -> t7`.
+/// Record row variables are left alone, as they don't print.
+pub fn generalize(ty: &Type) -> Type {
+    fn rename(ty: &Type, names: &mut Vec<usize>) -> Type {
+        match ty {
+            Type::Var(n) => {
+                let index = names.iter().position(|m| m == n).unwrap_or_else(|| {
+                    names.push(*n);
+                    names.len() - 1
+                });
+                Type::Var(GENERIC_VAR_BASE + index)
+            }
+            Type::List(t) => Type::List(Box::new(rename(t, names))),
+            Type::Set(t) => Type::Set(Box::new(rename(t, names))),
+            Type::Map(k, v) => Type::Map(Box::new(rename(k, names)), Box::new(rename(v, names))),
+            Type::Function(args, ret) => {
+                let args = args.iter().map(|t| rename(t, names)).collect();
+                Type::Function(args, Box::new(rename(ret, names)))
+            }
+            Type::Record(fields, rest) => {
+                let fields = fields.iter().map(|(name, t)| (name.clone(), rename(t, names))).collect();
+                Type::Record(fields, *rest)
+            }
+            Type::Int | Type::Bool | Type::String => ty.clone(),
+        }
+    }
+    rename(ty, &mut Vec::new())
+}
+
 pub fn get_builtin_env() -> HashMap<String, Type> {
     let mut env = HashMap::new();
 
-    let a = Type::Var(1000);
-    let b = Type::Var(1001);
+    let a = Type::Var(GENERIC_VAR_BASE);
+    let b = Type::Var(GENERIC_VAR_BASE + 1);
 
     // map :: (a -> b) -> [a] -> [b]
     env.insert("map".to_string(),
@@ -325,8 +673,8 @@ pub fn get_builtin_env() -> HashMap<String, Type> {
         )
     );
 
-    // fold/foldl/foldr :: (b -> a -> b) -> b -> [a] -> b
-    for name in &["fold", "foldl", "foldr"] {
+    // fold/foldl :: (b -> a -> b) -> b -> [a] -> b
+    for name in &["fold", "foldl"] {
         env.insert(name.to_string(),
             Type::Function(
                 vec![
@@ -339,14 +687,27 @@ pub fn get_builtin_env() -> HashMap<String, Type> {
         );
     }
 
-    // zip :: [a] -> [b] -> [(a, b)]
+    // foldr :: (a -> b -> b) -> b -> [a] -> b
+    env.insert("foldr".to_string(),
+        Type::Function(
+            vec![
+                Type::Function(vec![a.clone(), b.clone()], Box::new(b.clone())),
+                b.clone(),
+                Type::List(Box::new(a.clone()))
+            ],
+            Box::new(b.clone())
+        )
+    );
+
+    // zip :: [a] -> [a] -> [[a]]
+    // There are no tuples, so each pair is a two-element list
     env.insert("zip".to_string(),
         Type::Function(
             vec![
                 Type::List(Box::new(a.clone())),
-                Type::List(Box::new(b.clone()))
+                Type::List(Box::new(a.clone()))
             ],
-            Box::new(Type::List(Box::new(a.clone()))) // Simplified - we don't have tuples
+            Box::new(Type::List(Box::new(Type::List(Box::new(a.clone())))))
         )
     );
 
@@ -438,5 +799,106 @@ pub fn get_builtin_env() -> HashMap<String, Type> {
         )
     );
 
+    let map_ab = Type::Map(Box::new(a.clone()), Box::new(b.clone()));
+    let mut entry_fields = BTreeMap::new();
+    entry_fields.insert("key".to_string(), a.clone());
+    entry_fields.insert("value".to_string(), b.clone());
+    let entry = Type::Record(entry_fields, None);
+
+    // fromList :: [{ key: a, value: b }] -> Map a b
+    env.insert("fromList".to_string(),
+        Type::Function(
+            vec![Type::List(Box::new(entry.clone()))],
+            Box::new(map_ab.clone())
+        )
+    );
+
+    // toList :: Map a b -> [{ key: a, value: b }]
+    env.insert("toList".to_string(),
+        Type::Function(
+            vec![map_ab.clone()],
+            Box::new(Type::List(Box::new(entry)))
+        )
+    );
+
+    // insert :: a -> b -> Map a b -> Map a b
+    env.insert("insert".to_string(),
+        Type::Function(
+            vec![a.clone(), b.clone(), map_ab.clone()],
+            Box::new(map_ab.clone())
+        )
+    );
+
+    // insertWith :: (b -> b -> b) -> a -> b -> Map a b -> Map a b
+    env.insert("insertWith".to_string(),
+        Type::Function(
+            vec![
+                Type::Function(vec![b.clone(), b.clone()], Box::new(b.clone())),
+                a.clone(),
+                b.clone(),
+                map_ab.clone()
+            ],
+            Box::new(map_ab.clone())
+        )
+    );
+
+    // lookup :: a -> Map a b -> b
+    env.insert("lookup".to_string(),
+        Type::Function(
+            vec![a.clone(), map_ab.clone()],
+            Box::new(b.clone())
+        )
+    );
+
+    // keys :: Map a b -> [a]
+    env.insert("keys".to_string(),
+        Type::Function(
+            vec![map_ab.clone()],
+            Box::new(Type::List(Box::new(a.clone())))
+        )
+    );
+
+    // values :: Map a b -> [b]
+    env.insert("values".to_string(),
+        Type::Function(
+            vec![map_ab],
+            Box::new(Type::List(Box::new(b.clone())))
+        )
+    );
+
+    let set_a = Type::Set(Box::new(a.clone()));
+
+    // set :: [a] -> Set a
+    env.insert("set".to_string(),
+        Type::Function(
+            vec![Type::List(Box::new(a.clone()))],
+            Box::new(set_a.clone())
+        )
+    );
+
+    // add :: a -> Set a -> Set a
+    env.insert("add".to_string(),
+        Type::Function(
+            vec![a.clone(), set_a.clone()],
+            Box::new(set_a.clone())
+        )
+    );
+
+    // member :: a -> Set a -> Bool
+    env.insert("member".to_string(),
+        Type::Function(
+            vec![a.clone(), set_a.clone()],
+            Box::new(Type::Bool)
+        )
+    );
+
+    // elems :: Set a -> [a]
+    env.insert("elems".to_string(),
+        Type::Function(
+            vec![set_a],
+            Box::new(Type::List(Box::new(a.clone())))
+        )
+    );
+
     env
 }
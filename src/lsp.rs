@@ -0,0 +1,378 @@
+use crate::challenges::{get_all_challenges, Challenge};
+use crate::lang::analysis::Analysis;
+use crate::lang::ast::Span;
+use crate::lang::cst::Cst;
+use crate::lang::types::{self, Type};
+use crate::runner::Runner;
+use crate::score::Metric;
+use anyhow::Result;
+use lsp_server::{Connection, Message, Notification, Request, Response};
+use lsp_types::notification::{self as notif, Notification as _};
+use lsp_types::request::{self as req, Request as _};
+use lsp_types::{
+    CodeAction, CodeActionOrCommand, CodeActionParams, CodeActionProviderCapability, Command, CompletionItem,
+    CompletionItemKind, CompletionOptions, Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams,
+    DidCloseTextDocumentParams, DidOpenTextDocumentParams, ExecuteCommandOptions, ExecuteCommandParams,
+    GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability,
+    InlayHint, InlayHintLabel, InlayHintParams, Location, MarkupContent, MarkupKind, MessageType, OneOf, Position,
+    PublishDiagnosticsParams, Range, ServerCapabilities, ShowMessageParams, TextDocumentSyncCapability,
+    TextDocumentSyncKind, Url,
+};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::thread;
+
+/// The command behind the "Run challenge tests" code action.
+const RUN_TESTS: &str = "less.runTests";
+
+/// `less lsp`: a language server on standard input and output, for editing
+/// solutions in any editor with LSP support. A file is tied to a challenge
+/// by a `-- challenge: <id>` comment.
+pub fn run() -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    connection.initialize(serde_json::to_value(capabilities())?)?;
+    // Serving takes the connection, which has to be closed before the
+    // threads writing to it can finish
+    Server { connection, documents: HashMap::new(), challenges: get_all_challenges() }.serve()?;
+    io_threads.join()?;
+    Ok(())
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions::default()),
+        inlay_hint_provider: Some(OneOf::Left(true)),
+        code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+        execute_command_provider: Some(ExecuteCommandOptions {
+            commands: vec![RUN_TESTS.to_string()],
+            work_done_progress_options: Default::default(),
+        }),
+        ..Default::default()
+    }
+}
+
+struct Server {
+    connection: Connection,
+    /// The text of each open file.
+    documents: HashMap<Url, String>,
+    challenges: Vec<Challenge>,
+}
+
+impl Server {
+    fn serve(mut self) -> Result<()> {
+        while let Ok(message) = self.connection.receiver.recv() {
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request)? {
+                        break;
+                    }
+                    let id = request.id.clone();
+                    let response = match self.handle(request) {
+                        Ok(result) => Response::new_ok(id, result),
+                        Err(e) => Response::new_err(id, lsp_server::ErrorCode::InvalidParams as i32, e.to_string()),
+                    };
+                    self.connection.sender.send(Message::Response(response))?;
+                }
+                Message::Notification(notification) => self.notify(notification)?,
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&self, request: Request) -> Result<Value> {
+        let params = request.params;
+        let result = match request.method.as_str() {
+            req::HoverRequest::METHOD => {
+                let params: HoverParams = serde_json::from_value(params)?;
+                let position = params.text_document_position_params;
+                self.document(&position.text_document.uri)
+                    .and_then(|source| hover(&self.analyse(source), position.position))
+                    .map(serde_json::to_value)
+                    .transpose()?
+            }
+            req::GotoDefinition::METHOD => {
+                let params: GotoDefinitionParams = serde_json::from_value(params)?;
+                let position = params.text_document_position_params;
+                let uri = position.text_document.uri;
+                self.document(&uri)
+                    .and_then(|source| {
+                        let span = Analysis::new(source, None).definition(offset(source, position.position))?;
+                        Some(range(source, Span::new(span.start, span.end)))
+                    })
+                    .map(|range| GotoDefinitionResponse::Scalar(Location { uri: uri.clone(), range }))
+                    .map(serde_json::to_value)
+                    .transpose()?
+            }
+            req::Completion::METHOD => Some(serde_json::to_value(completions())?),
+            req::InlayHintRequest::METHOD => {
+                let params: InlayHintParams = serde_json::from_value(params)?;
+                let hints: Vec<InlayHint> = self.document(&params.text_document.uri)
+                    .map(|source| score_hint(source, self.challenge(source)))
+                    .into_iter()
+                    .collect();
+                Some(serde_json::to_value(hints)?)
+            }
+            req::CodeActionRequest::METHOD => {
+                let params: CodeActionParams = serde_json::from_value(params)?;
+                let uri = params.text_document.uri;
+                let actions: Vec<CodeActionOrCommand> = self.document(&uri)
+                    .and_then(|source| self.challenge(source))
+                    .map(|challenge| run_tests_action(&uri, challenge))
+                    .into_iter()
+                    .collect();
+                Some(serde_json::to_value(actions)?)
+            }
+            req::ExecuteCommand::METHOD => {
+                let params: ExecuteCommandParams = serde_json::from_value(params)?;
+                if params.command == RUN_TESTS {
+                    let uri: Url = serde_json::from_value(params.arguments.into_iter().next().unwrap_or_default())?;
+                    match self.document(&uri).map(|source| (source, self.challenge(source))) {
+                        Some((source, Some(challenge))) => {
+                            // The tests can take seconds, which other requests
+                            // shouldn't have to wait for
+                            let (source, challenge) = (source.to_string(), challenge.clone());
+                            let sender = self.connection.sender.clone();
+                            thread::spawn(move || {
+                                let message = run_tests(&source, &challenge);
+                                let notification = Notification::new(notif::ShowMessage::METHOD.to_string(), message);
+                                _ = sender.send(Message::Notification(notification));
+                            });
+                        }
+                        _ => self.send(notif::ShowMessage::METHOD, ShowMessageParams {
+                            typ: MessageType::ERROR,
+                            message: "Add a `-- challenge: <id>` comment to choose the challenge".to_string(),
+                        })?,
+                    }
+                }
+                None
+            }
+            _ => None,
+        };
+        Ok(result.unwrap_or(Value::Null))
+    }
+
+    fn notify(&mut self, notification: Notification) -> Result<()> {
+        let params = notification.params;
+        match notification.method.as_str() {
+            notif::DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams = serde_json::from_value(params)?;
+                self.update(params.text_document.uri, params.text_document.text)?;
+            }
+            notif::DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams = serde_json::from_value(params)?;
+                // With full sync, the last change is the whole text
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.update(params.text_document.uri, change.text)?;
+                }
+            }
+            notif::DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams = serde_json::from_value(params)?;
+                self.documents.remove(&params.text_document.uri);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn update(&mut self, uri: Url, source: String) -> Result<()> {
+        let diagnostics = diagnostics(&self.analyse(&source));
+        self.documents.insert(uri.clone(), source);
+        self.send(notif::PublishDiagnostics::METHOD, PublishDiagnosticsParams { uri, diagnostics, version: None })
+    }
+
+    fn send(&self, method: &str, params: impl serde::Serialize) -> Result<()> {
+        let notification = Notification::new(method.to_string(), params);
+        self.connection.sender.send(Message::Notification(notification))?;
+        Ok(())
+    }
+
+    fn document(&self, uri: &Url) -> Option<&str> {
+        self.documents.get(uri).map(String::as_str)
+    }
+
+    fn challenge(&self, source: &str) -> Option<&Challenge> {
+        let id = challenge_id(source)?;
+        self.challenges.iter().find(|c| c.id == id)
+    }
+
+    /// Check a file, against its challenge's type signature if it has one.
+    fn analyse(&self, source: &str) -> Analysis {
+        let signature = self.challenge(source).and_then(Challenge::signature);
+        Analysis::new(source, signature.as_ref())
+    }
+}
+
+/// The id in a `-- challenge: <id>` comment.
+fn challenge_id(source: &str) -> Option<usize> {
+    Cst::new(source).comments().iter().find_map(|comment| {
+        let text = comment.text.trim_start_matches('-').trim();
+        text.strip_prefix("challenge:")?.trim().parse().ok()
+    })
+}
+
+fn diagnostics(analysis: &Analysis) -> Vec<Diagnostic> {
+    let source = analysis.source();
+    analysis.diagnostics().iter()
+        .map(|diagnostic| Diagnostic {
+            range: range(source, diagnostic.span.unwrap_or(Span::new(0, source.len()))),
+            severity: Some(DiagnosticSeverity::ERROR),
+            source: Some("less".to_string()),
+            message: diagnostic.message.clone(),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// The name under the cursor and its type.
+fn hover(analysis: &Analysis, position: Position) -> Option<Hover> {
+    let source = analysis.source();
+    let (occurrence, ty) = analysis.type_at(offset(source, position))?;
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!("```\n{} : {}\n```", occurrence.name, ty),
+        }),
+        range: Some(range(source, Span::new(occurrence.span.start, occurrence.span.end))),
+    })
+}
+
+/// Every builtin, with its type.
+fn completions() -> Vec<CompletionItem> {
+    let mut builtins: Vec<(String, Type)> = types::get_builtin_env().into_iter().collect();
+    builtins.sort_by(|a, b| a.0.cmp(&b.0));
+    builtins.into_iter()
+        .map(|(name, ty)| CompletionItem {
+            label: name,
+            kind: Some(if matches!(ty, Type::Function(..)) { CompletionItemKind::FUNCTION } else { CompletionItemKind::CONSTANT }),
+            detail: Some(ty.to_string()),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// The score so far, at the end of the code, in the challenge's metric.
+fn score_hint(source: &str, challenge: Option<&Challenge>) -> InlayHint {
+    let metric = challenge.map_or(Metric::Chars, |c| c.metric);
+    let score = metric.measure(source).map_or("-".to_string(), |n| n.to_string());
+    let label = match challenge {
+        Some(challenge) => format!("{} {} (par {})", score, metric.unit(), challenge.par_score),
+        None => format!("{} {}", score, metric.unit()),
+    };
+    InlayHint {
+        position: position(source, source.trim_end().len()),
+        label: InlayHintLabel::String(label),
+        kind: None,
+        text_edits: None,
+        tooltip: None,
+        padding_left: Some(true),
+        padding_right: None,
+        data: None,
+    }
+}
+
+fn run_tests_action(uri: &Url, challenge: &Challenge) -> CodeActionOrCommand {
+    let title = format!("Run tests for challenge {}: {}", challenge.id, challenge.name);
+    CodeActionOrCommand::CodeAction(CodeAction {
+        title: title.clone(),
+        command: Some(Command { title, command: RUN_TESTS.to_string(), arguments: Some(vec![Value::from(uri.as_str())]) }),
+        ..Default::default()
+    })
+}
+
+/// Run the challenge's visible tests, and describe the first failure.
+fn run_tests(source: &str, challenge: &Challenge) -> ShowMessageParams {
+    let mut run = Runner::new().spawn_tests(source, &challenge.test_cases, challenge.signature().as_ref());
+    run.wait();
+    let results: Vec<_> = run.results().iter().flatten().collect();
+    let passed = results.iter().filter(|r| r.passed).count();
+    let mut message = format!("{}: {}/{} tests passed", challenge.name, passed, challenge.test_cases.len());
+    if let Some(failed) = results.iter().find(|r| !r.passed) {
+        match &failed.error {
+            Some(error) => message.push_str(&format!(". {}: {}", failed.description, error)),
+            None => message.push_str(&format!(
+                ". {}: expected {}, got {}",
+                failed.description, failed.expected, failed.actual
+            )),
+        }
+    }
+    let typ = if passed == challenge.test_cases.len() { MessageType::INFO } else { MessageType::WARNING };
+    ShowMessageParams { typ, message }
+}
+
+/// LSP positions count UTF-16 code units along the line.
+fn position(source: &str, offset: usize) -> Position {
+    let before = &source[..offset.min(source.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Position {
+        line: before.matches('\n').count() as u32,
+        character: before[line_start..].encode_utf16().count() as u32,
+    }
+}
+
+fn offset(source: &str, position: Position) -> usize {
+    let line_start: usize = source.split_inclusive('\n').take(position.line as usize).map(str::len).sum();
+    let line = source[line_start..].split('\n').next().unwrap_or("");
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= position.character as usize {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    line_start + line.len()
+}
+
+fn range(source: &str, span: Span) -> Range {
+    Range { start: position(source, span.start), end: position(source, span.end) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(line: u32, character: u32) -> Position {
+        Position { line, character }
+    }
+
+    #[test]
+    fn test_analysis() {
+        let source = "let double = \\x -> x * 2\nin map double";
+        let analysis = Analysis::new(source, None);
+        let value = |hover: Hover| match hover.contents {
+            HoverContents::Markup(content) => content.value,
+            _ => unreachable!(),
+        };
+        assert_eq!(value(hover(&analysis, at(1, 8)).unwrap()), "```\ndouble : Int -> Int\n```");
+        assert_eq!(value(hover(&analysis, at(1, 4)).unwrap()), "```\nmap : (a -> b) -> [a] -> [b]\n```");
+
+        assert!(diagnostics(&analysis).is_empty());
+        let undefined = diagnostics(&Analysis::new("\\x ->\n  x + y", None));
+        assert_eq!(undefined[0].message, "Undefined variable: y");
+        assert_eq!(undefined[0].range, Range { start: at(1, 6), end: at(1, 7) });
+        let parse = diagnostics(&Analysis::new("let x = in x", None));
+        assert_eq!(parse[0].range.start, at(0, 8));
+    }
+
+    #[test]
+    fn test_challenge_comment() {
+        let source = "-- challenge: 3\nsum";
+        assert_eq!(challenge_id(source), Some(3));
+        assert_eq!(challenge_id("sum"), None);
+        let hint = score_hint(source, None);
+        assert!(matches!(hint.label, InlayHintLabel::String(label) if label == "3 chars"));
+        assert_eq!(hint.position, at(1, 3));
+    }
+
+    #[test]
+    fn test_positions() {
+        let source = "\"é😀\" ++\n  x";
+        for offset_ in [0, 1, 3, 7, 11, 13] {
+            assert_eq!(offset(source, position(source, offset_)), offset_);
+        }
+        assert_eq!(position(source, 7), at(0, 4));
+    }
+}
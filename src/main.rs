@@ -1,15 +1,55 @@
 mod lang;
 mod challenges;
+mod config;
+mod crash;
+mod diff;
+mod lsp;
 mod storage;
 mod runner;
+mod score;
 mod ui;
 
-use challenges::get_all_challenges;
+use challenges::{get_all_challenges, load_pack, validate::validate, Challenge};
+use score::{Metric, Scores};
 use storage::{Solution, Storage};
-use ui::{editor::{Editor, EditorResult}, menu::{Menu, MenuAction}, repl::Repl, reference::Reference};
+use ui::{buffer::Buffer, editor::{Editor, EditorResult}, keys::Action, menu::{Menu, MenuAction}, repl::Repl, reference::Reference};
+use std::collections::HashMap;
 use std::io;
+use std::path::Path;
+use std::process;
 
 fn main() -> io::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("validate-challenges") {
+        validate_challenges(args.get(1).map(String::as_str));
+    }
+    if args.first().map(String::as_str) == Some("minify") {
+        print_rewritten(args.get(1).map(String::as_str), lang::minify::minify);
+    }
+    if args.first().map(String::as_str) == Some("fmt") {
+        let keep_comments = !args.iter().any(|a| a == "--strip-comments");
+        let path = args[1..].iter().find(|a| *a != "--strip-comments");
+        print_rewritten(path.map(String::as_str), |source| {
+            lang::pretty::format_source(source, lang::pretty::WIDTH, keep_comments)
+        });
+    }
+
+    if args.first().map(String::as_str) == Some("lsp") {
+        if let Err(e) = lsp::run() {
+            eprintln!("Error: {:#}", e);
+            process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Err(e) = config::load() {
+        eprintln!("Error: {:#}", e);
+        process::exit(1);
+    }
+
+    // Restore the terminal and save the code being edited if anything panics
+    crash::install_hook();
+
     // Setup terminal
     ui::setup_terminal()?;
 
@@ -21,57 +61,80 @@ fn main() -> io::Result<()> {
     // Handle any errors
     if let Err(e) = result {
         eprintln!("Error: {}", e);
-        std::process::exit(1);
+        process::exit(1);
     }
 
     Ok(())
 }
 
+/// `less validate-challenges [pack]`: check the built-in challenges, or the
+/// ones in a pack file, and exit with an error if any have problems.
+fn validate_challenges(pack: Option<&str>) -> ! {
+    let challenges = match pack {
+        Some(path) => load_pack(Path::new(path)).unwrap_or_else(|e| {
+            eprintln!("Error: {:#}", e);
+            process::exit(1);
+        }),
+        None => get_all_challenges(),
+    };
+
+    let problems = validate(&challenges);
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    println!("{} challenges checked, {} problems found", challenges.len(), problems.len());
+
+    process::exit(if problems.is_empty() { 0 } else { 1 });
+}
+
+/// `less minify [file]` and `less fmt [--strip-comments] [file]`: print a program, read from
+/// the file or from standard input, after rewriting it.
+fn print_rewritten(path: Option<&str>, rewrite: impl Fn(&str) -> anyhow::Result<String>) -> ! {
+    let source = match path {
+        Some(path) => std::fs::read_to_string(path),
+        None => io::read_to_string(io::stdin()),
+    };
+    let result = source
+        .map_err(anyhow::Error::from)
+        .and_then(|source| rewrite(&source));
+
+    match result {
+        Ok(rewritten) => {
+            println!("{}", rewritten);
+            process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            process::exit(1);
+        }
+    }
+}
+
 fn run_app() -> io::Result<()> {
     let challenges = get_all_challenges();
-    let mut menu = Menu::new(challenges)?;
+    let mut menu = Menu::new(challenges.clone())?;
+    // Each challenge's code and undo history, kept until the game quits
+    let mut buffers: HashMap<usize, Buffer> = HashMap::new();
+
+    // Offer back the code that was open when the game last crashed
+    if let Some(recovery) = crash::recovery() {
+        if let Some(challenge) = challenges.iter().find(|c| c.id == recovery.challenge_id) {
+            if show_recovery_prompt(challenge)? {
+                let quit = open_challenge(menu.get_storage(), challenge, Some(recovery.code), &mut buffers)?;
+                // Only now that the editor has had the code; another crash saves it again
+                crash::discard_recovery();
+                if quit {
+                    return Ok(());
+                }
+            }
+        }
+    }
 
     loop {
         match menu.run()? {
             MenuAction::SelectChallenge(challenge) => {
-                // Load any existing best solution
-                let storage = menu.get_storage();
-                let best = storage.get_personal_best(challenge.id)
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-
-                let mut editor = Editor::new(challenge.clone());
-
-                if let Some(pb) = best {
-                    editor.load_code(pb.code);
-                }
-
-                match editor.run()? {
-                    EditorResult::Exit => break,
-                    EditorResult::Back => {
-                        // Save solution if all tests passed
-                        if editor.all_tests_passed() {
-                            let code = editor.get_code();
-                            let char_count = editor.get_char_count();
-                            let beat_par = char_count <= challenge.par_score;
-
-                            let solution = Solution {
-                                challenge_id: challenge.id,
-                                code,
-                                char_count,
-                                passed: true,
-                                timestamp: std::time::SystemTime::now()
-                                    .duration_since(std::time::UNIX_EPOCH)
-                                    .unwrap()
-                                    .as_secs() as i64,
-                            };
-
-                            storage.save_solution(&solution)
-                                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-
-                            storage.update_beat_par(challenge.id, beat_par)
-                                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-                        }
-                    }
+                if open_challenge(menu.get_storage(), &challenge, None, &mut buffers)? {
+                    break;
                 }
             }
             MenuAction::OpenRepl => {
@@ -83,7 +146,7 @@ fn run_app() -> io::Result<()> {
                 reference.run()?;
             }
             MenuAction::ShowLeaderboard => {
-                show_leaderboard(menu.get_storage())?;
+                show_leaderboard(menu.get_storage(), &challenges)?;
             }
             MenuAction::Exit => break,
         }
@@ -92,17 +155,140 @@ fn run_app() -> io::Result<()> {
     Ok(())
 }
 
-fn show_leaderboard(storage: &Storage) -> io::Result<()> {
+/// Edit a challenge, starting from `code` or the personal best. Returns true
+/// if the player asked to quit the game.
+fn open_challenge(
+    storage: &Storage,
+    challenge: &Challenge,
+    code: Option<String>,
+    buffers: &mut HashMap<usize, Buffer>,
+) -> io::Result<bool> {
+    let kept = buffers.remove(&challenge.id);
+    let is_new = kept.is_none();
+    let mut editor = Editor::new(challenge.clone(), kept.unwrap_or_else(Buffer::new));
+
+    match code {
+        Some(code) => editor.load_code(code),
+        // A kept buffer already has the code the player left
+        None if is_new => {
+            // Load any existing best solution
+            let best = storage.get_personal_best(challenge.id)
+                .map_err(io::Error::other)?;
+
+            if let Some(pb) = best {
+                editor.load_code(pb.code);
+            }
+        }
+        None => {}
+    }
+
+    let result = editor.run()?;
+    crash::clear_current();
+
+    let quit = match result {
+        EditorResult::Exit => true,
+        EditorResult::Back => {
+            // Save solution if all tests passed
+            if editor.all_tests_passed() {
+                let code = editor.get_code();
+                let scores = Scores::measure(&code);
+                let beat_par = scores.get(challenge.metric) <= challenge.par_score;
+
+                let solution = Solution {
+                    challenge_id: challenge.id,
+                    code,
+                    scores,
+                    passed: true,
+                    timestamp: std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .unwrap()
+                        .as_secs() as i64,
+                };
+
+                storage.save_solution(&solution, challenge.metric)
+                    .map_err(io::Error::other)?;
+
+                storage.update_beat_par(challenge.id, beat_par)
+                    .map_err(io::Error::other)?;
+            }
+            false
+        }
+    };
+
+    buffers.insert(challenge.id, editor.into_buffer());
+    Ok(quit)
+}
+
+fn show_recovery_prompt(challenge: &Challenge) -> io::Result<bool> {
     use crossterm::{
         cursor,
+        event::KeyCode,
         queue,
-        style::{Color, Print, ResetColor, SetForegroundColor},
+        style::{Print, ResetColor, SetForegroundColor},
+        terminal::{Clear, ClearType},
+    };
+    use std::io::Write;
+
+    let theme = config::theme();
+    let mut stdout = io::stdout();
+
+    queue!(stdout, Clear(ClearType::All))?;
+
+    queue!(stdout, cursor::MoveTo(0, 0))?;
+    queue!(stdout, SetForegroundColor(theme.error), Print("═══ The game crashed last time ═══"), ResetColor)?;
+
+    queue!(stdout, cursor::MoveTo(0, 2))?;
+    queue!(stdout, Print(format!(" Unsaved code for Challenge {}: {} was recovered.", challenge.id, challenge.name)))?;
+
+    queue!(stdout, cursor::MoveTo(0, 3))?;
+    queue!(
+        stdout,
+        SetForegroundColor(theme.muted),
+        Print(format!(" A crash report was saved in {}", Storage::data_dir().display())),
+        ResetColor
+    )?;
+
+    queue!(stdout, cursor::MoveTo(0, 5))?;
+    queue!(stdout, SetForegroundColor(theme.accent), Print(" Open it in the editor? (y/n, n keeps it for next time)"), ResetColor)?;
+
+    stdout.flush()?;
+
+    loop {
+        match ui::read_key()?.code {
+            KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter => return Ok(true),
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => return Ok(false),
+            _ => {}
+        }
+    }
+}
+
+/// Best solutions per challenge, in one metric at a time. Tab and the arrow
+/// keys switch metric; any other key goes back to the menu.
+fn show_leaderboard(storage: &Storage, challenges: &[Challenge]) -> io::Result<()> {
+    let mut metric = Metric::default();
+    loop {
+        render_leaderboard(storage, challenges, metric)?;
+        let key = ui::read_key()?;
+        match config::keys().action(&key, &[Action::NextMetric, Action::PreviousMetric]) {
+            Some(Action::NextMetric) => metric = metric.next(),
+            Some(Action::PreviousMetric) => metric = metric.prev(),
+            _ => return Ok(()),
+        }
+    }
+}
+
+fn render_leaderboard(storage: &Storage, challenges: &[Challenge], metric: Metric) -> io::Result<()> {
+    use crossterm::{
+        cursor,
+        queue,
+        style::{Print, ResetColor, SetForegroundColor},
         terminal::{self, Clear, ClearType},
     };
     use std::io::Write;
 
+    let theme = config::theme();
     let mut stdout = io::stdout();
-    let (width, height) = terminal::size()?;
+    let (_, height) = terminal::size()?;
 
     queue!(stdout, Clear(ClearType::All))?;
 
@@ -110,52 +296,68 @@ fn show_leaderboard(storage: &Storage) -> io::Result<()> {
 
     // Title
     queue!(stdout, cursor::MoveTo(0, current_line), Clear(ClearType::CurrentLine))?;
-    queue!(stdout, SetForegroundColor(Color::Cyan), Print("═══ Your Leaderboard ═══"), ResetColor)?;
+    queue!(stdout, SetForegroundColor(theme.title), Print("═══ Your Leaderboard ═══"), ResetColor)?;
     current_line += 2;
 
     let bests = storage.get_all_personal_bests()
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        .map_err(io::Error::other)?;
 
     let total_score = storage.get_total_score()
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        .map_err(io::Error::other)?;
+
+    let entries = storage.get_leaderboard(metric)
+        .map_err(io::Error::other)?;
 
     // Stats
     queue!(stdout, cursor::MoveTo(0, current_line), Clear(ClearType::CurrentLine))?;
-    queue!(stdout, SetForegroundColor(Color::Yellow), Print(format!(" Total Score: {} points", total_score)), ResetColor)?;
+    queue!(stdout, SetForegroundColor(theme.accent), Print(format!(" Total Score: {} points", total_score)), ResetColor)?;
     current_line += 1;
 
     queue!(stdout, cursor::MoveTo(0, current_line), Clear(ClearType::CurrentLine))?;
-    queue!(stdout, SetForegroundColor(Color::Yellow), Print(format!(" Challenges Completed: {}", bests.len())), ResetColor)?;
+    queue!(stdout, SetForegroundColor(theme.accent), Print(format!(" Challenges Completed: {}", bests.len())), ResetColor)?;
     current_line += 1;
 
     queue!(stdout, cursor::MoveTo(0, current_line), Clear(ClearType::CurrentLine))?;
-    queue!(stdout, SetForegroundColor(Color::Yellow), Print(format!(" Beat Par: {}", bests.iter().filter(|b| b.beat_par).count())), ResetColor)?;
+    queue!(stdout, SetForegroundColor(theme.accent), Print(format!(" Beat Par: {}", bests.iter().filter(|b| b.beat_par).count())), ResetColor)?;
+    current_line += 2;
+
+    // Metric tabs
+    queue!(stdout, cursor::MoveTo(0, current_line), Clear(ClearType::CurrentLine), Print(" "))?;
+    for m in Metric::ALL {
+        let color = if m == metric { theme.title } else { theme.muted };
+        queue!(stdout, SetForegroundColor(color), Print(format!("[{}] ", m)), ResetColor)?;
+    }
     current_line += 2;
 
     // Header
     queue!(stdout, cursor::MoveTo(0, current_line), Clear(ClearType::CurrentLine))?;
-    queue!(stdout, SetForegroundColor(Color::White), Print(format!(" {:<4} {:<12} {:<8}", "ID", "Chars", "Status")), ResetColor)?;
+    queue!(stdout, SetForegroundColor(theme.text), Print(format!(" {:<4} {:<12} {:<8}", "ID", metric, "Status")), ResetColor)?;
     current_line += 1;
 
     queue!(stdout, cursor::MoveTo(0, current_line), Clear(ClearType::CurrentLine))?;
-    queue!(stdout, SetForegroundColor(Color::DarkGrey), Print(" ────────────────────────────────"), ResetColor)?;
+    queue!(stdout, SetForegroundColor(theme.muted), Print(" ────────────────────────────────"), ResetColor)?;
     current_line += 1;
 
     // List
-    for best in &bests {
+    for entry in &entries {
         if current_line >= height - 3 {
             break; // Leave room for footer
         }
 
-        let status = if best.beat_par { "✓ Beat Par" } else { "  Solved" };
-        let color = if best.beat_par { Color::Green } else { Color::Yellow };
+        // Par only means something in the metric the challenge is scored in
+        let challenge = challenges.iter().find(|c| c.id == entry.challenge_id);
+        let (status, color) = match challenge {
+            Some(c) if c.metric == metric && entry.score <= c.par_score => ("✓ Beat Par", theme.success),
+            Some(c) if c.metric != metric => ("  Solved", theme.muted),
+            _ => ("  Solved", theme.warning),
+        };
 
         queue!(stdout, cursor::MoveTo(0, current_line), Clear(ClearType::CurrentLine))?;
         queue!(
             stdout,
-            SetForegroundColor(Color::White),
-            Print(format!(" {:>3}  ", best.challenge_id)),
-            Print(format!("{:>4} chars  ", best.char_count)),
+            SetForegroundColor(theme.text),
+            Print(format!(" {:>3}  ", entry.challenge_id)),
+            Print(format!("{:>4} {:<7} ", entry.score, metric.unit())),
             SetForegroundColor(color),
             Print(status),
             ResetColor
@@ -165,11 +367,13 @@ fn show_leaderboard(storage: &Storage) -> io::Result<()> {
 
     // Footer
     queue!(stdout, cursor::MoveTo(0, height - 2), Clear(ClearType::CurrentLine))?;
-    queue!(stdout, SetForegroundColor(Color::DarkGrey), Print(" Press any key to return to menu..."), ResetColor)?;
-
-    stdout.flush()?;
-
-    ui::read_key()?;
-
-    Ok(())
+    let keys = config::keys();
+    let footer = format!(
+        " {}/{}: Switch metric | Any other key: Back to menu",
+        keys.label(Action::NextMetric),
+        keys.label(Action::PreviousMetric)
+    );
+    queue!(stdout, SetForegroundColor(theme.muted), Print(footer), ResetColor)?;
+
+    stdout.flush()
 }
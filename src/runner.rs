@@ -1,10 +1,33 @@
 use crate::lang::{parser, interpreter, types};
-use crate::challenges::TestCase;
-use anyhow::{Result, anyhow};
+use crate::lang::ast::Span;
+use crate::lang::error::{EvalError, RuntimeError};
+use crate::challenges::generate::{input_source, shrink_args};
+use crate::challenges::{Challenge, Comparison, Reference, TestCase};
+use crate::crash::catch_panic;
+use crate::diff::Data;
+use crate::lang::interpreter::{CancelFlag, Interpreter, Value};
+use crate::lang::types::Type;
+use std::collections::BTreeSet;
+use std::fmt;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
 use std::time::{Duration, Instant};
 
 const TIMEOUT_SECS: u64 = 2;
+/// Test cases evaluated at once.
+const MAX_WORKERS: usize = 4;
+/// Parsing, type checking and evaluation recurse deeply, so threads doing
+/// them get far more than the default stack.
+pub const WORKER_STACK_SIZE: usize = 256 * 1024 * 1024;
+/// How often the watchdog looks for tests past their deadline.
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(10);
+/// Random inputs checked against the reference solution on submission.
+pub const RANDOM_TESTS: usize = 20;
+/// Smaller inputs tried when shrinking a failing random test.
+const MAX_SHRINK_ATTEMPTS: usize = 500;
 
 #[derive(Debug, Clone)]
 pub struct TestResult {
@@ -12,7 +35,45 @@ pub struct TestResult {
     pub expected: String,
     pub actual: String,
     pub description: String,
-    pub error: Option<String>,
+    pub error: Option<TestError>,
+    /// Both values, when the test ran to completion, for a structural diff.
+    pub expected_value: Option<Data>,
+    pub actual_value: Option<Data>,
+}
+
+#[derive(Debug, Clone)]
+pub enum TestError {
+    Parse(String),
+    Type(String),
+    /// Spans in the error are relative to the solution code; `None` means the
+    /// error happened inside the test input rather than the solution.
+    Runtime(EvalError),
+    Timeout,
+    /// The interpreter itself panicked; the session keeps running.
+    Crash(String),
+    /// The challenge's expected value doesn't parse or evaluate.
+    Expected(String),
+    /// The solution's type doesn't fit the challenge's type signature.
+    Signature { actual: String, expected: String },
+}
+
+impl fmt::Display for TestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TestError::Parse(e) => write!(f, "{}", e),
+            TestError::Type(e) => write!(f, "Type error: {}", e),
+            TestError::Runtime(e) if e.error == RuntimeError::DepthExceeded => {
+                write!(f, "Infinite recursion detected")
+            }
+            TestError::Runtime(e) => write!(f, "{}", e),
+            TestError::Timeout => write!(f, "Execution timeout exceeded"),
+            TestError::Crash(message) => write!(f, "Internal error: {}", message),
+            TestError::Expected(e) => write!(f, "Invalid expected value: {}", e),
+            TestError::Signature { actual, expected } => {
+                write!(f, "Your solution has type {}, expected {}", actual, expected)
+            }
+        }
+    }
 }
 
 pub struct Runner {
@@ -21,117 +82,652 @@ pub struct Runner {
 
 impl Runner {
     pub fn new() -> Self {
-        Runner {
-            timeout_duration: Duration::from_secs(TIMEOUT_SECS),
+        Self::with_timeout(Duration::from_secs(TIMEOUT_SECS))
+    }
+
+    pub fn with_timeout(timeout_duration: Duration) -> Self {
+        Runner { timeout_duration }
+    }
+
+    /// Start running the test cases on a small pool of worker threads and
+    /// return immediately. Results arrive through the returned `TestRun` as
+    /// each test finishes. Code that doesn't fit `signature` fails every test
+    /// without running.
+    pub fn spawn_tests(&self, code: &str, test_cases: &[TestCase], signature: Option<&Type>) -> TestRun {
+        let jobs = test_cases.iter().cloned().map(|case| Job { case, args: None }).collect();
+        self.spawn(code, jobs, None, signature)
+    }
+
+    /// Run everything a submission has to pass: the visible tests, then the
+    /// hidden ones, then `random_tests` random inputs from `seed` compared
+    /// with the reference solution. A failing random input is shrunk to a
+    /// smaller one that still fails.
+    pub fn spawn_submission(&self, code: &str, challenge: &Challenge, seed: u64, random_tests: usize) -> TestRun {
+        let mut jobs: Vec<Job> = challenge.test_cases.iter()
+            .chain(&challenge.hidden_tests)
+            .cloned()
+            .map(|case| Job { case, args: None })
+            .collect();
+
+        if let Some(reference) = &challenge.reference {
+            let mut rng = fastrand::Rng::with_seed(seed);
+            for _ in 0..random_tests {
+                let args = reference.generate(&mut rng);
+                jobs.push(Job { case: reference.test_case(&args), args: Some(args) });
+            }
+        }
+
+        self.spawn(code, jobs, challenge.reference.clone(), challenge.signature().as_ref())
+    }
+
+    fn spawn(&self, code: &str, jobs: Vec<Job>, reference: Option<Reference>, signature: Option<&Type>) -> TestRun {
+        let (sender, receiver) = mpsc::channel();
+        let jobs: Arc<[Job]> = Arc::from(jobs);
+        let run = TestRun {
+            receiver,
+            cancel: CancelFlag::new(),
+            jobs: Arc::clone(&jobs),
+            results: vec![None; jobs.len()],
+        };
+
+        if let Err(error) = signature.map_or(Ok(()), |signature| check_signature(code, signature)) {
+            for (index, job) in jobs.iter().enumerate() {
+                let _ = sender.send((index, failure(&job.case, error.clone())));
+            }
+            return run;
+        }
+
+        let code: Arc<str> = Arc::from(code);
+        let reference = reference.map(Arc::new);
+        let next_test = Arc::new(AtomicUsize::new(0));
+        // Deadline and cancel flag of each test that is currently running
+        let running: Arc<Mutex<RunningTests>> = Arc::new(Mutex::new(vec![None; jobs.len()]));
+
+        let parallelism = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        let worker_count = parallelism.min(MAX_WORKERS).min(jobs.len());
+
+        let mut spawned = 0;
+        for _ in 0..worker_count {
+            let worker = Worker {
+                code: Arc::clone(&code),
+                jobs: Arc::clone(&jobs),
+                reference: reference.clone(),
+                next_test: Arc::clone(&next_test),
+                running: Arc::clone(&running),
+                cancel: run.cancel.clone(),
+                timeout: self.timeout_duration,
+                sender: sender.clone(),
+            };
+            let spawn = thread::Builder::new()
+                .name("test-worker".to_string())
+                .stack_size(WORKER_STACK_SIZE)
+                .spawn(move || worker.run());
+            if spawn.is_ok() {
+                spawned += 1;
+            }
+        }
+
+        if spawned == 0 {
+            for (index, job) in jobs.iter().enumerate() {
+                let error = TestError::Crash("could not start a test worker".to_string());
+                let _ = sender.send((index, failure(&job.case, error)));
+            }
+            return run;
+        }
+
+        let watchdog = Watchdog {
+            jobs,
+            next_test,
+            running,
+            cancel: run.cancel.clone(),
+            sender,
+        };
+        // Without a watchdog tests still finish, just without a deadline
+        let _ = thread::Builder::new()
+            .name("test-watchdog".to_string())
+            .spawn(move || watchdog.run());
+
+        run
+    }
+}
+
+/// Test cases running in the background. Dropping it cancels the tests that
+/// haven't finished yet.
+pub struct TestRun {
+    receiver: Receiver<(usize, TestResult)>,
+    cancel: CancelFlag,
+    jobs: Arc<[Job]>,
+    results: Vec<Option<TestResult>>,
+}
+
+impl TestRun {
+    /// Pick up results that arrived since the last call. Returns true if
+    /// there were any.
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        while let Ok((index, result)) = self.receiver.try_recv() {
+            changed |= self.record(index, result);
+        }
+        changed
+    }
+
+    /// Block until every test has a result.
+    pub fn wait(&mut self) {
+        while !self.is_finished() {
+            match self.receiver.recv() {
+                Ok((index, result)) => {
+                    self.record(index, result);
+                }
+                // Every worker and the watchdog are gone
+                Err(_) => break,
+            }
+        }
+    }
+
+    /// Results in test case order; `None` for tests still running.
+    pub fn results(&self) -> &[Option<TestResult>] {
+        &self.results
+    }
+
+    /// The test cases being run, in the same order as the results.
+    pub fn test_cases(&self) -> impl Iterator<Item = &TestCase> {
+        self.jobs.iter().map(|job| &job.case)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.results.iter().all(Option::is_some)
+    }
+
+    /// Keep the first result for each test; a worker that finishes after the
+    /// watchdog reported a timeout is ignored.
+    fn record(&mut self, index: usize, result: TestResult) -> bool {
+        match self.results.get_mut(index) {
+            Some(slot @ None) => {
+                *slot = Some(result);
+                true
+            }
+            _ => false,
         }
     }
+}
 
-    pub fn run_tests(&self, code: &str, test_cases: &[TestCase]) -> Vec<TestResult> {
-        test_cases.iter().map(|tc| self.run_single_test(code, tc)).collect()
+impl Drop for TestRun {
+    fn drop(&mut self) {
+        self.cancel.cancel();
     }
+}
 
-    fn run_single_test(&self, code: &str, test_case: &TestCase) -> TestResult {
-        let start = Instant::now();
+/// A test case to run, with its arguments if they were generated.
+struct Job {
+    case: TestCase,
+    args: Option<Vec<Data>>,
+}
 
-        let result = self.execute_with_timeout(code, &test_case.input, start);
+/// When a worker's current evaluation has to stop.
+#[derive(Clone)]
+struct Deadline {
+    at: Instant,
+    flag: CancelFlag,
+    /// Whether passing it fails the test. Shrinking already has a failure
+    /// to report, so it just stops.
+    reports_timeout: bool,
+}
 
-        match result {
-            Ok(actual) => {
-                let actual_str = actual.trim();
-                let expected_str = test_case.expected.trim();
-                let passed = actual_str == expected_str;
+/// The deadline of each test case a worker is evaluating.
+type RunningTests = Vec<Option<Deadline>>;
 
-                TestResult {
-                    passed,
-                    expected: expected_str.to_string(),
-                    actual: actual_str.to_string(),
-                    description: test_case.description.clone(),
-                    error: None,
+struct Worker {
+    code: Arc<str>,
+    jobs: Arc<[Job]>,
+    reference: Option<Arc<Reference>>,
+    next_test: Arc<AtomicUsize>,
+    running: Arc<Mutex<RunningTests>>,
+    cancel: CancelFlag,
+    timeout: Duration,
+    sender: Sender<(usize, TestResult)>,
+}
+
+impl Worker {
+    fn run(self) {
+        while !self.cancel.is_cancelled() {
+            // Claim the next test under the lock so the watchdog never sees
+            // it started but not yet running
+            let flag = CancelFlag::new();
+            let (index, job) = {
+                let mut running = lock(&self.running);
+                let index = self.next_test.fetch_add(1, AtomicOrdering::Relaxed);
+                let Some(job) = self.jobs.get(index) else {
+                    break;
+                };
+                running[index] = Some(Deadline {
+                    at: Instant::now() + self.timeout,
+                    flag: flag.clone(),
+                    reports_timeout: true,
+                });
+                (index, job)
+            };
+
+            let mut result = run_single_test(&self.code, &job.case, flag);
+            if let (Some(args), Some(reference)) = (&job.args, &self.reference) {
+                if is_counterexample(&result) {
+                    result = self.shrink(index, reference, args, result);
                 }
             }
-            Err(e) => TestResult {
-                passed: false,
-                expected: test_case.expected.clone(),
-                actual: "".to_string(),
-                description: test_case.description.clone(),
-                error: Some(e.to_string()),
-            },
+            lock(&self.running)[index] = None;
+
+            if self.sender.send((index, result)).is_err() {
+                break;
+            }
         }
     }
 
-    fn execute_with_timeout(&self, code: &str, input: &str, start: Instant) -> Result<String> {
-        // Check if we've already exceeded timeout
-        if start.elapsed() > self.timeout_duration {
-            return Err(anyhow!("Execution timeout exceeded"));
+    /// Look for smaller arguments that still fail, taking the first smaller
+    /// candidate that fails until none do. Shrinking gets a deadline of its
+    /// own, and stops with the smallest failure found so far when it passes.
+    fn shrink(&self, index: usize, reference: &Reference, args: &[Data], failure: TestResult) -> TestResult {
+        let flag = CancelFlag::new();
+        {
+            let mut running = lock(&self.running);
+            // Already reported as timed out
+            let Some(deadline) = running[index].as_mut() else {
+                return failure;
+            };
+            *deadline = Deadline {
+                at: Instant::now() + self.timeout,
+                flag: flag.clone(),
+                reports_timeout: false,
+            };
         }
 
-        // Parse the user's code
-        let user_expr = parser::parse(code)
-            .map_err(|e| anyhow!("Parse error: {}", e))?;
+        let mut smallest = (args.to_vec(), failure);
+        let mut attempts = 0;
+        'shrinking: loop {
+            for candidate in shrink_args(&reference.inputs, &smallest.0) {
+                attempts += 1;
+                if attempts > MAX_SHRINK_ATTEMPTS || flag.is_cancelled() || self.cancel.is_cancelled() {
+                    break 'shrinking;
+                }
+                let result = run_single_test(&self.code, &reference.test_case(&candidate), flag.clone());
+                if is_counterexample(&result) && !flag.is_cancelled() {
+                    smallest = (candidate, result);
+                    continue 'shrinking;
+                }
+            }
+            break;
+        }
 
-        // Type check
-        let mut type_checker = types::TypeChecker::new();
-        let mut type_env = types::get_builtin_env();
-        type_checker.infer(&user_expr, &mut type_env)
-            .map_err(|e| anyhow!("Type error: {}", e))?;
+        let (smallest_args, mut result) = smallest;
+        if smallest_args != args {
+            result.description = format!("{} (shrunk from {})", result.description, input_source(args));
+        }
+        result
+    }
+}
 
-        // Create a function application with the input
-        let full_code = if input.is_empty() {
-            code.to_string()
-        } else {
-            format!("({}) {}", code, input)
-        };
+/// A failure the solution is to blame for. Timeouts don't count, as a
+/// smaller input hitting the deadline says nothing about the solution.
+fn is_counterexample(result: &TestResult) -> bool {
+    !result.passed && !matches!(result.error, Some(TestError::Timeout | TestError::Expected(_)))
+}
 
-        // Parse and evaluate the full expression
-        let expr = parser::parse(&full_code)
-            .map_err(|e| anyhow!("Parse error: {}", e))?;
+/// Cancels tests that run past their deadline and reports them as timed out
+/// straight away, even if the worker takes a while to notice.
+struct Watchdog {
+    jobs: Arc<[Job]>,
+    next_test: Arc<AtomicUsize>,
+    running: Arc<Mutex<RunningTests>>,
+    cancel: CancelFlag,
+    sender: Sender<(usize, TestResult)>,
+}
 
-        let mut interpreter = interpreter::Interpreter::new();
-        let env = Rc::new(interpreter::get_builtin_env());
+impl Watchdog {
+    fn run(self) {
+        loop {
+            thread::sleep(WATCHDOG_INTERVAL);
 
-        // Simple timeout check - in a real implementation we'd use a separate thread
-        let value = interpreter.eval(&expr, &env)
-            .map_err(|e| {
-                if e.to_string().contains("Maximum recursion depth") {
-                    anyhow!("Infinite recursion detected")
-                } else {
-                    e
+            let now = Instant::now();
+            let mut running = lock(&self.running);
+            for (index, slot) in running.iter_mut().enumerate() {
+                let Some(deadline) = slot else {
+                    continue;
+                };
+                if self.cancel.is_cancelled() {
+                    deadline.flag.cancel();
+                } else if now >= deadline.at {
+                    deadline.flag.cancel();
+                    if deadline.reports_timeout {
+                        *slot = None;
+                        let _ = self.sender.send((index, failure(&self.jobs[index].case, TestError::Timeout)));
+                    }
                 }
-            })?;
+            }
 
-        if start.elapsed() > self.timeout_duration {
-            return Err(anyhow!("Execution timeout exceeded"));
+            let all_started = self.next_test.load(AtomicOrdering::Relaxed) >= self.jobs.len();
+            if self.cancel.is_cancelled() || (all_started && running.iter().all(Option::is_none)) {
+                break;
+            }
         }
+    }
+}
+
+/// Check the solution's type against the challenge's signature. Code that
+/// doesn't parse or type-check passes here, so each test reports why.
+fn check_signature(code: &str, signature: &Type) -> Result<(), TestError> {
+    let Ok(expr) = parser::parse(code) else {
+        return Ok(());
+    };
+    let mut type_checker = types::TypeChecker::new();
+    let Ok(ty) = type_checker.infer(&expr, &mut types::get_builtin_env()) else {
+        return Ok(());
+    };
+
+    type_checker.check_signature(&ty, signature).map_err(|_| TestError::Signature {
+        actual: types::generalize(&ty).to_string(),
+        expected: signature.to_string(),
+    })
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn run_single_test(code: &str, test_case: &TestCase, cancel: CancelFlag) -> TestResult {
+    let result = catch_panic(|| {
+        // Random tests run the reference solution here, so it gets the same
+        // deadline as the code being tested
+        let expected = evaluate_expected(&test_case.expected, cancel.clone())?;
+        let actual = execute(code, &test_case.input, cancel)?;
+        Ok((expected, actual))
+    })
+    .unwrap_or_else(|message| Err(TestError::Crash(message)));
 
-        Ok(value.to_string_repr())
+    match result {
+        Ok((expected, actual)) => TestResult {
+            passed: values_match(&actual, &expected, test_case.compare),
+            expected: expected.to_string_repr(),
+            actual: actual.to_string_repr(),
+            description: test_case.description.clone(),
+            error: None,
+            expected_value: Some(Data::from(&expected)),
+            actual_value: Some(Data::from(&actual)),
+        },
+        Err(e) => failure(test_case, e),
     }
+}
+
+fn failure(test_case: &TestCase, error: TestError) -> TestResult {
+    TestResult {
+        passed: false,
+        expected: test_case.expected.clone(),
+        actual: "".to_string(),
+        description: test_case.description.clone(),
+        error: Some(error),
+        expected_value: None,
+        actual_value: None,
+    }
+}
+
+fn execute(code: &str, input: &str, cancel: CancelFlag) -> Result<Value, TestError> {
+    // Parse the user's code
+    let user_expr = parser::parse(code)
+        .map_err(|e| TestError::Parse(e.to_string()))?;
+
+    // Type check
+    let mut type_checker = types::TypeChecker::new();
+    let mut type_env = types::get_builtin_env();
+    type_checker.infer(&user_expr, &mut type_env)
+        .map_err(|e| TestError::Type(e.to_string()))?;
+
+    // Create a function application with the input
+    let (full_code, code_offset) = if input.is_empty() {
+        (code.to_string(), 0)
+    } else {
+        (format!("({}) {}", code, input), 1)
+    };
+
+    // Parse and evaluate the full expression
+    let expr = parser::parse(&full_code)
+        .map_err(|e| TestError::Parse(e.to_string()))?;
+
+    let mut interpreter = Interpreter::with_cancel(cancel);
+    let env = Rc::new(interpreter::get_builtin_env());
+
+    interpreter.eval(&expr, &env).map_err(|e| match e.error {
+        RuntimeError::Cancelled => TestError::Timeout,
+        _ => TestError::Runtime(relative_to_code(e, code_offset, code.len())),
+    })
+}
 
-    pub fn count_chars(&self, code: &str) -> usize {
-        code.chars().filter(|c| !c.is_whitespace()).count()
+/// Evaluate a test's expected value. Failing here is a bug in the challenge,
+/// not in the solution.
+fn evaluate_expected(expected: &str, cancel: CancelFlag) -> Result<Value, TestError> {
+    let expr = parser::parse(expected)
+        .map_err(|e| TestError::Expected(e.to_string()))?;
+
+    let env = Rc::new(interpreter::get_builtin_env());
+    Interpreter::with_cancel(cancel)
+        .eval(&expr, &env)
+        .map_err(|e| match e.error {
+            RuntimeError::Cancelled => TestError::Timeout,
+            _ => TestError::Expected(e.to_string()),
+        })
+}
+
+fn values_match(actual: &Value, expected: &Value, compare: Comparison) -> bool {
+    match (compare, actual, expected) {
+        (Comparison::Exact, _, _) => actual == expected,
+        (Comparison::Unordered, Value::List(a), Value::List(b)) => {
+            let mut a = a.clone();
+            let mut b = b.clone();
+            a.sort();
+            b.sort();
+            a == b
+        }
+        (Comparison::SetEquality, Value::List(a), Value::List(b)) => {
+            a.iter().collect::<BTreeSet<_>>() == b.iter().collect::<BTreeSet<_>>()
+        }
+        (Comparison::Tolerance(t), Value::Number(a), Value::Number(b)) => {
+            a.abs_diff(*b) <= t.unsigned_abs()
+        }
+        (Comparison::Tolerance(_), Value::List(a), Value::List(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| values_match(a, b, compare))
+        }
+        (Comparison::Tolerance(_), Value::Record(a), Value::Record(b)) => {
+            a.len() == b.len()
+                && a.iter().zip(b).all(|((ka, a), (kb, b))| ka == kb && values_match(a, b, compare))
+        }
+        _ => actual == expected,
     }
 }
 
+/// Shift error locations from the `(code) input` harness back onto the user's
+/// code, dropping locations that point into the test input.
+fn relative_to_code(mut error: EvalError, offset: usize, len: usize) -> EvalError {
+    let shift = |span: Span| {
+        if span.start >= offset && span.end <= offset + len {
+            Some(Span::new(span.start - offset, span.end - offset))
+        } else {
+            None
+        }
+    };
+
+    error.span = error.span.and_then(shift);
+    for frame in &mut error.trace {
+        frame.call_site = frame.call_site.and_then(shift);
+    }
+    error
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_simple_execution() {
-        let runner = Runner::new();
-        let test_case = TestCase {
-            input: "5".to_string(),
-            expected: "10".to_string(),
-            description: "double 5".to_string(),
-        };
+        let test_case = TestCase::new("5", "10", "double 5");
 
-        let result = runner.run_single_test("\\x -> x * 2", &test_case);
+        let result = run_single_test("\\x -> x * 2", &test_case, CancelFlag::new());
         assert!(result.passed, "Expected pass but got: {:?}", result);
     }
 
     #[test]
-    fn test_char_count() {
+    fn test_overflow_fails_test_case() {
+        let test_case = TestCase::new("64", "0", "overflow");
+
+        let result = run_single_test("\\n -> 2 ^ n", &test_case, CancelFlag::new());
+        assert!(!result.passed);
+        assert!(matches!(result.error, Some(TestError::Runtime(_))), "{:?}", result);
+    }
+
+    fn case(input: &str, expected: &str) -> TestCase {
+        TestCase::new(input, expected, input)
+    }
+
+    fn run_all(runner: &Runner, code: &str, cases: &[TestCase], signature: Option<&Type>) -> Vec<TestResult> {
+        finish(runner.spawn_tests(code, cases, signature))
+    }
+
+    fn finish(mut run: TestRun) -> Vec<TestResult> {
+        run.wait();
+        run.results().iter().flatten().cloned().collect()
+    }
+
+    #[test]
+    fn test_results_keep_test_order() {
+        let cases: Vec<TestCase> = (1..=10).map(|n| case(&n.to_string(), &(n * n).to_string())).collect();
+        let results = run_all(&Runner::new(), "\\x -> x * x", &cases, None);
+
+        assert_eq!(results.len(), 10);
+        for (n, result) in (1..=10).zip(&results) {
+            assert!(result.passed, "{:?}", result);
+            assert_eq!(result.actual, (n * n).to_string());
+        }
+    }
+
+    #[test]
+    fn test_solution_must_fit_signature() {
+        let signature = parser::parse_type("[Int] -> [Int]").unwrap();
+        let cases = vec![case("[1, 2]", "[1, 2]"), case("[]", "[]")];
+
+        for result in run_all(&Runner::new(), "sum", &cases, Some(&signature)) {
+            let error = result.error.expect("signature mismatch").to_string();
+            assert_eq!(error, "Your solution has type [Int] -> Int, expected [Int] -> [Int]");
+        }
+        let results = run_all(&Runner::new(), "\\x, y -> x", &cases, Some(&signature));
+        assert_eq!(
+            results[0].error.as_ref().unwrap().to_string(),
+            "Your solution has type a -> b -> a, expected [Int] -> [Int]"
+        );
+
+        // More general solutions are fine, and other errors are left to the tests
+        assert!(run_all(&Runner::new(), "\\l -> l", &cases, Some(&signature)).iter().all(|r| r.passed));
+        let results = run_all(&Runner::new(), "\\l -> ", &cases, Some(&signature));
+        assert!(matches!(results[0].error, Some(TestError::Parse(_))), "{:?}", results[0]);
+    }
+
+    #[test]
+    fn test_zip_builtin_fits_zip_challenge() {
+        let challenge = crate::challenges::get_all_challenges().into_iter().find(|c| c.id == 17).unwrap();
+        let results = run_all(&Runner::new(), "zip", &challenge.test_cases, challenge.signature().as_ref());
+        assert!(results.iter().all(|r| r.passed), "{:?}", results);
+    }
+
+    #[test]
+    fn test_deadline_cancels_slow_test() {
+        let runner = Runner::with_timeout(Duration::from_millis(50));
+        let slow = "\\n -> if n == 0 then 0 else sum (map (\\y -> length [x | x <- 1..3000, x > 3000]) (1..3000))";
+        let cases = vec![case("1", "0"), case("0", "0")];
+
+        // The timeout result shows the deadline fired, however long a busy
+        // machine took to get there
+        let results = run_all(&runner, slow, &cases, None);
+
+        assert!(matches!(results[0].error, Some(TestError::Timeout)), "{:?}", results[0]);
+        assert!(results[1].passed, "{:?}", results[1]);
+    }
+
+    fn check(code: &str, input: &str, expected: &str, compare: Comparison) -> TestResult {
+        let test_case = TestCase { compare, ..case(input, expected) };
+        run_single_test(code, &test_case, CancelFlag::new())
+    }
+
+    #[test]
+    fn test_expected_compared_as_values() {
+        assert!(check("\\l -> l", "[1, 2]", "[1,2]", Comparison::Exact).passed);
+        assert!(check("\\s -> s", "\"a b\"", " \"a\" ++ \" b\" ", Comparison::Exact).passed);
+        assert!(!check("\\l -> l", "[1, 2]", "[2, 1]", Comparison::Exact).passed);
+    }
+
+    #[test]
+    fn test_comparators() {
+        let id = "\\l -> l";
+        assert!(check(id, "[3, 1, 2, 1]", "[1, 1, 2, 3]", Comparison::Unordered).passed);
+        assert!(!check(id, "[3, 1, 2]", "[1, 1, 2, 3]", Comparison::Unordered).passed);
+        assert!(check(id, "[3, 1, 2, 1]", "[1, 2, 3]", Comparison::SetEquality).passed);
+        assert!(!check(id, "[3, 1]", "[1, 2, 3]", Comparison::SetEquality).passed);
+        assert!(check(id, "[10, 20]", "[11, 19]", Comparison::Tolerance(1)).passed);
+        assert!(!check(id, "[10, 20]", "[12, 20]", Comparison::Tolerance(1)).passed);
+    }
+
+    #[test]
+    fn test_invalid_expected_value() {
+        let result = check("\\x -> x", "1", "[1,", Comparison::Exact);
+        assert!(matches!(result.error, Some(TestError::Expected(_))), "{:?}", result);
+    }
+
+    #[test]
+    fn test_challenge_expectations_evaluate() {
+        for challenge in crate::challenges::get_all_challenges() {
+            for test_case in &challenge.test_cases {
+                assert!(
+                    evaluate_expected(&test_case.expected, CancelFlag::new()).is_ok(),
+                    "challenge {} test {:?}",
+                    challenge.id,
+                    test_case.description
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_references_pass_submission_tests() {
         let runner = Runner::new();
-        assert_eq!(runner.count_chars("\\x -> x * 2"), 9);
-        assert_eq!(runner.count_chars("  \\x  ->  x * 2  "), 9);
+        for challenge in crate::challenges::get_all_challenges() {
+            let Some(reference) = &challenge.reference else { continue };
+            let results = finish(runner.spawn_submission(&reference.solution, &challenge, 7, RANDOM_TESTS));
+            assert_eq!(results.len(), challenge.test_cases.len() + challenge.hidden_tests.len() + RANDOM_TESTS);
+            for result in results {
+                assert!(result.passed, "challenge {} {}: {:?}", challenge.id, result.description, result);
+            }
+        }
+    }
+
+    #[test]
+    fn test_failing_random_input_is_shrunk() {
+        let reverse = crate::challenges::get_all_challenges().into_iter().find(|c| c.id == 7).unwrap();
+        let almost = "\\l -> if length l > 2 then [] else reverse l";
+        let results = finish(Runner::new().spawn_submission(almost, &reverse, 3, 10));
+
+        let random: Vec<_> = results.iter().filter(|r| r.description.starts_with("random input")).collect();
+        assert_eq!(random.len(), 10);
+        assert!(random.iter().any(|r| !r.passed));
+        for result in random.iter().filter(|r| !r.passed) {
+            assert!(result.description.starts_with("random input [0, 0, 0]"), "{}", result.description);
+            assert_eq!(result.actual, "[]");
+        }
+    }
+
+    #[test]
+    fn test_random_input_source() {
+        use crate::challenges::generate::{input_source, Gen};
+
+        assert_eq!(input_source(&[Data::Number(-3), Data::List(vec![Data::Number(-1)])]), "(-3) [-1]");
+        let mut rng = fastrand::Rng::with_seed(1);
+        for _ in 0..20 {
+            match Gen::sorted(Gen::list(0, 6, Gen::int(-5, 5))).generate(&mut rng) {
+                Data::List(items) => assert!(items.windows(2).all(|w| w[0] <= w[1])),
+                other => panic!("expected a list, got {}", other),
+            }
+        }
     }
 }
@@ -0,0 +1,130 @@
+use crate::lang::lexer::{self, TokenKind};
+use crate::lang::parser;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// What a solution's size is measured in. Each challenge picks one for its
+/// par score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// Bytes of source, whitespace and comments included.
+    Bytes,
+    /// Characters outside whitespace and comments. Spaces inside string
+    /// literals still count.
+    #[default]
+    Chars,
+    /// Lexical tokens, so long names cost no more than short ones.
+    Tokens,
+    /// Nodes of the parsed syntax tree.
+    AstNodes,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 4] = [Metric::Bytes, Metric::Chars, Metric::Tokens, Metric::AstNodes];
+
+    /// The size of `code`, or `None` when counting AST nodes of code that
+    /// doesn't parse.
+    pub fn measure(self, code: &str) -> Option<usize> {
+        let tokens = || lexer::tokenize(code).into_iter().filter(|t| !t.is_trivia());
+        match self {
+            Metric::Bytes => Some(code.len()),
+            Metric::Chars => Some(tokens().map(|t| t.text(code).chars().count()).sum()),
+            Metric::Tokens => Some(tokens().filter(|t| t.kind != TokenKind::Unknown).count()),
+            Metric::AstNodes => parser::parse(code).ok().map(|expr| expr.node_count()),
+        }
+    }
+
+    /// The unit scores are given in, such as "chars".
+    pub fn unit(self) -> &'static str {
+        match self {
+            Metric::Bytes => "bytes",
+            Metric::Chars => "chars",
+            Metric::Tokens => "tokens",
+            Metric::AstNodes => "nodes",
+        }
+    }
+
+    /// The metric after this one, wrapping around.
+    pub fn next(self) -> Metric {
+        let index = Metric::ALL.iter().position(|&m| m == self).unwrap_or(0);
+        Metric::ALL[(index + 1) % Metric::ALL.len()]
+    }
+
+    /// The metric before this one, wrapping around.
+    pub fn prev(self) -> Metric {
+        let index = Metric::ALL.iter().position(|&m| m == self).unwrap_or(0);
+        Metric::ALL[(index + Metric::ALL.len() - 1) % Metric::ALL.len()]
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Metric::Bytes => "Bytes",
+            Metric::Chars => "Chars",
+            Metric::Tokens => "Tokens",
+            Metric::AstNodes => "AST nodes",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A solution measured in every metric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Scores {
+    pub bytes: usize,
+    pub chars: usize,
+    pub tokens: usize,
+    pub ast_nodes: usize,
+}
+
+impl Scores {
+    /// Scores for code that parses; AST nodes are 0 otherwise.
+    pub fn measure(code: &str) -> Scores {
+        let measure = |metric: Metric| metric.measure(code).unwrap_or(0);
+        Scores {
+            bytes: measure(Metric::Bytes),
+            chars: measure(Metric::Chars),
+            tokens: measure(Metric::Tokens),
+            ast_nodes: measure(Metric::AstNodes),
+        }
+    }
+
+    pub fn get(&self, metric: Metric) -> usize {
+        match metric {
+            Metric::Bytes => self.bytes,
+            Metric::Chars => self.chars,
+            Metric::Tokens => self.tokens,
+            Metric::AstNodes => self.ast_nodes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics() {
+        let code = "\\x -> x * 2  -- double it\n";
+        assert_eq!(Scores::measure(code), Scores { bytes: 26, chars: 7, tokens: 6, ast_nodes: 4 });
+
+        assert_eq!(Metric::Chars.measure("\\x -> x * 2"), Some(7));
+        assert_eq!(Metric::Chars.measure("  \\x  ->  x * 2  "), Some(7));
+        assert_eq!(Metric::Chars.measure("\"a b\" ++ \"--\""), Some(11));
+        assert_eq!(Metric::Tokens.measure("filter (\\n -> n >= 10)"), Some(9));
+        assert_eq!(Metric::AstNodes.measure("match l with [] -> 0 | h::t -> h"), Some(8));
+        assert_eq!(Metric::AstNodes.measure("\\x ->"), None);
+    }
+
+    #[test]
+    fn test_tokens_cover_source() {
+        let code = "let f = \\x -> \"not -- a comment\" -- comment\n#{1 => x} é";
+        let tokens = lexer::tokenize(code);
+        let joined: String = tokens.iter().map(|t| t.text(code)).collect();
+        assert_eq!(joined, code);
+        assert!(tokens.iter().all(|t| !t.span.is_empty()));
+        assert_eq!(tokens.last().map(|t| t.kind), Some(TokenKind::Ident));
+    }
+}
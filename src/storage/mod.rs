@@ -1,12 +1,16 @@
-use rusqlite::{Connection, Result as SqlResult};
+use crate::score::{Metric, Scores};
+use rusqlite::{Connection, Result as SqlResult, Row};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
+/// Columns holding a solution's scores, in the order of the `Scores` fields.
+const SCORE_COLUMNS: [&str; 4] = ["byte_count", "char_count", "token_count", "node_count"];
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Solution {
     pub challenge_id: usize,
     pub code: String,
-    pub char_count: usize,
+    pub scores: Scores,
     pub passed: bool,
     pub timestamp: i64,
 }
@@ -15,10 +19,18 @@ pub struct Solution {
 pub struct PersonalBest {
     pub challenge_id: usize,
     pub code: String,
-    pub char_count: usize,
+    pub scores: Scores,
     pub beat_par: bool,
 }
 
+/// The best passing solution to a challenge under one metric.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeaderboardEntry {
+    pub challenge_id: usize,
+    pub code: String,
+    pub score: usize,
+}
+
 pub struct Storage {
     conn: Connection,
 }
@@ -31,14 +43,19 @@ impl Storage {
             std::fs::create_dir_all(parent).ok();
         }
 
-        let conn = Connection::open(&db_path)?;
+        Self::open(Connection::open(&db_path)?)
+    }
 
+    fn open(conn: Connection) -> SqlResult<Self> {
         conn.execute(
             "CREATE TABLE IF NOT EXISTS solutions (
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
                 challenge_id INTEGER NOT NULL,
                 code TEXT NOT NULL,
+                byte_count INTEGER NOT NULL DEFAULT 0,
                 char_count INTEGER NOT NULL,
+                token_count INTEGER NOT NULL DEFAULT 0,
+                node_count INTEGER NOT NULL DEFAULT 0,
                 passed INTEGER NOT NULL,
                 timestamp INTEGER NOT NULL
             )",
@@ -49,58 +66,79 @@ impl Storage {
             "CREATE TABLE IF NOT EXISTS personal_bests (
                 challenge_id INTEGER PRIMARY KEY,
                 code TEXT NOT NULL,
+                byte_count INTEGER NOT NULL DEFAULT 0,
                 char_count INTEGER NOT NULL,
+                token_count INTEGER NOT NULL DEFAULT 0,
+                node_count INTEGER NOT NULL DEFAULT 0,
                 beat_par INTEGER NOT NULL
             )",
             [],
         )?;
 
+        for table in ["solutions", "personal_bests"] {
+            add_score_columns(&conn, table)?;
+        }
+
         Ok(Storage { conn })
     }
 
-    fn get_db_path() -> PathBuf {
+    /// Directory holding the database and crash reports.
+    pub fn data_dir() -> PathBuf {
         let mut path = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
         path.push(".code_golf_game");
-        path.push("solutions.db");
         path
     }
 
-    pub fn save_solution(&self, solution: &Solution) -> SqlResult<()> {
+    fn get_db_path() -> PathBuf {
+        Self::data_dir().join("solutions.db")
+    }
+
+    /// Record a solution. A passing one becomes the personal best if it
+    /// scores lower in `metric`, the challenge's metric.
+    pub fn save_solution(&self, solution: &Solution, metric: Metric) -> SqlResult<()> {
+        let scores = &solution.scores;
         self.conn.execute(
-            "INSERT INTO solutions (challenge_id, code, char_count, passed, timestamp)
-             VALUES (?1, ?2, ?3, ?4, ?5)",
+            "INSERT INTO solutions (challenge_id, code, byte_count, char_count, token_count, node_count, passed, timestamp)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
             (
                 solution.challenge_id,
                 &solution.code,
-                solution.char_count,
+                scores.bytes,
+                scores.chars,
+                scores.tokens,
+                scores.ast_nodes,
                 if solution.passed { 1 } else { 0 },
                 solution.timestamp,
             ),
         )?;
 
         if solution.passed {
-            self.update_personal_best(solution)?;
+            self.update_personal_best(solution, metric)?;
         }
 
         Ok(())
     }
 
-    fn update_personal_best(&self, solution: &Solution) -> SqlResult<()> {
+    fn update_personal_best(&self, solution: &Solution, metric: Metric) -> SqlResult<()> {
         let current_best = self.get_personal_best(solution.challenge_id)?;
 
         let should_update = match current_best {
             None => true,
-            Some(best) => solution.char_count < best.char_count,
+            Some(best) => solution.scores.get(metric) < best.scores.get(metric),
         };
 
         if should_update {
+            let scores = &solution.scores;
             self.conn.execute(
-                "INSERT OR REPLACE INTO personal_bests (challenge_id, code, char_count, beat_par)
-                 VALUES (?1, ?2, ?3, ?4)",
+                "INSERT OR REPLACE INTO personal_bests (challenge_id, code, byte_count, char_count, token_count, node_count, beat_par)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
                 (
                     solution.challenge_id,
                     &solution.code,
-                    solution.char_count,
+                    scores.bytes,
+                    scores.chars,
+                    scores.tokens,
+                    scores.ast_nodes,
                     0, // Will be updated when we know par score
                 ),
             )?;
@@ -111,7 +149,7 @@ impl Storage {
 
     pub fn get_personal_best(&self, challenge_id: usize) -> SqlResult<Option<PersonalBest>> {
         let mut stmt = self.conn.prepare(
-            "SELECT challenge_id, code, char_count, beat_par
+            "SELECT challenge_id, code, byte_count, char_count, token_count, node_count, beat_par
              FROM personal_bests
              WHERE challenge_id = ?1"
         )?;
@@ -119,12 +157,7 @@ impl Storage {
         let mut rows = stmt.query([challenge_id])?;
 
         if let Some(row) = rows.next()? {
-            Ok(Some(PersonalBest {
-                challenge_id: row.get(0)?,
-                code: row.get(1)?,
-                char_count: row.get(2)?,
-                beat_par: row.get::<_, i32>(3)? != 0,
-            }))
+            Ok(Some(personal_best(row)?))
         } else {
             Ok(None)
         }
@@ -140,21 +173,38 @@ impl Storage {
 
     pub fn get_all_personal_bests(&self) -> SqlResult<Vec<PersonalBest>> {
         let mut stmt = self.conn.prepare(
-            "SELECT challenge_id, code, char_count, beat_par
+            "SELECT challenge_id, code, byte_count, char_count, token_count, node_count, beat_par
              FROM personal_bests
              ORDER BY challenge_id"
         )?;
 
-        let bests = stmt.query_map([], |row| {
-            Ok(PersonalBest {
+        let bests = stmt.query_map([], personal_best)?;
+
+        bests.collect()
+    }
+
+    /// The lowest scoring passing solution to each challenge under `metric`,
+    /// whichever metric the challenge itself uses.
+    pub fn get_leaderboard(&self, metric: Metric) -> SqlResult<Vec<LeaderboardEntry>> {
+        let column = score_column(metric);
+        // SQLite takes the other columns from the row holding the minimum
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT challenge_id, code, MIN({}) FROM solutions
+             WHERE passed = 1
+             GROUP BY challenge_id
+             ORDER BY challenge_id",
+            column
+        ))?;
+
+        let entries = stmt.query_map([], |row| {
+            Ok(LeaderboardEntry {
                 challenge_id: row.get(0)?,
                 code: row.get(1)?,
-                char_count: row.get(2)?,
-                beat_par: row.get::<_, i32>(3)? != 0,
+                score: row.get(2)?,
             })
         })?;
 
-        bests.collect()
+        entries.collect()
     }
 
     pub fn get_total_score(&self) -> SqlResult<i64> {
@@ -173,3 +223,131 @@ impl Storage {
         Ok(score)
     }
 }
+
+fn score_column(metric: Metric) -> &'static str {
+    match metric {
+        Metric::Bytes => SCORE_COLUMNS[0],
+        Metric::Chars => SCORE_COLUMNS[1],
+        Metric::Tokens => SCORE_COLUMNS[2],
+        Metric::AstNodes => SCORE_COLUMNS[3],
+    }
+}
+
+fn personal_best(row: &Row) -> SqlResult<PersonalBest> {
+    Ok(PersonalBest {
+        challenge_id: row.get(0)?,
+        code: row.get(1)?,
+        scores: Scores {
+            bytes: row.get(2)?,
+            chars: row.get(3)?,
+            tokens: row.get(4)?,
+            ast_nodes: row.get(5)?,
+        },
+        beat_par: row.get::<_, i32>(6)? != 0,
+    })
+}
+
+/// Databases from before there were several metrics only have `char_count`.
+/// Add the missing columns and score every saved solution again.
+fn add_score_columns(conn: &Connection, table: &str) -> SqlResult<()> {
+    let existing: Vec<String> = conn
+        .prepare(&format!("PRAGMA table_info({})", table))?
+        .query_map([], |row| row.get(1))?
+        .collect::<SqlResult<_>>()?;
+
+    let missing: Vec<&str> = SCORE_COLUMNS.iter()
+        .copied()
+        .filter(|column| !existing.iter().any(|c| c == column))
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    for column in missing {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} INTEGER NOT NULL DEFAULT 0", table, column), [])?;
+    }
+
+    let rows: Vec<(i64, String)> = conn
+        .prepare(&format!("SELECT rowid, code FROM {}", table))?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<SqlResult<_>>()?;
+    for (rowid, code) in rows {
+        let scores = Scores::measure(&code);
+        conn.execute(
+            &format!(
+                "UPDATE {} SET byte_count = ?1, char_count = ?2, token_count = ?3, node_count = ?4 WHERE rowid = ?5",
+                table
+            ),
+            (scores.bytes, scores.chars, scores.tokens, scores.ast_nodes, rowid),
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solution(challenge_id: usize, code: &str) -> Solution {
+        Solution {
+            challenge_id,
+            code: code.to_string(),
+            scores: Scores::measure(code),
+            passed: true,
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn test_best_solution_per_metric() {
+        let storage = Storage::open(Connection::open_in_memory().unwrap()).unwrap();
+        let few_tokens = "\\long_name -> long_name * 2";
+        let few_chars = "\\x->x+x+x-x";
+        storage.save_solution(&solution(1, few_tokens), Metric::Tokens).unwrap();
+        storage.save_solution(&solution(1, few_chars), Metric::Tokens).unwrap();
+
+        let best = storage.get_personal_best(1).unwrap().unwrap();
+        assert_eq!(best.code, few_tokens);
+        assert_eq!(best.scores, Scores::measure(few_tokens));
+
+        let by_chars = storage.get_leaderboard(Metric::Chars).unwrap();
+        assert_eq!(by_chars, vec![LeaderboardEntry { challenge_id: 1, code: few_chars.to_string(), score: 11 }]);
+        let by_tokens = storage.get_leaderboard(Metric::Tokens).unwrap();
+        assert_eq!(by_tokens[0].code, few_tokens);
+        assert_eq!(by_tokens[0].score, 6);
+    }
+
+    #[test]
+    fn test_old_database_is_migrated() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE solutions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                challenge_id INTEGER NOT NULL,
+                code TEXT NOT NULL,
+                char_count INTEGER NOT NULL,
+                passed INTEGER NOT NULL,
+                timestamp INTEGER NOT NULL
+            );
+            CREATE TABLE personal_bests (
+                challenge_id INTEGER PRIMARY KEY,
+                code TEXT NOT NULL,
+                char_count INTEGER NOT NULL,
+                beat_par INTEGER NOT NULL
+            );
+            INSERT INTO solutions (challenge_id, code, char_count, passed, timestamp) VALUES (6, 'sum -- easy', 6, 1, 0);
+            INSERT INTO personal_bests VALUES (6, 'sum -- easy', 6, 1);",
+        ).unwrap();
+
+        let storage = Storage::open(conn).unwrap();
+        let best = storage.get_personal_best(6).unwrap().unwrap();
+        assert_eq!(best.scores, Scores { bytes: 11, chars: 3, tokens: 1, ast_nodes: 1 });
+        assert!(best.beat_par);
+        assert_eq!(storage.get_leaderboard(Metric::AstNodes).unwrap()[0].score, 1);
+
+        // Opening again leaves it as it is
+        let storage = Storage::open(storage.conn).unwrap();
+        assert_eq!(storage.get_all_personal_bests().unwrap().len(), 1);
+    }
+}
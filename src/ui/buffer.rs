@@ -0,0 +1,590 @@
+use crate::lang::lexer::{self, TokenKind};
+use std::ops::Range;
+
+/// Undo steps kept; older ones are forgotten.
+const MAX_UNDO: usize = 1000;
+
+/// Characters typed with their closing partner, and the partner.
+const PAIRS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('"', '"')];
+
+/// Text being edited, with a cursor that moves by characters, words and
+/// lines, and the history of edits to undo and redo.
+pub struct Buffer {
+    chars: Vec<char>,
+    /// Index into `chars`.
+    cursor: usize,
+    /// The column Up and Down aim for, kept while passing shorter lines.
+    goal_column: Option<usize>,
+    /// Where the selection started; it runs from here to the cursor.
+    anchor: Option<usize>,
+    /// States before each group of edits, latest last.
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    /// The kind of the latest edit, while more of the same would join its
+    /// undo group.
+    last_edit: Option<Edit>,
+}
+
+struct Snapshot {
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    /// Typing characters one after another.
+    Type,
+    /// Backspace or Delete held down.
+    Erase,
+    /// Anything else, undone on its own.
+    Other,
+}
+
+impl Buffer {
+    pub fn new() -> Self {
+        Buffer {
+            chars: Vec::new(),
+            cursor: 0,
+            goal_column: None,
+            anchor: None,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            last_edit: None,
+        }
+    }
+
+    /// Replace the text, putting the cursor at the end. This can be undone
+    /// like any edit, unless the buffer was empty.
+    pub fn set_text(&mut self, text: &str) {
+        let chars: Vec<char> = text.chars().collect();
+        if chars == self.chars {
+            return;
+        }
+        if !self.chars.is_empty() {
+            self.record(Edit::Other);
+        }
+        self.chars = chars;
+        self.cursor = self.chars.len();
+        self.goal_column = None;
+        self.anchor = None;
+    }
+
+    pub fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    /// Zero-based line and column, in characters, of a position.
+    fn line_col(&self, pos: usize) -> (usize, usize) {
+        let before = &self.chars[..pos];
+        let line = before.iter().filter(|&&c| c == '\n').count();
+        let col = before.iter().rev().take_while(|&&c| c != '\n').count();
+        (line, col)
+    }
+
+    pub fn cursor_line_col(&self) -> (usize, usize) {
+        self.line_col(self.cursor)
+    }
+
+    /// Byte offset of the cursor in the text, as the lexer and parser count.
+    pub fn cursor_offset(&self) -> usize {
+        self.chars[..self.cursor].iter().map(|c| c.len_utf8()).sum()
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.chars.iter().filter(|&&c| c == '\n').count() + 1
+    }
+
+    /// Where the line holding `pos` starts.
+    fn line_start(&self, pos: usize) -> usize {
+        self.chars[..pos].iter().rposition(|&c| c == '\n').map_or(0, |i| i + 1)
+    }
+
+    /// Where the line holding `pos` ends, before its newline.
+    fn line_end(&self, pos: usize) -> usize {
+        self.chars[pos..].iter().position(|&c| c == '\n').map_or(self.chars.len(), |i| pos + i)
+    }
+
+    /// Insert a character in place of any selection.
+    pub fn insert(&mut self, c: char) {
+        if self.take_selection().is_some() {
+            self.record(Edit::Other);
+            self.erase_selection();
+        } else {
+            // A new line starts a new undo group
+            self.record(if c == '\n' { Edit::Other } else { Edit::Type });
+        }
+        self.chars.insert(self.cursor, c);
+        self.cursor += 1;
+        self.goal_column = None;
+    }
+
+    /// Insert a typed character, closing brackets and quotes as they're
+    /// opened, typing over a closer that's already there, and bracketing
+    /// any selection.
+    pub fn type_char(&mut self, c: char) {
+        let next = self.chars.get(self.cursor).copied();
+        if let Some(range) = self.selection() {
+            if let Some(&(open, close)) = PAIRS.iter().find(|(open, _)| *open == c) {
+                self.record(Edit::Other);
+                self.anchor = None;
+                self.chars.insert(range.end, close);
+                self.chars.insert(range.start, open);
+                self.cursor = range.end + 2;
+                return;
+            }
+        } else if PAIRS.iter().any(|&(_, close)| close == c) && next == Some(c) {
+            self.right();
+            return;
+        }
+
+        let close = PAIRS.iter().find(|(open, _)| *open == c).map(|&(_, close)| close);
+        let prev = self.cursor.checked_sub(1).map(|p| self.chars[p]);
+        // Only close where nothing would end up inside the pair
+        let before_space = next.is_none_or(|n| n.is_whitespace() || ")],}".contains(n));
+        let after_word = prev.is_some_and(|p| p.is_alphanumeric() || p == '_' || p == '"');
+        self.insert(c);
+        if let Some(close) = close.filter(|_| before_space && !(c == '"' && after_word)) {
+            self.chars.insert(self.cursor, close);
+        }
+    }
+
+    /// Insert text, such as a paste, in place of any selection, as one
+    /// undo step.
+    pub fn insert_str(&mut self, text: &str) {
+        self.record(Edit::Other);
+        self.erase_selection();
+        let chars: Vec<char> = text.chars().collect();
+        self.chars.splice(self.cursor..self.cursor, chars.iter().copied());
+        self.cursor += chars.len();
+        self.goal_column = None;
+    }
+
+    /// Delete the character before the cursor, or the selection. Between
+    /// a pair such as `()`, both go.
+    pub fn backspace(&mut self) {
+        if self.delete_selection() {
+            return;
+        }
+        let pair = (self.cursor.checked_sub(1).map(|p| self.chars[p]), self.chars.get(self.cursor).copied());
+        if PAIRS.iter().any(|&(open, close)| pair == (Some(open), Some(close))) {
+            self.record(Edit::Erase);
+            self.chars.remove(self.cursor);
+        }
+        if self.cursor > 0 {
+            self.record(Edit::Erase);
+            self.cursor -= 1;
+            self.chars.remove(self.cursor);
+        }
+        self.goal_column = None;
+    }
+
+    pub fn delete(&mut self) {
+        if self.delete_selection() {
+            return;
+        }
+        if self.cursor < self.chars.len() {
+            self.record(Edit::Erase);
+            self.chars.remove(self.cursor);
+        }
+        self.goal_column = None;
+    }
+
+    /// Delete back to the start of the word before the cursor.
+    pub fn delete_word_back(&mut self) {
+        if self.delete_selection() {
+            return;
+        }
+        let start = self.word_start();
+        self.record(Edit::Other);
+        self.chars.drain(start..self.cursor);
+        self.cursor = start;
+        self.goal_column = None;
+    }
+
+    /// Delete up to the end of the word after the cursor.
+    pub fn delete_word_forward(&mut self) {
+        if self.delete_selection() {
+            return;
+        }
+        let end = self.word_end();
+        self.record(Edit::Other);
+        self.chars.drain(self.cursor..end);
+        self.goal_column = None;
+    }
+
+    pub fn left(&mut self) {
+        self.move_to(self.cursor.saturating_sub(1));
+    }
+
+    pub fn right(&mut self) {
+        self.move_to((self.cursor + 1).min(self.chars.len()));
+    }
+
+    pub fn word_left(&mut self) {
+        self.move_to(self.word_start());
+    }
+
+    pub fn word_right(&mut self) {
+        self.move_to(self.word_end());
+    }
+
+    /// To the first non-blank character of the line, or to its very start
+    /// if the cursor is already there.
+    pub fn home(&mut self) {
+        let start = self.line_start(self.cursor);
+        let indented = start + self.chars[start..].iter().take_while(|&&c| c == ' ' || c == '\t').count();
+        self.move_to(if self.cursor == indented { start } else { indented });
+    }
+
+    pub fn end(&mut self) {
+        self.move_to(self.line_end(self.cursor));
+    }
+
+    pub fn up(&mut self, lines: usize) {
+        let (line, _) = self.cursor_line_col();
+        self.move_to_line(line.saturating_sub(lines));
+    }
+
+    pub fn down(&mut self, lines: usize) {
+        let (line, _) = self.cursor_line_col();
+        self.move_to_line((line + lines).min(self.line_count() - 1));
+    }
+
+    /// Move to a line, as near the goal column as it's long.
+    fn move_to_line(&mut self, line: usize) {
+        let column = self.goal_column.unwrap_or_else(|| self.cursor_line_col().1);
+        let start: usize = self.chars
+            .split_inclusive(|&c| c == '\n')
+            .take(line)
+            .map(<[char]>::len)
+            .sum();
+        self.cursor = (start + column).min(self.line_end(start));
+        self.goal_column = Some(column);
+        self.last_edit = None;
+    }
+
+    fn move_to(&mut self, pos: usize) {
+        self.cursor = pos;
+        self.goal_column = None;
+        // Typing somewhere else starts a new undo group
+        self.last_edit = None;
+    }
+
+    /// Start selecting from the cursor if `selecting` and not already, or
+    /// stop. Call before moving the cursor.
+    pub fn set_selecting(&mut self, selecting: bool) {
+        if !selecting {
+            self.anchor = None;
+        } else if self.anchor.is_none() {
+            self.anchor = Some(self.cursor);
+        }
+    }
+
+    pub fn select_all(&mut self) {
+        self.anchor = Some(0);
+        self.move_to(self.chars.len());
+    }
+
+    /// The selected characters, if any are.
+    pub fn selection(&self) -> Option<Range<usize>> {
+        let anchor = self.anchor?;
+        let range = anchor.min(self.cursor)..anchor.max(self.cursor);
+        (!range.is_empty()).then_some(range)
+    }
+
+    pub fn selected_text(&self) -> Option<String> {
+        self.selection().map(|range| self.chars[range].iter().collect())
+    }
+
+    /// Delete the selection and return what it was.
+    pub fn cut(&mut self) -> Option<String> {
+        let text = self.selected_text()?;
+        self.delete_selection();
+        Some(text)
+    }
+
+    /// End the selection, returning it if it wasn't empty.
+    fn take_selection(&mut self) -> Option<Range<usize>> {
+        let range = self.selection();
+        if range.is_none() {
+            self.anchor = None;
+        }
+        range
+    }
+
+    /// Delete the selection as an undo step of its own. Returns false if
+    /// nothing was selected.
+    fn delete_selection(&mut self) -> bool {
+        if self.take_selection().is_none() {
+            return false;
+        }
+        self.record(Edit::Other);
+        self.erase_selection();
+        true
+    }
+
+    /// Remove the selected text without recording an undo step.
+    fn erase_selection(&mut self) {
+        if let Some(range) = self.selection() {
+            self.chars.drain(range.clone());
+            self.cursor = range.start;
+        }
+        self.anchor = None;
+        self.goal_column = None;
+    }
+
+    /// The bracket at or just before the cursor and the one matching it,
+    /// going by the lexer so brackets in strings and comments don't count.
+    pub fn matching_brackets(&self) -> Option<(usize, usize)> {
+        let text = self.text();
+        // Char index of each bracket, and whether it opens
+        let mut brackets = Vec::new();
+        let mut chars_before = 0;
+        let mut counted = 0;
+        for token in lexer::tokenize(&text) {
+            chars_before += text[counted..token.span.start].chars().count();
+            counted = token.span.start;
+            let token_text = token.text(&text);
+            match (token.kind, token_text) {
+                (TokenKind::Punct, "(" | "[" | "{") => brackets.push((chars_before, true)),
+                (TokenKind::Punct, ")" | "]" | "}") => brackets.push((chars_before, false)),
+                // The opening brackets of maps and sets
+                (TokenKind::Operator, "#{" | "#[") => brackets.push((chars_before + 1, true)),
+                _ => {}
+            }
+        }
+
+        let at = |pos: usize| brackets.iter().position(|&(p, _)| p == pos);
+        let index = at(self.cursor).or_else(|| at(self.cursor.checked_sub(1)?))?;
+        let (pos, opens) = brackets[index];
+        let mut depth = 0;
+        let candidates: Box<dyn Iterator<Item = &(usize, bool)>> = if opens {
+            Box::new(brackets[index..].iter())
+        } else {
+            Box::new(brackets[..=index].iter().rev())
+        };
+        for &(other, other_opens) in candidates {
+            depth += if other_opens == opens { 1 } else { -1 };
+            if depth == 0 {
+                return Some((pos, other));
+            }
+        }
+        None
+    }
+
+    /// Go back to before the latest group of edits. Returns false if there
+    /// was nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let Some(snapshot) = self.undo.pop() else { return false };
+        let current = self.restore(snapshot);
+        self.redo.push(current);
+        true
+    }
+
+    /// Redo the latest group of edits undone. Returns false if there was
+    /// nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        let Some(snapshot) = self.redo.pop() else { return false };
+        let current = self.restore(snapshot);
+        self.undo.push(current);
+        true
+    }
+
+    /// Save the state before an edit, unless the edit continues the group
+    /// of the one before it.
+    fn record(&mut self, edit: Edit) {
+        if edit != Edit::Other && self.last_edit == Some(edit) {
+            return;
+        }
+        self.undo.push(Snapshot { chars: self.chars.clone(), cursor: self.cursor });
+        if self.undo.len() > MAX_UNDO {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+        self.last_edit = Some(edit);
+    }
+
+    /// Go back to a snapshot, returning the state it replaced.
+    fn restore(&mut self, snapshot: Snapshot) -> Snapshot {
+        let current = Snapshot { chars: std::mem::replace(&mut self.chars, snapshot.chars), cursor: self.cursor };
+        self.cursor = snapshot.cursor;
+        self.goal_column = None;
+        self.anchor = None;
+        self.last_edit = None;
+        current
+    }
+
+    /// The start of the word before the cursor, skipping any spaces first.
+    fn word_start(&self) -> usize {
+        let mut pos = self.cursor;
+        while pos > 0 && self.chars[pos - 1].is_whitespace() {
+            pos -= 1;
+        }
+        if let Some(class) = pos.checked_sub(1).map(|p| char_class(self.chars[p])) {
+            while pos > 0 && char_class(self.chars[pos - 1]) == class {
+                pos -= 1;
+            }
+        }
+        pos
+    }
+
+    /// The end of the word after the cursor, skipping any spaces first.
+    fn word_end(&self) -> usize {
+        let mut pos = self.cursor;
+        while pos < self.chars.len() && self.chars[pos].is_whitespace() {
+            pos += 1;
+        }
+        if let Some(class) = self.chars.get(pos).map(|&c| char_class(c)) {
+            while pos < self.chars.len() && char_class(self.chars[pos]) == class {
+                pos += 1;
+            }
+        }
+        pos
+    }
+}
+
+/// Words are runs of name characters, or runs of symbols such as `->`.
+fn char_class(c: char) -> u8 {
+    if c.is_alphanumeric() || c == '_' {
+        0
+    } else if c.is_whitespace() {
+        1
+    } else {
+        2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A buffer with the cursor where `|` is.
+    fn buffer(text: &str) -> Buffer {
+        let mut buffer = Buffer::new();
+        buffer.set_text(&text.replace('|', ""));
+        buffer.cursor = text.chars().position(|c| c == '|').unwrap();
+        buffer
+    }
+
+    fn show(buffer: &Buffer) -> String {
+        let mut text = buffer.text();
+        let byte = text.char_indices().nth(buffer.cursor).map_or(text.len(), |(i, _)| i);
+        text.insert(byte, '|');
+        text
+    }
+
+    #[test]
+    fn test_line_movement() {
+        let mut b = buffer("match xs with\n  [] -> 0\n  x :: r|est -> x");
+        b.up(1);
+        assert_eq!(show(&b), "match xs with\n  [] -> |0\n  x :: rest -> x");
+        b.up(1);
+        assert_eq!(show(&b), "match xs| with\n  [] -> 0\n  x :: rest -> x");
+        b.end();
+        b.down(1);
+        // The column is remembered across the shorter line
+        assert_eq!(show(&b), "match xs with\n  [] -> 0|\n  x :: rest -> x");
+        b.down(5);
+        assert_eq!(show(&b), "match xs with\n  [] -> 0\n  x :: rest -|> x");
+        b.home();
+        assert_eq!(show(&b), "match xs with\n  [] -> 0\n  |x :: rest -> x");
+        b.home();
+        assert_eq!(show(&b), "match xs with\n  [] -> 0\n|  x :: rest -> x");
+        b.end();
+        assert_eq!(show(&b), "match xs with\n  [] -> 0\n  x :: rest -> x|");
+        assert_eq!(b.cursor_line_col(), (2, 16));
+    }
+
+    #[test]
+    fn test_undo() {
+        let mut b = buffer("|");
+        for c in "map f".chars() {
+            b.insert(c);
+        }
+        b.insert('\n');
+        b.insert('x');
+        b.backspace();
+        b.backspace();
+        assert_eq!(show(&b), "map f|");
+        b.undo();
+        assert_eq!(show(&b), "map f\nx|");
+        // A new line is a group of its own
+        b.undo();
+        assert_eq!(show(&b), "map f\n|");
+        b.undo();
+        assert_eq!(show(&b), "map f|");
+        b.undo();
+        assert_eq!(show(&b), "|");
+        assert!(!b.undo());
+        for _ in 0..3 {
+            b.redo();
+        }
+        assert_eq!(show(&b), "map f\nx|");
+
+        // Typing after moving starts a new group, and forgets what was undone
+        b.left();
+        b.insert('y');
+        assert!(!b.redo());
+        b.set_text("map g");
+        b.undo();
+        assert_eq!(show(&b), "map f\ny|x");
+        b.undo();
+        assert_eq!(show(&b), "map f\n|x");
+    }
+
+    #[test]
+    fn test_selection() {
+        let mut b = buffer("map |f xs");
+        b.set_selecting(true);
+        b.word_right();
+        assert_eq!(b.selected_text().as_deref(), Some("f"));
+        b.type_char('(');
+        assert_eq!(show(&b), "map (f)| xs");
+        b.set_selecting(true);
+        b.home();
+        b.home();
+        assert_eq!(b.cut().as_deref(), Some("map (f)"));
+        b.insert_str("filter even");
+        assert_eq!(show(&b), "filter even| xs");
+        b.undo();
+        b.undo();
+        assert_eq!(show(&b), "|map (f) xs");
+    }
+
+    #[test]
+    fn test_brackets() {
+        let mut b = buffer("map |");
+        for c in "(f \"a\")".chars() {
+            b.type_char(c);
+        }
+        assert_eq!(show(&b), "map (f \"a\")|");
+        b.left();
+        b.type_char('[');
+        assert_eq!(show(&b), "map (f \"a\"[|])");
+        b.backspace();
+        assert_eq!(show(&b), "map (f \"a\"|)");
+        // Nothing is added in front of a word
+        b.home();
+        b.type_char('(');
+        assert_eq!(show(&b), "(|map (f \"a\")");
+
+        let b = buffer("#{1 => [2]}|");
+        assert_eq!(b.matching_brackets(), Some((10, 1)));
+        let b = buffer("(\"(\" ++ x|)");
+        assert_eq!(b.matching_brackets(), Some((9, 0)));
+    }
+
+    #[test]
+    fn test_words() {
+        let mut b = buffer("\\x -> f|oo_bar x");
+        b.word_right();
+        assert_eq!(show(&b), "\\x -> foo_bar| x");
+        b.word_left();
+        b.word_left();
+        assert_eq!(show(&b), "\\x |-> foo_bar x");
+        b.delete_word_forward();
+        assert_eq!(show(&b), "\\x | foo_bar x");
+        b.delete_word_back();
+        assert_eq!(show(&b), "\\| foo_bar x");
+    }
+}
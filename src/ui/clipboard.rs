@@ -0,0 +1,51 @@
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+/// Text last cut or copied in the game, shared by every challenge.
+static CLIPBOARD: Mutex<Option<String>> = Mutex::new(None);
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Keep `text` for pasting, and hand it to the terminal's clipboard too with
+/// an OSC 52 escape sequence. Terminals that don't support it ignore it.
+pub fn copy(text: &str) -> io::Result<()> {
+    *CLIPBOARD.lock().unwrap_or_else(|e| e.into_inner()) = Some(text.to_string());
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", base64(text.as_bytes()))?;
+    stdout.flush()
+}
+
+/// What was last cut or copied in the game. Pasting from other programs
+/// comes in through the terminal's bracketed paste instead.
+pub fn contents() -> Option<String> {
+    CLIPBOARD.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+fn base64(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | ((b as u32) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[((n >> (18 - 6 * i)) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64("\\x -> x".as_bytes()), "XHggLT4geA==");
+    }
+}
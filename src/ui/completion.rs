@@ -0,0 +1,113 @@
+use crossterm::{
+    cursor,
+    event::{KeyCode, KeyEvent},
+    queue,
+    style::{Attribute, Print, ResetColor, SetAttribute, SetForegroundColor},
+};
+use std::io::{self, Write};
+
+use super::theme::set_background;
+use crate::config;
+use crate::lang::analysis::{Analysis, Completion};
+
+/// Most names shown at once; the list scrolls to the selected one.
+const ROWS: usize = 8;
+
+/// The names that could finish the one being typed, with their types, in a
+/// list over the code next to the cursor.
+pub struct Popup {
+    /// What's been typed of the name.
+    prefix: String,
+    completions: Vec<Completion>,
+    selected: usize,
+}
+
+pub enum Action {
+    /// Finish the name by inserting this at the cursor.
+    Insert(String),
+    /// The selection moved.
+    Moved,
+    /// The popup is done with. Keys other than Esc still do what they
+    /// normally would.
+    Close,
+}
+
+/// Start completing the name that ends `offset` bytes into `source`.
+/// Returns `None` if there isn't one there, when Tab does what it would
+/// otherwise.
+pub fn open(source: &str, offset: usize) -> Option<Popup> {
+    let completions = Analysis::new(source, None).completions(offset)?;
+    let start = source[..offset].rfind(|c: char| !(c.is_alphanumeric() || c == '_')).map_or(0, |i| i + 1);
+    Some(Popup { prefix: source[start..offset].to_string(), completions, selected: 0 })
+}
+
+impl Popup {
+    pub fn is_empty(&self) -> bool {
+        self.completions.is_empty()
+    }
+
+    /// The rest of the name, if there's only one it could be.
+    pub fn only(&self) -> Option<String> {
+        match self.completions.as_slice() {
+            [completion] => Some(self.rest(completion)),
+            _ => None,
+        }
+    }
+
+    fn rest(&self, completion: &Completion) -> String {
+        completion.name[self.prefix.len()..].to_string()
+    }
+
+    pub fn handle(&mut self, key: &KeyEvent) -> Action {
+        match key.code {
+            KeyCode::Up => self.selected = (self.selected + self.completions.len() - 1) % self.completions.len(),
+            KeyCode::Down => self.selected = (self.selected + 1) % self.completions.len(),
+            KeyCode::Tab | KeyCode::Enter => return Action::Insert(self.rest(&self.completions[self.selected])),
+            _ => return Action::Close,
+        }
+        Action::Moved
+    }
+
+    /// Draw the list under the cursor at `(x, y)`, or over it if there's
+    /// no room below, lined up with the start of the name.
+    pub fn render(&self, stdout: &mut impl Write, x: u16, y: u16, width: u16, height: u16) -> io::Result<()> {
+        let theme = config::theme();
+        let items: Vec<(&str, String)> = self.completions.iter()
+            .map(|c| (c.name.as_str(), c.ty.as_ref().map_or(String::new(), |ty| format!(" : {}", ty))))
+            .collect();
+        let shown = items.len().min(ROWS);
+        let first = (self.selected + 1).saturating_sub(ROWS);
+        let popup_width = items.iter()
+            .map(|(name, ty)| name.chars().count() + ty.chars().count() + 2)
+            .max()
+            .unwrap_or(0)
+            .min(width as usize);
+
+        let left = x.saturating_sub(self.prefix.chars().count() as u16).min(width - popup_width as u16);
+        let top = if y as usize + 1 + shown < height as usize {
+            y + 1
+        } else {
+            y.saturating_sub(shown as u16)
+        };
+
+        for (row, (i, (name, ty))) in items.iter().enumerate().skip(first).take(shown).enumerate() {
+            // Marked as well as colored, for themes without colors
+            let (marker, background) = if i == self.selected { ('▸', theme.selection) } else { (' ', theme.surface) };
+            let name = super::truncate(name, popup_width.saturating_sub(2));
+            let ty = super::truncate(ty, popup_width.saturating_sub(2 + name.chars().count()));
+            let padding = popup_width - 2 - name.chars().count() - ty.chars().count();
+            queue!(stdout, cursor::MoveTo(left, top + row as u16))?;
+            set_background(stdout, background)?;
+            queue!(
+                stdout,
+                SetForegroundColor(theme.text),
+                Print(format!("{}{}", marker, name)),
+                SetForegroundColor(theme.accent),
+                Print(format!("{}{} ", ty, " ".repeat(padding))),
+                SetAttribute(Attribute::Reset),
+                ResetColor
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -1,128 +1,358 @@
 use crossterm::{
     cursor,
-    event::{KeyCode, KeyEvent, KeyModifiers},
-    execute, queue,
-    style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
+    event::{KeyCode, KeyModifiers},
+    queue,
+    style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor},
     terminal::{self, Clear, ClearType},
 };
 use std::io::{self, Write};
-
+use std::ops::Range;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::buffer::Buffer;
+use super::completion::{self, Popup};
+use super::highlight;
+use super::keys::Action;
+use super::theme::set_background;
+use super::{clipboard, Input};
 use crate::challenges::Challenge;
-use crate::runner::{Runner, TestResult};
+use crate::config;
+use crate::crash::catch_panic;
+use crate::diff::{self, Data};
+use crate::lang::analysis::Analysis;
+use crate::lang::{minify, pretty};
+use crate::runner::{Runner, TestError, TestRun, RANDOM_TESTS, WORKER_STACK_SIZE};
+
+/// How often to check for new test results while tests are running.
+const RESULT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long typing has to pause before the code is checked again.
+const CHECK_DELAY: Duration = Duration::from_millis(300);
+
+/// A line of the results pane as colored pieces of text.
+type Line = Vec<(Color, String)>;
 
 pub struct Editor {
-    code: Vec<char>,
-    cursor_pos: usize,
+    buffer: Buffer,
+    /// The first line of code on screen.
     scroll_offset: usize,
     challenge: Challenge,
     runner: Runner,
-    last_results: Option<Vec<TestResult>>,
+    test_run: Option<TestRun>,
+    /// Which tests of the latest run are hidden, shown without their values.
+    hidden: Range<usize>,
+    /// The latest run, if it was a submission.
+    submission: Option<Submission>,
+    /// The test case the results pane is scrolled to.
+    selected_test: usize,
+    /// The latest finished check of the code.
+    analysis: Option<Analysis>,
+    /// A check still running in the background.
+    checking: Option<Receiver<Analysis>>,
+    /// The code as of the last change, and when that was.
+    edited: (String, Instant),
+    /// The code last sent to be checked.
+    checked_code: Option<String>,
+    /// Names to finish the one at the cursor with, after Tab.
+    completion: Option<Popup>,
 }
 
 impl Editor {
-    pub fn new(challenge: Challenge) -> Self {
+    /// Edit a challenge's code in `buffer`, which keeps its undo history
+    /// from any earlier visit.
+    pub fn new(challenge: Challenge, buffer: Buffer) -> Self {
         Editor {
-            code: Vec::new(),
-            cursor_pos: 0,
+            buffer,
             scroll_offset: 0,
             challenge,
             runner: Runner::new(),
-            last_results: None,
+            test_run: None,
+            hidden: 0..0,
+            submission: None,
+            selected_test: 0,
+            analysis: None,
+            checking: None,
+            edited: (String::new(), Instant::now()),
+            checked_code: None,
+            completion: None,
         }
     }
 
+    /// Replace the code; the old code is a Ctrl+Z away.
     pub fn load_code(&mut self, code: String) {
-        self.code = code.chars().collect();
-        self.cursor_pos = self.code.len();
+        self.buffer.set_text(&code);
+    }
+
+    /// The code and its undo history, to pick up where the player left off.
+    pub fn into_buffer(self) -> Buffer {
+        self.buffer
     }
 
     pub fn run(&mut self) -> io::Result<EditorResult> {
         loop {
+            crate::crash::set_current(self.challenge.id, self.get_code());
+            if let Some(run) = &mut self.test_run {
+                run.poll();
+            }
+            self.update_analysis();
+            let (_, height) = terminal::size()?;
+            let page = self.editor_height(height) as usize;
+            self.scroll_to_cursor(page);
             self.render()?;
 
-            let key = super::read_key()?;
+            // Keep redrawing while results are still coming in
+            let running = self.test_run.as_ref().is_some_and(|run| !run.is_finished());
+            let unchecked = self.checked_code.as_deref() != Some(self.edited.0.as_str());
+            let input = if running || unchecked || self.checking.is_some() {
+                match super::poll_input(RESULT_POLL_INTERVAL)? {
+                    Some(input) => input,
+                    None => continue,
+                }
+            } else {
+                super::read_input()?
+            };
+            let key = match input {
+                Input::Key(key) => key,
+                Input::Paste(text) => {
+                    self.buffer.insert_str(&text);
+                    continue;
+                }
+            };
+
+            if let Some(popup) = &mut self.completion {
+                match popup.handle(&key) {
+                    completion::Action::Insert(rest) => {
+                        self.buffer.insert_str(&rest);
+                        self.completion = None;
+                        continue;
+                    }
+                    completion::Action::Moved => continue,
+                    completion::Action::Close => {
+                        self.completion = None;
+                        if key.code == KeyCode::Esc {
+                            continue;
+                        }
+                    }
+                }
+            }
 
+            // Ctrl+C copies a selection, and quits otherwise
             if super::is_ctrl_c(&key) {
-                return Ok(EditorResult::Exit);
+                match self.buffer.selected_text() {
+                    Some(text) => clipboard::copy(&text)?,
+                    None => return Ok(EditorResult::Exit),
+                }
+                continue;
             }
 
-            if super::is_ctrl_r(&key) {
-                self.execute_code();
+            let actions = [
+                Action::Back, Action::Run, Action::Submit, Action::Format, Action::Minify, Action::NextTest,
+                Action::PreviousTest, Action::Undo, Action::Redo, Action::Cut, Action::Paste, Action::SelectAll,
+            ];
+            if let Some(action) = config::keys().action(&key, &actions) {
+                match action {
+                    Action::Back => return Ok(EditorResult::Back),
+                    Action::Run => self.execute_code(),
+                    Action::Submit => self.submit(),
+                    Action::Format => self.format()?,
+                    Action::Minify => self.minify(),
+                    Action::NextTest => {
+                        let tests = self.test_run.as_ref().map_or(0, |run| run.results().len());
+                        let last = tests.saturating_sub(1);
+                        self.selected_test = (self.selected_test + 1).min(last);
+                    }
+                    Action::PreviousTest => self.selected_test = self.selected_test.saturating_sub(1),
+                    Action::Undo => _ = self.buffer.undo(),
+                    Action::Redo => _ = self.buffer.redo(),
+                    Action::Cut => {
+                        if let Some(text) = self.buffer.cut() {
+                            clipboard::copy(&text)?;
+                        }
+                    }
+                    Action::Paste => {
+                        if let Some(text) = clipboard::contents() {
+                            self.buffer.insert_str(&text);
+                        }
+                    }
+                    Action::SelectAll => self.buffer.select_all(),
+                    _ => {}
+                }
                 continue;
             }
 
+            let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+            // Terminals differ in which of these they send for word motions
+            let word = ctrl || key.modifiers.contains(KeyModifiers::ALT);
+            if matches!(
+                key.code,
+                KeyCode::Left | KeyCode::Right | KeyCode::Up | KeyCode::Down
+                    | KeyCode::Home | KeyCode::End | KeyCode::PageUp | KeyCode::PageDown
+            ) {
+                self.buffer.set_selecting(key.modifiers.contains(KeyModifiers::SHIFT));
+            }
             match key.code {
-                KeyCode::Esc => return Ok(EditorResult::Back),
+                KeyCode::Char('w') if ctrl => self.buffer.delete_word_back(),
                 KeyCode::Char(c) => {
-                    if key.modifiers.contains(KeyModifiers::CONTROL) {
+                    if ctrl {
                         continue;
                     }
-                    self.insert_char(c);
+                    self.buffer.type_char(c);
+                }
+                KeyCode::Backspace if word => self.buffer.delete_word_back(),
+                KeyCode::Backspace => self.buffer.backspace(),
+                KeyCode::Delete if word => self.buffer.delete_word_forward(),
+                KeyCode::Delete => self.buffer.delete(),
+                KeyCode::Left if word => self.buffer.word_left(),
+                KeyCode::Left => self.buffer.left(),
+                KeyCode::Right if word => self.buffer.word_right(),
+                KeyCode::Right => self.buffer.right(),
+                KeyCode::Up => self.buffer.up(1),
+                KeyCode::Down => self.buffer.down(1),
+                KeyCode::PageUp => {
+                    self.buffer.up(page);
+                    self.scroll_offset = self.scroll_offset.saturating_sub(page);
                 }
-                KeyCode::Backspace => self.backspace(),
-                KeyCode::Delete => self.delete(),
-                KeyCode::Left => self.move_cursor_left(),
-                KeyCode::Right => self.move_cursor_right(),
-                KeyCode::Home => self.cursor_pos = 0,
-                KeyCode::End => self.cursor_pos = self.code.len(),
-                KeyCode::Enter => self.insert_char('\n'),
-                KeyCode::Tab => {
-                    self.insert_char(' ');
-                    self.insert_char(' ');
+                KeyCode::PageDown => {
+                    self.buffer.down(page);
+                    let last_page = self.buffer.line_count().saturating_sub(page);
+                    self.scroll_offset = (self.scroll_offset + page).min(last_page);
                 }
+                KeyCode::Home => self.buffer.home(),
+                KeyCode::End => self.buffer.end(),
+                KeyCode::Enter => self.buffer.insert('\n'),
+                KeyCode::Tab => self.complete(),
                 _ => {}
             }
         }
     }
 
-    fn insert_char(&mut self, c: char) {
-        self.code.insert(self.cursor_pos, c);
-        self.cursor_pos += 1;
+    /// Pick up a finished check, and start another once the code has been
+    /// left alone for a moment. Only one check runs at a time.
+    fn update_analysis(&mut self) {
+        if let Some(receiver) = &self.checking {
+            match receiver.try_recv() {
+                Ok(analysis) => {
+                    self.analysis = Some(analysis);
+                    self.checking = None;
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => self.checking = None,
+            }
+        }
+
+        let code = self.get_code();
+        if code != self.edited.0 {
+            self.edited = (code, Instant::now());
+        }
+        let (code, edited_at) = &self.edited;
+        if self.checking.is_some() || self.checked_code.as_ref() == Some(code) || edited_at.elapsed() < CHECK_DELAY {
+            return;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let source = code.clone();
+        let signature = self.challenge.signature();
+        // A panic or a failed spawn drops the sender, which leaves the code
+        // without an analysis
+        _ = thread::Builder::new()
+            .name("analysis".to_string())
+            .stack_size(WORKER_STACK_SIZE)
+            .spawn(move || {
+                if let Ok(analysis) = catch_panic(|| Analysis::new(&source, signature.as_ref())) {
+                    _ = sender.send(analysis);
+                }
+            });
+        self.checking = Some(receiver);
+        self.checked_code = Some(code.clone());
+    }
+
+    /// The latest check, if it's of the code as it is now.
+    fn current_analysis(&self) -> Option<&Analysis> {
+        self.analysis.as_ref().filter(|analysis| analysis.source() == self.edited.0)
     }
 
-    fn backspace(&mut self) {
-        if self.cursor_pos > 0 {
-            self.cursor_pos -= 1;
-            self.code.remove(self.cursor_pos);
+    /// Finish the name before the cursor if only one name fits, or list
+    /// the ones that do. Anywhere else, Tab indents.
+    fn complete(&mut self) {
+        let Some(popup) = completion::open(&self.get_code(), self.buffer.cursor_offset()) else {
+            self.buffer.insert(' ');
+            self.buffer.insert(' ');
+            return;
+        };
+        match popup.only() {
+            Some(rest) => self.buffer.insert_str(&rest),
+            None if !popup.is_empty() => self.completion = Some(popup),
+            None => {}
         }
     }
 
-    fn delete(&mut self) {
-        if self.cursor_pos < self.code.len() {
-            self.code.remove(self.cursor_pos);
+    /// Scroll just far enough to show the cursor's line among `page` lines.
+    fn scroll_to_cursor(&mut self, page: usize) {
+        let (line, _) = self.buffer.cursor_line_col();
+        if line < self.scroll_offset {
+            self.scroll_offset = line;
+        } else if line >= self.scroll_offset + page {
+            self.scroll_offset = line + 1 - page.max(1);
         }
     }
 
-    fn move_cursor_left(&mut self) {
-        if self.cursor_pos > 0 {
-            self.cursor_pos -= 1;
+    /// Replace the code with its minified form. Code that doesn't parse is
+    /// left alone; running it shows why.
+    fn minify(&mut self) {
+        if let Ok(minified) = minify::minify(&self.get_code()) {
+            self.load_code(minified);
         }
     }
 
-    fn move_cursor_right(&mut self) {
-        if self.cursor_pos < self.code.len() {
-            self.cursor_pos += 1;
+    /// Lay the code out canonically, to the width of the screen if that's
+    /// narrower than usual.
+    fn format(&mut self) -> io::Result<()> {
+        let (width, _) = terminal::size()?;
+        let width = (width as usize).min(pretty::WIDTH);
+        if let Ok(formatted) = pretty::format_source(&self.get_code(), width, true) {
+            self.load_code(formatted);
         }
+        Ok(())
     }
 
     fn execute_code(&mut self) {
-        let code_str: String = self.code.iter().collect();
-        let results = self.runner.run_tests(&code_str, &self.challenge.test_cases);
-        self.last_results = Some(results);
+        let code_str = self.get_code();
+        // Replacing a previous run cancels whatever it still had running
+        self.selected_test = 0;
+        self.test_run = Some(self.runner.spawn_tests(&code_str, &self.challenge.test_cases, self.challenge.signature().as_ref()));
+        self.hidden = 0..0;
+        self.submission = None;
+    }
+
+    /// Run the visible tests along with the hidden and random ones. Only a
+    /// passing submission counts as solving the challenge.
+    fn submit(&mut self) {
+        let code = self.get_code();
+        let seed = fastrand::u64(..);
+        self.selected_test = 0;
+        self.test_run = Some(self.runner.spawn_submission(&code, &self.challenge, seed, RANDOM_TESTS));
+        let visible = self.challenge.test_cases.len();
+        self.hidden = visible..visible + self.challenge.hidden_tests.len();
+        self.submission = Some(Submission { code, seed });
     }
 
     pub fn get_code(&self) -> String {
-        self.code.iter().collect()
+        self.buffer.text()
     }
 
-    pub fn get_char_count(&self) -> usize {
-        self.runner.count_chars(&self.get_code())
+    /// The code's size in the challenge's metric, if it can be measured.
+    pub fn get_score(&self) -> Option<usize> {
+        self.challenge.metric.measure(&self.get_code())
     }
 
+    /// Whether the current code was submitted and passed every test.
     pub fn all_tests_passed(&self) -> bool {
-        self.last_results.as_ref()
-            .map(|results| results.iter().all(|r| r.passed))
-            .unwrap_or(false)
+        self.submission.as_ref().is_some_and(|submission| submission.code == self.get_code())
+            && self.test_run.as_ref()
+                .map(|run| run.results().iter().all(|r| r.as_ref().is_some_and(|r| r.passed)))
+                .unwrap_or(false)
     }
 
     fn render(&self) -> io::Result<()> {
@@ -136,51 +366,63 @@ impl Editor {
 
         // Code editor section (starting at line 3)
         let editor_start = 3;
-        let editor_height = if self.last_results.is_some() {
-            (height.saturating_sub(10)) / 2
-        } else {
-            height.saturating_sub(5)
-        };
+        let editor_height = self.editor_height(height);
 
         self.render_code_editor(&mut stdout, width, editor_height, editor_start)?;
 
         // Test results section (if available)
-        if let Some(ref results) = self.last_results {
+        if let Some(ref run) = self.test_run {
             let results_start = editor_start + editor_height;
-            self.render_test_results(&mut stdout, width, results, results_start)?;
+            let results_end = height.saturating_sub(2);
+            self.render_test_results(&mut stdout, width, run, results_start, results_end)?;
         }
 
+        self.render_info_line(&mut stdout, width, height.saturating_sub(2))?;
+
         // Status bar (bottom)
         self.render_status_bar(&mut stdout, width, height)?;
 
         // Position cursor in editor
         let (cursor_x, cursor_y) = self.calculate_cursor_position();
+        if let Some(popup) = &self.completion {
+            popup.render(&mut stdout, cursor_x, cursor_y + editor_start, width, height.saturating_sub(1))?;
+        }
         queue!(stdout, cursor::MoveTo(cursor_x, cursor_y + editor_start))?;
 
         stdout.flush()?;
         Ok(())
     }
 
+    /// Lines of code on screen, fewer while test results are showing.
+    fn editor_height(&self, height: u16) -> u16 {
+        if self.test_run.is_some() {
+            (height.saturating_sub(10)) / 2
+        } else {
+            height.saturating_sub(5)
+        }
+    }
+
     fn render_header(&self, stdout: &mut impl Write, width: u16, start_y: u16) -> io::Result<()> {
+        let theme = config::theme();
         // Line 0: Challenge name
         queue!(stdout, cursor::MoveTo(0, start_y), Clear(ClearType::CurrentLine))?;
         queue!(
             stdout,
-            SetForegroundColor(Color::Cyan),
+            SetForegroundColor(theme.title),
             Print(format!("Challenge {}: {}", self.challenge.id, self.challenge.name)),
             ResetColor
         )?;
 
         // Line 1: Description (truncate if too long)
         queue!(stdout, cursor::MoveTo(0, start_y + 1), Clear(ClearType::CurrentLine))?;
-        let desc = if self.challenge.description.len() > width as usize - 2 {
-            format!("{}...", &self.challenge.description[..width as usize - 5])
+        let desc = if self.challenge.description.chars().count() > (width as usize).saturating_sub(2) {
+            format!("{}...", super::truncate(&self.challenge.description, (width as usize).saturating_sub(5)))
         } else {
             self.challenge.description.clone()
         };
         queue!(
             stdout,
-            SetForegroundColor(Color::White),
+            SetForegroundColor(theme.text),
             Print(desc),
             ResetColor
         )?;
@@ -189,7 +431,7 @@ impl Editor {
         queue!(stdout, cursor::MoveTo(0, start_y + 2), Clear(ClearType::CurrentLine))?;
         queue!(
             stdout,
-            SetForegroundColor(Color::Yellow),
+            SetForegroundColor(theme.accent),
             Print(format!("Type: {}", self.challenge.type_signature)),
             ResetColor
         )?;
@@ -198,206 +440,350 @@ impl Editor {
     }
 
     fn render_code_editor(&self, stdout: &mut impl Write, width: u16, height: u16, start_y: u16) -> io::Result<()> {
-        let code_str: String = self.code.iter().collect();
+        let theme = config::theme();
+        let code_str = self.get_code();
         let lines: Vec<&str> = code_str.split('\n').collect();
+        let selection = self.buffer.selection();
+        let brackets = self.buffer.matching_brackets();
+        // Highlighting the whole code at once gets tokens spanning lines right
+        let colors = highlight::colors(&code_str);
+        // Characters with errors, at least one for each error
+        let errors: Vec<Range<usize>> = self.current_analysis()
+            .map(|analysis| analysis.diagnostics().iter()
+                .filter_map(|d| d.span)
+                .map(|span| {
+                    let start = code_str[..span.start].chars().count();
+                    start..start + code_str[span.start..span.end].chars().count().max(1)
+                })
+                .collect())
+            .unwrap_or_default();
+        // Position in the buffer of the start of each line shown
+        let mut line_start: usize = lines.iter().take(self.scroll_offset).map(|l| l.chars().count() + 1).sum();
 
         for i in 0..height as usize {
-            queue!(stdout, cursor::MoveTo(0, start_y + i as u16), Clear(ClearType::CurrentLine))?;
+            let y = start_y + i as u16;
+            queue!(stdout, cursor::MoveTo(0, y), Clear(ClearType::CurrentLine))?;
 
             let line_idx = i + self.scroll_offset;
             if line_idx < lines.len() {
                 let line = lines[line_idx];
                 // Truncate if too long
-                if line.len() > width as usize {
-                    let truncated = &line[..width as usize];
-                    self.render_line_with_highlight(stdout, truncated)?;
-                } else {
-                    self.render_line_with_highlight(stdout, line)?;
+                let shown = super::truncate(line, width as usize);
+                highlight::print_colored(stdout, shown.chars().zip(colors[line_start..].iter().copied()))?;
+
+                // Draw selected text, the brackets around the cursor and
+                // errors over the highlighted line
+                for (col, c) in shown.chars().enumerate() {
+                    let pos = line_start + col;
+                    let background = if selection.as_ref().is_some_and(|range| range.contains(&pos)) {
+                        Some(theme.selection)
+                    } else if brackets.is_some_and(|(a, b)| pos == a || pos == b) {
+                        Some(theme.surface)
+                    } else {
+                        None
+                    };
+                    let error = errors.iter().any(|range| range.contains(&pos));
+                    if background.is_none() && !error {
+                        continue;
+                    }
+                    queue!(stdout, cursor::MoveTo(col as u16, y), SetForegroundColor(colors[pos]))?;
+                    if let Some(background) = background {
+                        set_background(stdout, background)?;
+                    }
+                    if error {
+                        queue!(stdout, SetForegroundColor(theme.error), SetAttribute(Attribute::Underlined))?;
+                    }
+                    queue!(stdout, Print(c), SetAttribute(Attribute::Reset), ResetColor)?;
                 }
+                line_start += line.chars().count() + 1;
             }
         }
 
         Ok(())
     }
 
-    fn render_line_with_highlight(&self, stdout: &mut impl Write, line: &str) -> io::Result<()> {
-        let keywords = ["let", "in", "match", "with", "if", "then", "else", "true", "false"];
-
-        let mut i = 0;
-        let chars: Vec<char> = line.chars().collect();
+    fn render_test_results(&self, stdout: &mut impl Write, width: u16, run: &TestRun, start_y: u16, end_y: u16) -> io::Result<()> {
+        let theme = config::theme();
+        // Lay out every test's lines, then show the window that starts at
+        // the selected test
+        let mut lines: Vec<Line> = Vec::new();
+        let mut test_starts = Vec::new();
+        for (i, (test_case, result)) in run.test_cases().zip(run.results()).enumerate() {
+            test_starts.push(lines.len());
+            let marker = if i == self.selected_test { "▸" } else { " " };
+
+            let Some(result) = result else {
+                lines.push(vec![
+                    (theme.muted, format!("{} … RUN  ", marker)),
+                    (Color::Reset, test_case.description.clone()),
+                ]);
+                continue;
+            };
 
-        while i < chars.len() {
-            let ch = chars[i];
+            let (status, color) = if result.passed {
+                ("✓ PASS", theme.success)
+            } else {
+                ("✗ FAIL", theme.error)
+            };
+            let mut header = vec![
+                (color, format!("{} {} ", marker, status)),
+                (Color::Reset, result.description.clone()),
+            ];
+            if let Some(note) = test_case.compare.note() {
+                header.push((theme.muted, format!(" ({})", note)));
+            }
+            lines.push(header);
 
-            // Check for keywords
-            if ch.is_alphabetic() {
-                let start = i;
-                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
-                    i += 1;
-                }
-                let word: String = chars[start..i].iter().collect();
+            // Hidden tests don't give away their inputs
+            if result.passed || self.hidden.contains(&i) {
+                continue;
+            }
 
-                if keywords.contains(&word.as_str()) {
-                    queue!(stdout, SetForegroundColor(Color::Magenta), Print(&word), ResetColor)?;
-                } else if word.chars().all(|c| c.is_lowercase() || c == '_') && i < chars.len() {
-                    // Likely a function name
-                    queue!(stdout, SetForegroundColor(Color::Green), Print(&word), ResetColor)?;
-                } else {
-                    queue!(stdout, Print(&word))?;
-                }
-            } else if ch.is_numeric() {
-                // Number
-                let start = i;
-                while i < chars.len() && chars[i].is_numeric() {
-                    i += 1;
-                }
-                let num: String = chars[start..i].iter().collect();
-                queue!(stdout, SetForegroundColor(Color::Blue), Print(&num), ResetColor)?;
-            } else if ch == '"' {
-                // String
-                let start = i;
-                i += 1;
-                while i < chars.len() && chars[i] != '"' {
-                    i += 1;
-                }
-                if i < chars.len() {
-                    i += 1;
-                }
-                let string: String = chars[start..i].iter().collect();
-                queue!(stdout, SetForegroundColor(Color::Yellow), Print(&string), ResetColor)?;
-            } else if ch == '\\' {
-                // Lambda
-                queue!(stdout, SetForegroundColor(Color::Red), Print("\\"), ResetColor)?;
-                i += 1;
-            } else if ch == '-' && i + 1 < chars.len() && (chars[i + 1] == '>' || chars[i + 1] == '-') {
-                // Arrow or comment
-                if chars[i + 1] == '>' {
-                    queue!(stdout, SetForegroundColor(Color::Red), Print("->"), ResetColor)?;
-                    i += 2;
-                } else {
-                    // Comment
-                    let comment: String = chars[i..].iter().collect();
-                    queue!(stdout, SetForegroundColor(Color::DarkGrey), Print(&comment), ResetColor)?;
-                    break;
+            if let Some(ref error) = result.error {
+                for (i, line) in self.describe_error(error).into_iter().enumerate() {
+                    if i == 0 {
+                        lines.push(vec![(theme.error, format!("      Error: {}", line))]);
+                    } else {
+                        lines.push(vec![(theme.muted, format!("        {}", line))]);
+                    }
                 }
+            } else if let (Some(expected), Some(actual)) = (&result.expected_value, &result.actual_value) {
+                lines.extend(diff_lines(expected, actual, width as usize));
             } else {
-                queue!(stdout, Print(ch))?;
-                i += 1;
+                lines.push(vec![(Color::Reset, format!("      Expected: {}", result.expected))]);
+                lines.push(vec![(Color::Reset, format!("      Got:      {}", result.actual))]);
             }
         }
 
+        let visible = end_y.saturating_sub(start_y + 1) as usize;
+        let first = test_starts.get(self.selected_test).copied().unwrap_or(0)
+            .min(lines.len().saturating_sub(visible));
+
+        let title = match &self.submission {
+            // The seed makes the random inputs reproducible
+            Some(submission) => format!("Submission Results (seed {}):", submission.seed),
+            None => "Test Results:".to_string(),
+        };
+        let mut header = vec![(theme.title, title)];
+        if lines.len() > visible {
+            let keys = config::keys();
+            let scroll = format!(" ({}/{} to scroll)", keys.label(Action::NextTest), keys.label(Action::PreviousTest));
+            header.push((theme.muted, scroll));
+        }
+        print_line(stdout, &header, width, start_y)?;
+
+        for (offset, line) in lines.iter().skip(first).take(visible).enumerate() {
+            print_line(stdout, line, width, start_y + 1 + offset as u16)?;
+        }
+
         Ok(())
     }
 
-    fn render_test_results(&self, stdout: &mut impl Write, width: u16, results: &[TestResult], start_y: u16) -> io::Result<()> {
-        let mut current_line = start_y;
+    /// The error message with its location, followed by up to three frames
+    /// of the call stack.
+    fn describe_error(&self, error: &TestError) -> Vec<String> {
+        let mut lines = vec![error.to_string()];
 
-        // Header
-        queue!(stdout, cursor::MoveTo(0, current_line), Clear(ClearType::CurrentLine))?;
-        queue!(
-            stdout,
-            SetForegroundColor(Color::Cyan),
-            Print("Test Results:"),
-            ResetColor
-        )?;
-        current_line += 1;
-
-        for result in results.iter() {
-            let status = if result.passed { "✓ PASS" } else { "✗ FAIL" };
-            let color = if result.passed { Color::Green } else { Color::Red };
-
-            queue!(stdout, cursor::MoveTo(0, current_line), Clear(ClearType::CurrentLine))?;
-            queue!(
-                stdout,
-                SetForegroundColor(color),
-                Print(format!("  {} ", status)),
-                ResetColor,
-                Print(&result.description)
-            )?;
-            current_line += 1;
-
-            if !result.passed {
-                if let Some(ref error) = result.error {
-                    queue!(stdout, cursor::MoveTo(0, current_line), Clear(ClearType::CurrentLine))?;
-                    let err_msg = if error.len() > width as usize - 14 {
-                        format!("{}...", &error[..width as usize - 17])
-                    } else {
-                        error.clone()
-                    };
-                    queue!(
-                        stdout,
-                        SetForegroundColor(Color::Red),
-                        Print(format!("      Error: {}", err_msg)),
-                        ResetColor
-                    )?;
-                    current_line += 1;
-                } else {
-                    queue!(stdout, cursor::MoveTo(0, current_line), Clear(ClearType::CurrentLine))?;
-                    queue!(stdout, Print(format!("      Expected: {}", result.expected)))?;
-                    current_line += 1;
-
-                    queue!(stdout, cursor::MoveTo(0, current_line), Clear(ClearType::CurrentLine))?;
-                    queue!(stdout, Print(format!("      Got:      {}", result.actual)))?;
-                    current_line += 1;
+        if let TestError::Runtime(e) = error {
+            let code = self.get_code();
+            if let Some(span) = e.span {
+                let (line, col) = span.line_col(&code);
+                lines[0] = format!("{} (at {}:{})", lines[0], line, col);
+            }
+            for frame in e.trace.iter().take(3) {
+                match frame.call_site {
+                    Some(span) => {
+                        let (line, col) = span.line_col(&code);
+                        lines.push(format!("in {} called at {}:{}", frame.function, line, col));
+                    }
+                    None => lines.push(format!("in {}", frame.function)),
                 }
             }
+            if e.trace.len() > 3 {
+                lines.push(format!("... {} more calls", e.trace.len() - 3));
+            }
         }
 
-        Ok(())
+        lines
+    }
+
+    /// The error at the cursor, or else the type of the name under it, or
+    /// else the first error.
+    fn render_info_line(&self, stdout: &mut impl Write, width: u16, y: u16) -> io::Result<()> {
+        let theme = config::theme();
+        let Some(analysis) = self.current_analysis() else {
+            return print_line(stdout, &Vec::new(), width, y);
+        };
+        let offset = self.buffer.cursor_offset();
+        let diagnostics = analysis.diagnostics();
+        let at_cursor = diagnostics.iter()
+            .find(|d| d.span.is_some_and(|span| span.start <= offset && offset <= span.end));
+        let line = match (at_cursor, analysis.type_at(offset), diagnostics.first()) {
+            (Some(diagnostic), _, _) | (None, None, Some(diagnostic)) => {
+                vec![(theme.error, format!(" {}", one_line(&diagnostic.message)))]
+            }
+            (None, Some((occurrence, ty)), _) => vec![
+                (Color::Reset, format!(" {}", occurrence.name)),
+                (theme.muted, format!(" : {}", ty)),
+            ],
+            (None, None, None) => Vec::new(),
+        };
+        print_line(stdout, &line, width, y)
     }
 
     fn render_status_bar(&self, stdout: &mut impl Write, width: u16, height: u16) -> io::Result<()> {
+        let theme = config::theme();
         queue!(stdout, cursor::MoveTo(0, height - 1), Clear(ClearType::CurrentLine))?;
 
-        let char_count = self.get_char_count();
+        let score = self.get_score();
         let par = self.challenge.par_score;
-        let delta = char_count as i32 - par as i32;
+        // AST nodes can't be counted until the code parses
+        let (score_text, delta_text, delta_color) = match score {
+            Some(score) => {
+                let delta = score as i32 - par as i32;
+                let color = if delta <= 0 {
+                    theme.success
+                } else if delta <= 10 {
+                    theme.warning
+                } else {
+                    theme.error
+                };
+                (score.to_string(), format!("{:+}", delta), color)
+            }
+            None => ("-".to_string(), "-".to_string(), theme.error),
+        };
 
-        let delta_color = if delta <= 0 {
-            Color::Green
-        } else if delta <= 10 {
-            Color::Yellow
-        } else {
-            Color::Red
+        // The last known type, so it doesn't flicker while typing
+        let type_text = match &self.analysis {
+            Some(analysis) => analysis.program_type().map_or("?".to_string(), |ty| ty.to_string()),
+            None => "…".to_string(),
         };
 
+        let keys = config::keys();
         let status_text = format!(
-            " Chars: {} | Par: {} | Δ: {:+} | Ctrl+R: Run | Esc: Back | Ctrl+C: Exit",
-            char_count, par, delta
+            " {}: {} | Par: {} | Δ: {} | {} | {}: Run | {}: Submit | {}: Back | Ctrl+C: Exit",
+            self.challenge.metric, score_text, par, delta_text, type_text,
+            keys.label(Action::Run), keys.label(Action::Submit), keys.label(Action::Back)
         );
 
-        queue!(
-            stdout,
-            SetBackgroundColor(Color::DarkGrey),
-            SetForegroundColor(Color::White)
-        )?;
+        // The whole bar is colored by how far the score is from par
+        set_background(stdout, theme.surface)?;
+        queue!(stdout, SetForegroundColor(delta_color))?;
 
         // Print status, truncate if needed
-        if status_text.len() > width as usize {
-            queue!(stdout, Print(&status_text[..width as usize]))?;
+        let status_len = status_text.chars().count();
+        if status_len > width as usize {
+            queue!(stdout, Print(super::truncate(&status_text, width as usize)))?;
         } else {
             queue!(stdout, Print(&status_text))?;
             // Fill rest of line
-            for _ in status_text.len()..width as usize {
+            for _ in status_len..width as usize {
                 queue!(stdout, Print(" "))?;
             }
         }
 
-        queue!(stdout, ResetColor)?;
+        queue!(stdout, SetAttribute(Attribute::Reset), ResetColor)?;
 
         Ok(())
     }
 
     fn calculate_cursor_position(&self) -> (u16, u16) {
-        let code_before_cursor: String = self.code[..self.cursor_pos].iter().collect();
-        let lines: Vec<&str> = code_before_cursor.split('\n').collect();
-        let y = (lines.len() - 1) as u16;
-        let x = lines.last().unwrap_or(&"").len() as u16;
-        (x, y)
+        let (line, col) = self.buffer.cursor_line_col();
+        (col as u16, line.saturating_sub(self.scroll_offset) as u16)
     }
 }
 
+struct Submission {
+    code: String,
+    seed: u64,
+}
+
 pub enum EditorResult {
     Exit,
     Back,
 }
+
+/// The differences between two values, followed by both values with the
+/// first difference highlighted.
+fn diff_lines(expected: &Data, actual: &Data, width: usize) -> Vec<Line> {
+    let theme = config::theme();
+    let differences = diff::diff(expected, actual);
+    let mut lines: Vec<Line> = differences.iter()
+        .map(|d| vec![(theme.warning, format!("      {}", d))])
+        .collect();
+
+    let highlight = differences.first().map(|d| d.highlight()).unwrap_or_default();
+    lines.push(value_line("      Expected: ", expected, &highlight, theme.success, width));
+    lines.push(value_line("      Got:      ", actual, &highlight, theme.error, width));
+    lines
+}
+
+/// A labelled value, scrolled sideways if needed so the highlighted part is
+/// on screen.
+fn value_line(label: &str, value: &Data, highlight: &[diff::Step], color: Color, width: usize) -> Line {
+    let theme = config::theme();
+    let (text, range) = value.render(highlight);
+    let chars: Vec<char> = text.chars().collect();
+    let mut room = width.saturating_sub(label.chars().count()).max(1);
+    if chars.len() > room {
+        // Leave space for the "…" markers on either side
+        room = room.saturating_sub(2).max(1);
+    }
+
+    let start = match &range {
+        Some(range) if chars.len() > room && range.end > room => {
+            range.start.saturating_sub(room / 3).min(chars.len().saturating_sub(room))
+        }
+        _ => 0,
+    };
+    let end = (start + room).min(chars.len());
+    let piece = |from: usize, to: usize| chars[from.clamp(start, end)..to.clamp(start, end)].iter().collect::<String>();
+
+    let mut line = vec![(Color::Reset, label.to_string())];
+    if start > 0 {
+        line.push((theme.muted, "…".to_string()));
+    }
+    match range {
+        Some(range) => {
+            line.push((Color::Reset, piece(start, range.start)));
+            line.push((color, piece(range.start, range.end)));
+            line.push((Color::Reset, piece(range.end, end)));
+        }
+        None => line.push((Color::Reset, piece(start, end))),
+    }
+    if end < chars.len() {
+        line.push((theme.muted, "…".to_string()));
+    }
+    line
+}
+
+/// A message on one line. Parse errors draw the code they're about over
+/// several lines and end with what was expected.
+fn one_line(message: &str) -> String {
+    match message.lines().last() {
+        Some(last) if message.contains('\n') => format!("Parse error: {}", last.trim().trim_start_matches("= ")),
+        _ => message.to_string(),
+    }
+}
+
+/// Print a line at row `y`, cut off with "..." if it's wider than the screen.
+fn print_line(stdout: &mut impl Write, line: &Line, width: u16, y: u16) -> io::Result<()> {
+    queue!(stdout, cursor::MoveTo(0, y), Clear(ClearType::CurrentLine))?;
+
+    let total: usize = line.iter().map(|(_, text)| text.chars().count()).sum();
+    let mut room = if total > width as usize {
+        (width as usize).saturating_sub(3)
+    } else {
+        total
+    };
+
+    for (color, text) in line {
+        let shown = super::truncate(text, room);
+        room -= shown.chars().count();
+        queue!(stdout, SetForegroundColor(*color), Print(shown), ResetColor)?;
+    }
+    if total > width as usize {
+        queue!(stdout, Print("..."))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use crossterm::{
+    queue,
+    style::{Color, Print, ResetColor, SetForegroundColor},
+};
+use std::io::{self, Write};
+
+use crate::config;
+use crate::lang::interpreter;
+use crate::lang::lexer::{self, TokenKind};
+
+/// The color of each character of `source`. Coloring whole tokens from the
+/// lexer gets strings and comments right across lines, and `--` in a string
+/// stays part of the string.
+pub fn colors(source: &str) -> Vec<Color> {
+    let theme = config::theme();
+    let builtins = interpreter::get_builtin_env();
+    let mut colors = Vec::with_capacity(source.len());
+    for token in lexer::tokenize(source) {
+        let text = token.text(source);
+        let color = match token.kind {
+            TokenKind::Keyword => theme.keyword,
+            TokenKind::Number => theme.number,
+            TokenKind::String => theme.string,
+            TokenKind::Comment => theme.comment,
+            TokenKind::Ident if builtins.contains_key(text) => theme.builtin,
+            TokenKind::Operator if text == "\\" || text == "->" => theme.lambda,
+            TokenKind::Operator => theme.operator,
+            TokenKind::Unknown => theme.error,
+            TokenKind::Whitespace | TokenKind::Ident | TokenKind::Punct => Color::Reset,
+        };
+        colors.extend(text.chars().map(|_| color));
+    }
+    colors
+}
+
+/// Print code in color.
+pub fn print(stdout: &mut impl Write, source: &str) -> io::Result<()> {
+    print_colored(stdout, source.chars().zip(colors(source)))
+}
+
+/// Print characters in their colors, a run of the same color at a time.
+pub fn print_colored(stdout: &mut impl Write, chars: impl IntoIterator<Item = (char, Color)>) -> io::Result<()> {
+    let mut run = String::new();
+    let mut run_color = Color::Reset;
+    for (c, color) in chars {
+        if color != run_color && !run.is_empty() {
+            queue!(stdout, SetForegroundColor(run_color), Print(&run))?;
+            run.clear();
+        }
+        run_color = color;
+        run.push(c);
+    }
+    queue!(stdout, SetForegroundColor(run_color), Print(&run), ResetColor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_colors() {
+        let source = "map (\\s -> s ++ \"--\n\") -- sep\nxs";
+        let colors = colors(source);
+        assert_eq!(colors.len(), source.chars().count());
+        let color_at = |text: &str| colors[source.find(text).unwrap()];
+        assert_eq!(color_at("map"), Color::Green);
+        assert_eq!(color_at("\\"), Color::Red);
+        assert_eq!(color_at("++"), Color::Cyan);
+        // The string runs over the line break, and its dashes aren't a comment
+        assert_eq!(color_at("--\n\""), Color::Yellow);
+        assert_eq!(colors[source.find('\n').unwrap() + 1], Color::Yellow);
+        assert_eq!(color_at("-- sep"), Color::DarkGrey);
+        assert_eq!(color_at("xs"), Color::Reset);
+    }
+}
@@ -0,0 +1,239 @@
+use anyhow::{anyhow, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Something a key can be bound to. Typing, moving the cursor, Ctrl+C,
+/// which copies or quits, and the y/n answer to reopening recovered code
+/// aren't remappable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Move up the menu or scroll the reference.
+    Up,
+    Down,
+    /// Open the challenge selected in the menu.
+    Select,
+    Repl,
+    Help,
+    Leaderboard,
+    /// Leave the game from the menu, or the REPL when nothing is typed.
+    Quit,
+    /// Leave the editor or the REPL for the menu.
+    Back,
+    Run,
+    Submit,
+    Format,
+    Minify,
+    NextTest,
+    PreviousTest,
+    Undo,
+    Redo,
+    Cut,
+    Paste,
+    SelectAll,
+    /// Show the leaderboard in the next metric.
+    NextMetric,
+    PreviousMetric,
+}
+
+/// A key with the modifiers held down with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyBinding {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    /// Letters come lowercase with Shift, however the terminal reported
+    /// them; other characters say whether Shift was needed themselves.
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let modifiers = modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        match code {
+            KeyCode::Char(c) if c.is_alphabetic() => {
+                let shift = if c.is_uppercase() { KeyModifiers::SHIFT } else { KeyModifiers::NONE };
+                KeyBinding { code: KeyCode::Char(c.to_ascii_lowercase()), modifiers: modifiers | shift }
+            }
+            KeyCode::Char(_) => KeyBinding { code, modifiers: modifiers - KeyModifiers::SHIFT },
+            // Terminals report Shift+Tab as a key of its own
+            KeyCode::BackTab => KeyBinding { code: KeyCode::Tab, modifiers: modifiers | KeyModifiers::SHIFT },
+            _ => KeyBinding { code, modifiers },
+        }
+    }
+}
+
+/// Keys other than characters, by the names used in the config file and
+/// the ones shown on screen.
+const NAMED_KEYS: &[(&str, &str, KeyCode)] = &[
+    ("up", "↑", KeyCode::Up),
+    ("down", "↓", KeyCode::Down),
+    ("left", "←", KeyCode::Left),
+    ("right", "→", KeyCode::Right),
+    ("enter", "Enter", KeyCode::Enter),
+    ("esc", "Esc", KeyCode::Esc),
+    ("tab", "Tab", KeyCode::Tab),
+    ("backspace", "Backspace", KeyCode::Backspace),
+    ("delete", "Del", KeyCode::Delete),
+    ("home", "Home", KeyCode::Home),
+    ("end", "End", KeyCode::End),
+    ("pageup", "PgUp", KeyCode::PageUp),
+    ("pagedown", "PgDn", KeyCode::PageDown),
+    ("space", "Space", KeyCode::Char(' ')),
+];
+
+/// Parses keys like `k`, `esc`, `f5` and `ctrl+shift+z`.
+impl FromStr for KeyBinding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts: Vec<&str> = s.split('+').collect();
+        // `+` itself, alone or after modifiers
+        if s.ends_with("++") || s == "+" {
+            parts.truncate(parts.len() - 2);
+            parts.push("+");
+        }
+        let (key, modifier_names) = parts.split_last().ok_or_else(|| anyhow!("Empty key"))?;
+
+        let mut modifiers = KeyModifiers::NONE;
+        for name in modifier_names {
+            modifiers |= match name.to_lowercase().as_str() {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(anyhow!("Unknown modifier '{}' in '{}'", name, s)),
+            };
+        }
+
+        let lower = key.to_lowercase();
+        let code = if let Some(&(_, _, code)) = NAMED_KEYS.iter().find(|(name, _, _)| *name == lower) {
+            code
+        } else if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse().ok()) {
+            KeyCode::F(n)
+        } else {
+            let mut chars = key.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => KeyCode::Char(c),
+                _ => return Err(anyhow!("Unknown key '{}'", s)),
+            }
+        };
+        Ok(KeyBinding::new(code, modifiers))
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (modifier, name) in [(KeyModifiers::CONTROL, "Ctrl"), (KeyModifiers::ALT, "Alt"), (KeyModifiers::SHIFT, "Shift")] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}+", name)?;
+            }
+        }
+        match (self.code, NAMED_KEYS.iter().find(|(_, _, code)| *code == self.code)) {
+            (_, Some((_, shown, _))) => write!(f, "{}", shown),
+            (KeyCode::Char(c), None) => write!(f, "{}", c.to_uppercase()),
+            (KeyCode::F(n), None) => write!(f, "F{}", n),
+            (code, None) => write!(f, "{:?}", code),
+        }
+    }
+}
+
+/// Which keys do what.
+#[derive(Debug, Clone)]
+pub struct Keys {
+    bindings: HashMap<Action, Vec<KeyBinding>>,
+}
+
+impl Default for Keys {
+    fn default() -> Self {
+        let bindings = [
+            (Action::Up, &["up", "k"][..]),
+            (Action::Down, &["down", "j"]),
+            (Action::Select, &["enter"]),
+            (Action::Repl, &["r"]),
+            (Action::Help, &["h"]),
+            (Action::Leaderboard, &["l"]),
+            (Action::Quit, &["q", "esc"]),
+            (Action::Back, &["esc"]),
+            (Action::Run, &["ctrl+r"]),
+            (Action::Submit, &["ctrl+s"]),
+            (Action::Format, &["ctrl+f"]),
+            (Action::Minify, &["ctrl+g"]),
+            (Action::NextTest, &["ctrl+n"]),
+            (Action::PreviousTest, &["ctrl+p"]),
+            (Action::Undo, &["ctrl+z"]),
+            (Action::Redo, &["ctrl+y", "ctrl+shift+z"]),
+            (Action::Cut, &["ctrl+x"]),
+            (Action::Paste, &["ctrl+v"]),
+            (Action::SelectAll, &["ctrl+a"]),
+            (Action::NextMetric, &["tab", "right"]),
+            (Action::PreviousMetric, &["shift+tab", "left"]),
+        ];
+        Keys {
+            bindings: bindings.into_iter()
+                .map(|(action, keys)| (action, keys.iter().map(|k| k.parse().unwrap()).collect()))
+                .collect(),
+        }
+    }
+}
+
+impl Keys {
+    /// Bind `action` to these keys instead of its defaults.
+    pub fn bind(&mut self, action: Action, keys: Vec<KeyBinding>) {
+        self.bindings.insert(action, keys);
+    }
+
+    /// The first of `actions` that `key` is bound to.
+    pub fn action(&self, key: &KeyEvent, actions: &[Action]) -> Option<Action> {
+        let pressed = KeyBinding::new(key.code, key.modifiers);
+        actions.iter().copied().find(|action| self.bindings.get(action).is_some_and(|keys| keys.contains(&pressed)))
+    }
+
+    /// The main key for `action`, to show in help text.
+    pub fn label(&self, action: Action) -> String {
+        match self.bindings.get(&action).and_then(|keys| keys.first()) {
+            Some(key) => key.to_string(),
+            None => "(unbound)".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn test_bindings() {
+        let keys = Keys::default();
+        let editor = [Action::Run, Action::Undo, Action::Redo];
+        assert_eq!(keys.action(&press(KeyCode::Char('r'), KeyModifiers::CONTROL), &editor), Some(Action::Run));
+        assert_eq!(keys.action(&press(KeyCode::Char('r'), KeyModifiers::NONE), &editor), None);
+        assert_eq!(keys.action(&press(KeyCode::Char('z'), KeyModifiers::CONTROL), &editor), Some(Action::Undo));
+        // Terminals report Ctrl+Shift+Z either way
+        assert_eq!(keys.action(&press(KeyCode::Char('Z'), KeyModifiers::CONTROL), &editor), Some(Action::Redo));
+        assert_eq!(
+            keys.action(&press(KeyCode::Char('z'), KeyModifiers::CONTROL | KeyModifiers::SHIFT), &editor),
+            Some(Action::Redo)
+        );
+        // The menu's `r` isn't one of the editor's actions
+        assert_eq!(keys.action(&press(KeyCode::Char('r'), KeyModifiers::NONE), &[Action::Repl]), Some(Action::Repl));
+        let metrics = [Action::NextMetric, Action::PreviousMetric];
+        assert_eq!(keys.action(&press(KeyCode::BackTab, KeyModifiers::SHIFT), &metrics), Some(Action::PreviousMetric));
+        assert_eq!(keys.action(&press(KeyCode::BackTab, KeyModifiers::NONE), &metrics), Some(Action::PreviousMetric));
+
+        assert_eq!(keys.label(Action::Redo), "Ctrl+Y");
+        assert_eq!(keys.label(Action::Up), "↑");
+        assert_eq!(keys.label(Action::Quit), "Q");
+        assert_eq!(keys.label(Action::Back), "Esc");
+        assert_eq!(keys.label(Action::PreviousMetric), "Shift+Tab");
+        assert_eq!("alt+f5".parse::<KeyBinding>().unwrap().to_string(), "Alt+F5");
+        assert_eq!("ctrl++".parse::<KeyBinding>().unwrap().to_string(), "Ctrl++");
+        assert_eq!("PageDown".parse::<KeyBinding>().unwrap().to_string(), "PgDn");
+        assert!("ctrl+".parse::<KeyBinding>().is_err());
+        assert!("hyper+x".parse::<KeyBinding>().is_err());
+    }
+}
@@ -1,14 +1,15 @@
 use crossterm::{
     cursor,
-    event::{KeyCode, KeyEvent},
     queue,
-    style::{Color, Print, ResetColor, SetForegroundColor},
+    style::{Print, ResetColor, SetForegroundColor},
     terminal::{self, Clear, ClearType},
 };
 use std::io::{self, Write};
 
+use super::keys::Action;
 use crate::challenges::Challenge;
-use crate::storage::{PersonalBest, Storage};
+use crate::config;
+use crate::storage::Storage;
 
 pub struct Menu {
     challenges: Vec<Challenge>,
@@ -20,7 +21,7 @@ pub struct Menu {
 impl Menu {
     pub fn new(challenges: Vec<Challenge>) -> io::Result<Self> {
         let storage = Storage::new().map_err(|e| {
-            io::Error::new(io::ErrorKind::Other, format!("Database error: {}", e))
+            io::Error::other(format!("Database error: {}", e))
         })?;
 
         Ok(Menu {
@@ -41,17 +42,20 @@ impl Menu {
                 return Ok(MenuAction::Exit);
             }
 
-            match key.code {
-                KeyCode::Up | KeyCode::Char('k') => self.move_up(),
-                KeyCode::Down | KeyCode::Char('j') => self.move_down(),
-                KeyCode::Enter => {
-                    let challenge = self.challenges[self.selected].clone();
+            let actions = [
+                Action::Up, Action::Down, Action::Select, Action::Repl, Action::Help, Action::Leaderboard, Action::Quit,
+            ];
+            match config::keys().action(&key, &actions) {
+                Some(Action::Up) => self.move_up(),
+                Some(Action::Down) => self.move_down(),
+                Some(Action::Select) => {
+                    let challenge = Box::new(self.challenges[self.selected].clone());
                     return Ok(MenuAction::SelectChallenge(challenge));
                 }
-                KeyCode::Char('r') => return Ok(MenuAction::OpenRepl),
-                KeyCode::Char('h') => return Ok(MenuAction::OpenReference),
-                KeyCode::Char('l') => return Ok(MenuAction::ShowLeaderboard),
-                KeyCode::Char('q') | KeyCode::Esc => return Ok(MenuAction::Exit),
+                Some(Action::Repl) => return Ok(MenuAction::OpenRepl),
+                Some(Action::Help) => return Ok(MenuAction::OpenReference),
+                Some(Action::Leaderboard) => return Ok(MenuAction::ShowLeaderboard),
+                Some(Action::Quit) => return Ok(MenuAction::Exit),
                 _ => {}
             }
         }
@@ -95,10 +99,12 @@ impl Menu {
         self.render_challenge_list(&mut stdout, list_height, list_start_y)?;
 
         // Help bar
+        let theme = config::theme();
+        let keys = config::keys();
         queue!(stdout, cursor::MoveTo(0, height - 2))?;
         queue!(
             stdout,
-            SetForegroundColor(Color::DarkGrey),
+            SetForegroundColor(theme.muted),
             Print("─".repeat(width as usize)),
             ResetColor,
             Print("\n")
@@ -106,8 +112,12 @@ impl Menu {
 
         queue!(
             stdout,
-            SetForegroundColor(Color::White),
-            Print(" ↑/↓: Navigate | Enter: Select | R: REPL | H: Help | L: Leaderboard | Q: Quit"),
+            SetForegroundColor(theme.text),
+            Print(format!(
+                " {}/{}: Navigate | {}: Select | {}: REPL | {}: Help | {}: Leaderboard | {}: Quit",
+                keys.label(Action::Up), keys.label(Action::Down), keys.label(Action::Select), keys.label(Action::Repl),
+                keys.label(Action::Help), keys.label(Action::Leaderboard), keys.label(Action::Quit)
+            )),
             ResetColor
         )?;
 
@@ -118,7 +128,7 @@ impl Menu {
     fn render_title(&self, stdout: &mut impl Write) -> io::Result<()> {
         queue!(
             stdout,
-            SetForegroundColor(Color::Cyan),
+            SetForegroundColor(config::theme().title),
             Print("╔════════════════════════════════════════════════════════════╗\n"),
             Print("║          CODE GOLF - Functional Language Edition          ║\n"),
             Print("╚════════════════════════════════════════════════════════════╝\n"),
@@ -136,7 +146,7 @@ impl Menu {
 
         queue!(
             stdout,
-            SetForegroundColor(Color::Yellow),
+            SetForegroundColor(config::theme().accent),
             Print(format!(" Total Score: {} | Completed: {}/{} | Beat Par: {}\n\n",
                 total_score, completed, self.challenges.len(), beat_par)),
             ResetColor
@@ -146,6 +156,7 @@ impl Menu {
     }
 
     fn render_challenge_list(&mut self, stdout: &mut impl Write, height: u16, start_y: u16) -> io::Result<()> {
+        let theme = config::theme();
         let visible_items = height as usize;
         let end = (self.scroll_offset + visible_items).min(self.challenges.len());
 
@@ -164,16 +175,13 @@ impl Menu {
 
             // Selection marker
             if is_selected {
-                queue!(stdout, SetForegroundColor(Color::Green), Print(" > "))?;
+                queue!(stdout, SetForegroundColor(theme.success), Print(" > "))?;
             } else {
                 queue!(stdout, Print("   "))?;
             }
 
             // Challenge number and name (shortened to fit better)
-            let mut name_color = Color::White;
-            if challenge.is_tutorial {
-                name_color = Color::Cyan;
-            }
+            let name_color = if challenge.is_tutorial { theme.title } else { theme.text };
 
             let name = if challenge.name.len() > 25 {
                 format!("{:.22}...", challenge.name)
@@ -192,7 +200,7 @@ impl Menu {
             let stars = "★".repeat(challenge.difficulty) + &"☆".repeat(5 - challenge.difficulty);
             queue!(
                 stdout,
-                SetForegroundColor(Color::Yellow),
+                SetForegroundColor(theme.accent),
                 Print(format!(" {} ", stars)),
                 ResetColor
             )?;
@@ -200,35 +208,36 @@ impl Menu {
             // Par score
             queue!(
                 stdout,
-                SetForegroundColor(Color::DarkGrey),
+                SetForegroundColor(theme.muted),
                 Print(format!("Par:{:3} ", challenge.par_score)),
                 ResetColor
             )?;
 
             // Personal best
             if let Some(ref pb) = best {
+                let score = pb.scores.get(challenge.metric);
                 let color = if pb.beat_par {
-                    Color::Green
-                } else if pb.char_count <= challenge.par_score + 10 {
-                    Color::Yellow
+                    theme.success
+                } else if score <= challenge.par_score + 10 {
+                    theme.warning
                 } else {
-                    Color::Red
+                    theme.error
                 };
 
                 queue!(
                     stdout,
                     SetForegroundColor(color),
-                    Print(format!("Best:{:3}", pb.char_count)),
+                    Print(format!("Best:{:3}", score)),
                     ResetColor
                 )?;
 
                 if pb.beat_par {
-                    queue!(stdout, SetForegroundColor(Color::Green), Print(" ✓"), ResetColor)?;
+                    queue!(stdout, SetForegroundColor(theme.success), Print(" ✓"), ResetColor)?;
                 }
             } else {
                 queue!(
                     stdout,
-                    SetForegroundColor(Color::DarkGrey),
+                    SetForegroundColor(theme.muted),
                     Print("Best:---"),
                     ResetColor
                 )?;
@@ -244,7 +253,7 @@ impl Menu {
 }
 
 pub enum MenuAction {
-    SelectChallenge(Challenge),
+    SelectChallenge(Box<Challenge>),
     OpenRepl,
     OpenReference,
     ShowLeaderboard,
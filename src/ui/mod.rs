@@ -1,24 +1,33 @@
+pub mod buffer;
+pub mod clipboard;
+pub mod completion;
 pub mod editor;
+pub mod highlight;
+pub mod keys;
 pub mod menu;
 pub mod repl;
 pub mod reference;
+pub mod theme;
 
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
+    event::{self, DisableBracketedPaste, EnableBracketedPaste, Event, KeyCode, KeyEvent, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use std::io::{self, Write};
+use std::io;
+use std::time::Duration;
 
 pub fn setup_terminal() -> io::Result<()> {
     enable_raw_mode()?;
-    execute!(io::stdout(), EnterAlternateScreen)?;
+    // Pasted text arrives as one event rather than as typed keys, which
+    // would be auto-indented and auto-closed
+    execute!(io::stdout(), EnterAlternateScreen, EnableBracketedPaste)?;
     Ok(())
 }
 
 pub fn restore_terminal() -> io::Result<()> {
     disable_raw_mode()?;
-    execute!(io::stdout(), LeaveAlternateScreen)?;
+    execute!(io::stdout(), DisableBracketedPaste, LeaveAlternateScreen)?;
     Ok(())
 }
 
@@ -30,10 +39,42 @@ pub fn read_key() -> io::Result<KeyEvent> {
     }
 }
 
+/// A key press, or text pasted into the terminal.
+pub enum Input {
+    Key(KeyEvent),
+    Paste(String),
+}
+
+/// Wait up to `timeout` for a key press or a paste.
+pub fn poll_input(timeout: Duration) -> io::Result<Option<Input>> {
+    if event::poll(timeout)? {
+        match event::read()? {
+            Event::Key(key) => return Ok(Some(Input::Key(key))),
+            // Terminals send pasted line breaks as carriage returns
+            Event::Paste(text) => return Ok(Some(Input::Paste(text.replace("\r\n", "\n").replace('\r', "\n")))),
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+pub fn read_input() -> io::Result<Input> {
+    loop {
+        if let Some(input) = poll_input(Duration::from_secs(60))? {
+            return Ok(input);
+        }
+    }
+}
+
 pub fn is_ctrl_c(key: &KeyEvent) -> bool {
     key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL)
 }
 
-pub fn is_ctrl_r(key: &KeyEvent) -> bool {
-    key.code == KeyCode::Char('r') && key.modifiers.contains(KeyModifiers::CONTROL)
+/// The longest prefix of `s` that fits in `width` columns, cut on a char
+/// boundary so multi-byte text can't panic.
+pub fn truncate(s: &str, width: usize) -> &str {
+    match s.char_indices().nth(width) {
+        Some((end, _)) => &s[..end],
+        None => s,
+    }
 }
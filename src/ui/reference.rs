@@ -2,11 +2,15 @@ use crossterm::{
     cursor,
     event::KeyCode,
     queue,
-    style::{Color, Print, ResetColor, SetForegroundColor},
+    style::{Print, ResetColor, SetForegroundColor},
     terminal::{self, Clear, ClearType},
 };
 use std::io::{self, Write};
 
+use super::highlight;
+use super::keys::Action;
+use crate::config;
+
 pub struct Reference {
     scroll_offset: usize,
     content: Vec<String>,
@@ -29,103 +33,127 @@ impl Reference {
             "".to_string(),
             "BASIC SYNTAX".to_string(),
             "".to_string(),
-            "  Numbers:      42, -17, 0".to_string(),
-            "  Booleans:     true, false".to_string(),
-            "  Strings:      \"hello world\"".to_string(),
-            "  Lists:        [1, 2, 3], [], [1..10]".to_string(),
-            "  Ranges:       1..5  produces [1, 2, 3, 4, 5]".to_string(),
+            "  Numbers:      `42, -17, 0`".to_string(),
+            "  Booleans:     `true, false`".to_string(),
+            "  Strings:      `\"hello world\"`".to_string(),
+            "  Lists:        `[1, 2, 3], [], [1..10]`".to_string(),
+            "  Ranges:       `1..5`  produces `[1, 2, 3, 4, 5]`".to_string(),
             "".to_string(),
             "FUNCTIONS".to_string(),
             "".to_string(),
-            "  Lambda:       \\x -> x * 2".to_string(),
-            "  Multi-arg:    \\x y -> x + y".to_string(),
-            "  Let binding:  let double = \\x -> x * 2 in double 5".to_string(),
-            "  Application:  map (\\x -> x * 2) [1, 2, 3]".to_string(),
+            "  Lambda:       `\\x -> x * 2`".to_string(),
+            "  Multi-arg:    `\\x y -> x + y`".to_string(),
+            "  Let binding:  `let double = \\x -> x * 2 in double 5`".to_string(),
+            "  Application:  `map (\\x -> x * 2) [1, 2, 3]`".to_string(),
             "".to_string(),
             "OPERATORS".to_string(),
             "".to_string(),
-            "  Arithmetic:   + - * / % ^".to_string(),
-            "  Comparison:   == != < > <= >=".to_string(),
-            "  Logical:      && ||".to_string(),
-            "  List ops:     :: (cons), ++ (concat)".to_string(),
-            "  Composition:  >> (forward), << (backward)".to_string(),
+            "  Arithmetic:   `+ - * / % ^`".to_string(),
+            "  Comparison:   `== != < > <= >=`".to_string(),
+            "  Logical:      `&& ||`".to_string(),
+            "  List ops:     `::` (cons), `++` (concat)".to_string(),
+            "  Composition:  `f >> g`  is `\\x -> g (f x)`,  `f << g`  is `\\x -> f (g x)`".to_string(),
+            "  Pipeline:     `xs |> map f |> sum`   (`x |> f` is `f x`)".to_string(),
+            "  Application:  `sum $ map f $ take 3 xs`   (`f $ x` is `f x`)".to_string(),
             "".to_string(),
             "PATTERN MATCHING".to_string(),
             "".to_string(),
-            "  match list with".to_string(),
-            "    [] -> 0".to_string(),
-            "    h::t -> h + sum t".to_string(),
+            "  `match list with`".to_string(),
+            "    `[] -> 0`".to_string(),
+            "    `h::t -> h + sum t`".to_string(),
+            "".to_string(),
+            "  Patterns: `_`, variable, number, `[1,2,3], h::tail, { age = a }`".to_string(),
             "".to_string(),
-            "  Patterns: _, variable, number, [1,2,3], h::tail".to_string(),
+            "RECORDS".to_string(),
+            "".to_string(),
+            "  Literal:      `{ name = \"Ada\", age = 36 }`".to_string(),
+            "  Access:       `p.age`".to_string(),
+            "  Update:       `{ p | age = p.age + 1 }`".to_string(),
+            "  Pattern:      `match p with { age = 0 } -> 0 | { age } -> age`".to_string(),
             "".to_string(),
             "LIST COMPREHENSIONS".to_string(),
             "".to_string(),
-            "  [x * 2 | x <- [1..10]]".to_string(),
-            "  [x * 2 | x <- list, x > 5]".to_string(),
-            "  [x + y | x <- [1,2], y <- [10,20]]  (nested)".to_string(),
+            "  `[x * 2 | x <- [1..10]]`".to_string(),
+            "  `[x * 2 | x <- list, x > 5]`".to_string(),
+            "  `[x + y | x <- [1,2], y <- [10,20]]`  (nested)".to_string(),
             "".to_string(),
             "BUILT-IN FUNCTIONS".to_string(),
             "".to_string(),
-            "  map f list          - Apply f to each element".to_string(),
-            "  filter f list       - Keep elements where f returns true".to_string(),
-            "  fold f init list    - Left fold with accumulator".to_string(),
-            "  foldr f init list   - Right fold".to_string(),
-            "  zip list1 list2     - Combine into pairs".to_string(),
-            "  take n list         - First n elements".to_string(),
-            "  drop n list         - Skip first n elements".to_string(),
-            "  reverse list        - Reverse order".to_string(),
-            "  sort list           - Sort numbers ascending".to_string(),
-            "  length list         - Count elements".to_string(),
-            "  head list           - First element".to_string(),
-            "  tail list           - All but first".to_string(),
-            "  sum list            - Sum of numbers".to_string(),
-            "  product list        - Product of numbers".to_string(),
-            "  concat lists        - Flatten one level".to_string(),
-            "  elem item list      - Check if item in list".to_string(),
+            "  `map f list`          - Apply f to each element".to_string(),
+            "  `filter f list`       - Keep elements where f returns true".to_string(),
+            "  `fold f init list`    - Left fold with accumulator".to_string(),
+            "  `foldr f init list`   - Right fold".to_string(),
+            "  `zip list1 list2`     - Combine into `[x, y]` pairs".to_string(),
+            "  `take n list`         - First n elements".to_string(),
+            "  `drop n list`         - Skip first n elements".to_string(),
+            "  `reverse list`        - Reverse order".to_string(),
+            "  `sort list`           - Sort numbers ascending".to_string(),
+            "  `length list`         - Count elements".to_string(),
+            "  `head list`           - First element".to_string(),
+            "  `tail list`           - All but first".to_string(),
+            "  `sum list`            - Sum of numbers".to_string(),
+            "  `product list`        - Product of numbers".to_string(),
+            "  `concat lists`        - Flatten one level".to_string(),
+            "  `elem item list`      - Check if item in list".to_string(),
+            "".to_string(),
+            "MAPS AND SETS".to_string(),
+            "".to_string(),
+            "  `#{1 => \"a\"}, #{}`      - Map literal (Map Int String)".to_string(),
+            "  `#[1, 2], #[]`        - Set literal (Set Int)".to_string(),
+            "  `fromList entries`    - Map from [{ key = k, value = v }]".to_string(),
+            "  `toList m`            - Entries as [{ key = k, value = v }]".to_string(),
+            "  `insert k v m`        - Add or replace an entry".to_string(),
+            "  `insertWith f k v m`  - Combine with f new old if k exists".to_string(),
+            "  `lookup k m`          - Value for k (error if missing)".to_string(),
+            "  `keys m, values m`    - Keys / values in key order".to_string(),
+            "  `set list`            - Set of the list's elements".to_string(),
+            "  `add x s`             - Add an element".to_string(),
+            "  `member x s`          - Check if x is in s".to_string(),
+            "  `elems s`             - Elements in ascending order".to_string(),
             "".to_string(),
             "EXAMPLES".to_string(),
             "".to_string(),
             "  Sum a list:".to_string(),
-            "    fold (\\acc x -> acc + x) 0".to_string(),
-            "    sum  (builtin shortcut)".to_string(),
+            "    `fold (\\acc x -> acc + x) 0`".to_string(),
+            "    `sum`  (builtin shortcut)".to_string(),
             "".to_string(),
             "  Double all elements:".to_string(),
-            "    map (\\x -> x * 2)".to_string(),
-            "    \\list -> [x * 2 | x <- list]".to_string(),
+            "    `map (\\x -> x * 2)`".to_string(),
+            "    `\\list -> [x * 2 | x <- list]`".to_string(),
             "".to_string(),
             "  Filter evens:".to_string(),
-            "    filter (\\x -> x % 2 == 0)".to_string(),
+            "    `filter (\\x -> x % 2 == 0)`".to_string(),
             "".to_string(),
             "  Quicksort:".to_string(),
-            "    let qsort = \\list -> match list with".to_string(),
-            "      [] -> []".to_string(),
-            "      p::rest ->".to_string(),
-            "        let smaller = filter (\\x -> x < p) rest in".to_string(),
-            "        let larger = filter (\\x -> x >= p) rest in".to_string(),
-            "        qsort smaller ++ [p] ++ qsort larger".to_string(),
-            "    in qsort".to_string(),
+            "    `let qsort = \\list -> match list with`".to_string(),
+            "      `[] -> []`".to_string(),
+            "      `p::rest ->`".to_string(),
+            "        `let smaller = filter (\\x -> x < p) rest in`".to_string(),
+            "        `let larger = filter (\\x -> x >= p) rest in`".to_string(),
+            "        `qsort smaller ++ [p] ++ qsort larger`".to_string(),
+            "    `in qsort`".to_string(),
             "".to_string(),
             "  Fibonacci:".to_string(),
-            "    let fib = \\n -> match n with".to_string(),
-            "      0 -> 0".to_string(),
-            "      1 -> 1".to_string(),
-            "      _ -> fib (n - 1) + fib (n - 2)".to_string(),
-            "    in fib".to_string(),
+            "    `let fib = \\n -> match n with`".to_string(),
+            "      `0 -> 0`".to_string(),
+            "      `1 -> 1`".to_string(),
+            "      `_ -> fib (n - 1) + fib (n - 2)`".to_string(),
+            "    `in fib`".to_string(),
             "".to_string(),
             "PARTIAL APPLICATION".to_string(),
             "".to_string(),
             "  All functions support partial application:".to_string(),
-            "    add3 = (\\x y z -> x + y + z) 1 2    -- returns (\\z -> 1 + 2 + z)".to_string(),
-            "    map (+ 3) [1,2,3]   -- Note: (+) is not an operator section".to_string(),
+            "    `add3 = (\\x y z -> x + y + z) 1 2    -- returns (\\z -> 1 + 2 + z)`".to_string(),
+            "    `map (+ 3) [1,2,3]   -- Note: (+) is not an operator section`".to_string(),
             "".to_string(),
             "TIPS FOR CODE GOLF".to_string(),
             "".to_string(),
-            "  1. Use partial application: map (* 2) instead of map (\\x -> x * 2)".to_string(),
+            "  1. Use partial application: `map (* 2)` instead of `map (\\x -> x * 2)`".to_string(),
             "  2. List comprehensions are often shorter than map/filter".to_string(),
             "  3. Pattern matching can replace if-then-else".to_string(),
             "  4. Fold can implement most list operations".to_string(),
-            "  5. Use >> for function composition: x >> f >> g".to_string(),
-            "  6. Built-ins like sum, product save characters".to_string(),
+            "  5. Compose point-free: `filter f >> sum` needs no lambda".to_string(),
+            "  6. Built-ins like `sum`, `product` save characters".to_string(),
             "".to_string(),
             "Press any key to return to menu...".to_string(),
         ]
@@ -137,32 +165,32 @@ impl Reference {
 
             let key = super::read_key()?;
 
-            match key.code {
-                KeyCode::Up | KeyCode::Char('k') => {
+            match (config::keys().action(&key, &[Action::Up, Action::Down]), key.code) {
+                (Some(Action::Up), _) => {
                     if self.scroll_offset > 0 {
                         self.scroll_offset -= 1;
                     }
                 }
-                KeyCode::Down | KeyCode::Char('j') => {
+                (Some(Action::Down), _) => {
                     let (_, height) = terminal::size()?;
                     let visible_lines = height.saturating_sub(2) as usize;
                     if self.scroll_offset + visible_lines < self.content.len() {
                         self.scroll_offset += 1;
                     }
                 }
-                KeyCode::PageUp => {
+                (_, KeyCode::PageUp) => {
                     self.scroll_offset = self.scroll_offset.saturating_sub(10);
                 }
-                KeyCode::PageDown => {
+                (_, KeyCode::PageDown) => {
                     let (_, height) = terminal::size()?;
                     let visible_lines = height.saturating_sub(2) as usize;
                     self.scroll_offset = (self.scroll_offset + 10)
                         .min(self.content.len().saturating_sub(visible_lines));
                 }
-                KeyCode::Home => {
+                (_, KeyCode::Home) => {
                     self.scroll_offset = 0;
                 }
-                KeyCode::End => {
+                (_, KeyCode::End) => {
                     let (_, height) = terminal::size()?;
                     let visible_lines = height.saturating_sub(2) as usize;
                     self.scroll_offset = self.content.len().saturating_sub(visible_lines);
@@ -175,6 +203,8 @@ impl Reference {
     }
 
     fn render(&self) -> io::Result<()> {
+        let theme = config::theme();
+        let keys = config::keys();
         let mut stdout = io::stdout();
         let (width, height) = terminal::size()?;
 
@@ -189,20 +219,24 @@ impl Reference {
             let line = &self.content[i];
 
             // Truncate if too long
-            let display_line = if line.len() > width as usize {
-                &line[..width as usize]
-            } else {
-                line
-            };
+            let display_line = super::truncate(line, width as usize);
 
             if line.starts_with("╔") || line.starts_with("║") || line.starts_with("╚") {
-                queue!(stdout, SetForegroundColor(Color::Cyan), Print(display_line), ResetColor)?;
+                queue!(stdout, SetForegroundColor(theme.title), Print(display_line), ResetColor)?;
             } else if line.chars().all(|c| c.is_uppercase() || c.is_whitespace()) && !line.is_empty() {
-                queue!(stdout, SetForegroundColor(Color::Yellow), Print(display_line), ResetColor)?;
-            } else if line.starts_with("  ") && line.contains("->") {
-                queue!(stdout, SetForegroundColor(Color::Green), Print(display_line), ResetColor)?;
+                queue!(stdout, SetForegroundColor(theme.accent), Print(display_line), ResetColor)?;
             } else {
-                queue!(stdout, Print(display_line))?;
+                // Code is between backticks
+                let mut room = width as usize;
+                for (i, part) in line.split('`').enumerate() {
+                    let shown = super::truncate(part, room);
+                    room -= shown.chars().count();
+                    if i % 2 == 1 {
+                        highlight::print(&mut stdout, shown)?;
+                    } else {
+                        queue!(stdout, Print(shown))?;
+                    }
+                }
             }
         }
 
@@ -212,14 +246,17 @@ impl Reference {
             let scroll_percent = (self.scroll_offset * 100) / (self.content.len() - visible_lines);
             queue!(
                 stdout,
-                SetForegroundColor(Color::DarkGrey),
-                Print(format!(" Scroll: {}% (↑/↓, PgUp/PgDn)", scroll_percent)),
+                SetForegroundColor(theme.muted),
+                Print(format!(
+                    " Scroll: {}% ({}/{}, PgUp/PgDn)",
+                    scroll_percent, keys.label(Action::Up), keys.label(Action::Down)
+                )),
                 ResetColor
             )?;
         } else {
             queue!(
                 stdout,
-                SetForegroundColor(Color::DarkGrey),
+                SetForegroundColor(theme.muted),
                 Print(" Press any key to return"),
                 ResetColor
             )?;
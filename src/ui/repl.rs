@@ -2,19 +2,26 @@ use crossterm::{
     cursor,
     event::KeyCode,
     queue,
-    style::{Color, Print, ResetColor, SetForegroundColor},
+    style::{Print, ResetColor, SetForegroundColor},
     terminal::{self, Clear, ClearType},
 };
 use std::io::{self, Write};
 use std::rc::Rc;
 
+use super::completion::{self, Popup};
+use super::highlight;
+use super::keys::Action;
 use crate::lang::{parser, interpreter, types};
+use crate::config;
+use crate::crash::catch_panic;
 
 pub struct Repl {
     history: Vec<String>,
     current_input: Vec<char>,
     cursor_pos: usize,
     scroll_offset: usize,
+    /// Names to finish the one at the cursor with, after Tab.
+    completion: Option<Popup>,
 }
 
 impl Repl {
@@ -24,6 +31,7 @@ impl Repl {
             current_input: Vec::new(),
             cursor_pos: 0,
             scroll_offset: 0,
+            completion: None,
         }
     }
 
@@ -33,46 +41,63 @@ impl Repl {
         loop {
             self.render()?;
 
-            let key = super::read_key()?;
+            let key = match super::read_input()? {
+                super::Input::Key(key) => key,
+                // Input is a single line, so pasted lines are joined
+                super::Input::Paste(text) => {
+                    self.insert_str(&text.replace('\n', " "));
+                    continue;
+                }
+            };
+
+            if let Some(popup) = &mut self.completion {
+                match popup.handle(&key) {
+                    completion::Action::Insert(rest) => {
+                        self.insert_str(&rest);
+                        self.completion = None;
+                        continue;
+                    }
+                    completion::Action::Moved => continue,
+                    completion::Action::Close => {
+                        self.completion = None;
+                        if key.code == KeyCode::Esc {
+                            continue;
+                        }
+                    }
+                }
+            }
 
-            if super::is_ctrl_c(&key) || (key.code == KeyCode::Char('q') && self.current_input.is_empty()) {
+            let keys = config::keys();
+            let quit = keys.action(&key, &[Action::Back]).is_some()
+                || (self.current_input.is_empty() && keys.action(&key, &[Action::Quit]).is_some());
+            if super::is_ctrl_c(&key) || quit {
                 break;
             }
 
             match key.code {
-                KeyCode::Esc => break,
-                KeyCode::Enter => {
-                    if !self.current_input.is_empty() {
-                        self.execute_input()?;
-                    }
+                KeyCode::Enter if !self.current_input.is_empty() => {
+                    self.execute_input()?;
                 }
                 KeyCode::Char(c) => {
                     self.current_input.insert(self.cursor_pos, c);
                     self.cursor_pos += 1;
                 }
-                KeyCode::Backspace => {
-                    if self.cursor_pos > 0 {
-                        self.cursor_pos -= 1;
-                        self.current_input.remove(self.cursor_pos);
-                    }
+                KeyCode::Backspace if self.cursor_pos > 0 => {
+                    self.cursor_pos -= 1;
+                    self.current_input.remove(self.cursor_pos);
                 }
-                KeyCode::Delete => {
-                    if self.cursor_pos < self.current_input.len() {
-                        self.current_input.remove(self.cursor_pos);
-                    }
+                KeyCode::Delete if self.cursor_pos < self.current_input.len() => {
+                    self.current_input.remove(self.cursor_pos);
                 }
-                KeyCode::Left => {
-                    if self.cursor_pos > 0 {
-                        self.cursor_pos -= 1;
-                    }
+                KeyCode::Left if self.cursor_pos > 0 => {
+                    self.cursor_pos -= 1;
                 }
-                KeyCode::Right => {
-                    if self.cursor_pos < self.current_input.len() {
-                        self.cursor_pos += 1;
-                    }
+                KeyCode::Right if self.cursor_pos < self.current_input.len() => {
+                    self.cursor_pos += 1;
                 }
                 KeyCode::Home => self.cursor_pos = 0,
                 KeyCode::End => self.cursor_pos = self.current_input.len(),
+                KeyCode::Tab => self.complete(),
                 _ => {}
             }
         }
@@ -80,18 +105,39 @@ impl Repl {
         Ok(())
     }
 
+    fn insert_str(&mut self, text: &str) {
+        let count = text.chars().count();
+        self.current_input.splice(self.cursor_pos..self.cursor_pos, text.chars());
+        self.cursor_pos += count;
+    }
+
+    /// Finish the name before the cursor if only one name fits, or list
+    /// the ones that do.
+    fn complete(&mut self) {
+        let input: String = self.current_input.iter().collect();
+        let offset = self.current_input[..self.cursor_pos].iter().map(|c| c.len_utf8()).sum();
+        let Some(popup) = completion::open(&input, offset) else { return };
+        match popup.only() {
+            Some(rest) => self.insert_str(&rest),
+            None if !popup.is_empty() => self.completion = Some(popup),
+            None => {}
+        }
+    }
+
     fn print_welcome(&mut self) -> io::Result<()> {
+        let theme = config::theme();
         let mut stdout = io::stdout();
         queue!(stdout, Clear(ClearType::All))?;
 
         // Title
         queue!(stdout, cursor::MoveTo(0, 0), Clear(ClearType::CurrentLine))?;
-        queue!(stdout, SetForegroundColor(Color::Cyan), Print("═══ REPL - Interactive Mode ═══"), ResetColor)?;
+        queue!(stdout, SetForegroundColor(theme.title), Print("═══ REPL - Interactive Mode ═══"), ResetColor)?;
 
         queue!(stdout, cursor::MoveTo(0, 1), Clear(ClearType::CurrentLine))?;
 
         queue!(stdout, cursor::MoveTo(0, 2), Clear(ClearType::CurrentLine))?;
-        queue!(stdout, SetForegroundColor(Color::Yellow), Print("Type expressions to evaluate. Press Esc or Ctrl+C to exit."), ResetColor)?;
+        let exit = format!("Type expressions to evaluate. Press {} or Ctrl+C to exit.", config::keys().label(Action::Back));
+        queue!(stdout, SetForegroundColor(theme.accent), Print(exit), ResetColor)?;
 
         queue!(stdout, cursor::MoveTo(0, 3), Clear(ClearType::CurrentLine))?;
         queue!(stdout, Print("Examples:"))?;
@@ -142,6 +188,11 @@ impl Repl {
     }
 
     fn eval_expr(&self, input: &str) -> Result<String, String> {
+        catch_panic(|| self.eval_expr_unguarded(input))
+            .unwrap_or_else(|message| Err(format!("Internal error: {}", message)))
+    }
+
+    fn eval_expr_unguarded(&self, input: &str) -> Result<String, String> {
         // Parse
         let expr = parser::parse(input)
             .map_err(|e| e.to_string())?;
@@ -156,12 +207,16 @@ impl Repl {
         let mut interp = interpreter::Interpreter::new();
         let env = Rc::new(interpreter::get_builtin_env());
         let value = interp.eval(&expr, &env)
-            .map_err(|e| e.to_string())?;
+            .map_err(|e| match e.span {
+                Some(span) => format!("{} (at column {})", e, span.line_col(input).1),
+                None => e.to_string(),
+            })?;
 
         Ok(format!("{} : {}", value.to_string_repr(), ty))
     }
 
     fn render(&self) -> io::Result<()> {
+        let theme = config::theme();
         let mut stdout = io::stdout();
         let (width, height) = terminal::size()?;
 
@@ -171,11 +226,7 @@ impl Repl {
         // Render history line by line
         let visible_lines = history_height as usize;
         let end = self.history.len();
-        let start = if end > visible_lines {
-            end - visible_lines
-        } else {
-            0
-        };
+        let start = end.saturating_sub(visible_lines);
 
         for (line_offset, i) in (start..end).enumerate() {
             let y = history_start_y + line_offset as u16;
@@ -183,18 +234,14 @@ impl Repl {
 
             let line = &self.history[i];
             // Truncate if too long
-            let display_line = if line.len() > width as usize {
-                &line[..width as usize]
-            } else {
-                line
-            };
+            let display_line = super::truncate(line, width as usize);
 
             if line.starts_with(">") {
-                queue!(stdout, SetForegroundColor(Color::Green), Print(display_line), ResetColor)?;
+                queue!(stdout, SetForegroundColor(theme.success), Print(display_line), ResetColor)?;
             } else if line.contains("Error") {
-                queue!(stdout, SetForegroundColor(Color::Red), Print(display_line), ResetColor)?;
+                queue!(stdout, SetForegroundColor(theme.error), Print(display_line), ResetColor)?;
             } else {
-                queue!(stdout, SetForegroundColor(Color::White), Print(display_line), ResetColor)?;
+                queue!(stdout, SetForegroundColor(theme.text), Print(display_line), ResetColor)?;
             }
         }
 
@@ -204,10 +251,11 @@ impl Repl {
             queue!(stdout, cursor::MoveTo(0, y), Clear(ClearType::CurrentLine))?;
         }
 
-        // Input prompt
+        // Input prompt, after a blank line a completion popup may have covered
         let prompt_y = height.saturating_sub(3);
+        queue!(stdout, cursor::MoveTo(0, prompt_y.saturating_sub(1)), Clear(ClearType::CurrentLine))?;
         queue!(stdout, cursor::MoveTo(0, prompt_y), Clear(ClearType::CurrentLine))?;
-        queue!(stdout, SetForegroundColor(Color::DarkGrey))?;
+        queue!(stdout, SetForegroundColor(theme.muted))?;
         for _ in 0..width {
             queue!(stdout, Print("─"))?;
         }
@@ -215,18 +263,18 @@ impl Repl {
 
         // Input line
         queue!(stdout, cursor::MoveTo(0, prompt_y + 1), Clear(ClearType::CurrentLine))?;
-        queue!(stdout, SetForegroundColor(Color::Cyan), Print(">> "), ResetColor)?;
+        queue!(stdout, SetForegroundColor(theme.title), Print(">> "), ResetColor)?;
 
         let input_str: String = self.current_input.iter().collect();
         // Truncate input if too long
-        if input_str.len() > width as usize - 3 {
-            queue!(stdout, Print(&input_str[..width as usize - 3]))?;
-        } else {
-            queue!(stdout, Print(&input_str))?;
-        }
+        highlight::print(&mut stdout, super::truncate(&input_str, (width as usize).saturating_sub(3)))?;
 
         // Position cursor
-        let cursor_x = (3 + self.cursor_pos).min(width as usize - 1) as u16;
+        let cursor_x = (3 + self.cursor_pos).min((width as usize).saturating_sub(1)) as u16;
+        if let Some(popup) = &self.completion {
+            // Over the history, which is redrawn next time
+            popup.render(&mut stdout, cursor_x, prompt_y + 1, width, height)?;
+        }
         queue!(stdout, cursor::MoveTo(cursor_x, prompt_y + 1))?;
 
         stdout.flush()?;
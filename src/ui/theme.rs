@@ -0,0 +1,175 @@
+use anyhow::{anyhow, bail, Result};
+use crossterm::{
+    queue,
+    style::{Attribute, Color, SetAttribute, SetBackgroundColor},
+};
+use std::io::{self, Write};
+
+/// The colors every screen draws with, by what they're used for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    /// Ordinary text that stands out a little, like challenge names.
+    pub text: Color,
+    /// Hints, help bars and other text in the background.
+    pub muted: Color,
+    /// Titles and the selected item.
+    pub title: Color,
+    /// Headings and details worth a look, like type signatures.
+    pub accent: Color,
+    pub success: Color,
+    pub warning: Color,
+    pub error: Color,
+    /// Background of selected text.
+    pub selection: Color,
+    /// Background of the status bar, matching brackets and popups.
+    pub surface: Color,
+    pub keyword: Color,
+    pub number: Color,
+    pub string: Color,
+    pub comment: Color,
+    pub builtin: Color,
+    /// `\` and `->`.
+    pub lambda: Color,
+    pub operator: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            text: Color::White,
+            muted: Color::DarkGrey,
+            title: Color::Cyan,
+            accent: Color::Yellow,
+            success: Color::Green,
+            warning: Color::Yellow,
+            error: Color::Red,
+            selection: Color::DarkBlue,
+            surface: Color::DarkGrey,
+            keyword: Color::Magenta,
+            number: Color::Blue,
+            string: Color::Yellow,
+            comment: Color::DarkGrey,
+            builtin: Color::Green,
+            lambda: Color::Red,
+            operator: Color::Cyan,
+        }
+    }
+}
+
+impl Theme {
+    /// The terminal's own colors throughout, with reverse video for
+    /// backgrounds. Used when `NO_COLOR` is set.
+    pub fn monochrome() -> Self {
+        Theme {
+            text: Color::Reset,
+            muted: Color::Reset,
+            title: Color::Reset,
+            accent: Color::Reset,
+            success: Color::Reset,
+            warning: Color::Reset,
+            error: Color::Reset,
+            selection: Color::Reset,
+            surface: Color::Reset,
+            keyword: Color::Reset,
+            number: Color::Reset,
+            string: Color::Reset,
+            comment: Color::Reset,
+            builtin: Color::Reset,
+            lambda: Color::Reset,
+            operator: Color::Reset,
+        }
+    }
+
+    /// Bright colors only, with nothing in dark grey.
+    pub fn high_contrast() -> Self {
+        Theme {
+            muted: Color::Grey,
+            selection: Color::Blue,
+            surface: Color::Reset,
+            number: Color::Cyan,
+            comment: Color::Grey,
+            operator: Color::White,
+            ..Theme::default()
+        }
+    }
+
+    /// One of the built-in themes.
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Theme::default()),
+            "monochrome" => Some(Theme::monochrome()),
+            "high_contrast" => Some(Theme::high_contrast()),
+            _ => None,
+        }
+    }
+
+    /// Change the color used for `role`, one of the field names.
+    pub fn set(&mut self, role: &str, color: Color) -> Result<()> {
+        let field = match role {
+            "text" => &mut self.text,
+            "muted" => &mut self.muted,
+            "title" => &mut self.title,
+            "accent" => &mut self.accent,
+            "success" => &mut self.success,
+            "warning" => &mut self.warning,
+            "error" => &mut self.error,
+            "selection" => &mut self.selection,
+            "surface" => &mut self.surface,
+            "keyword" => &mut self.keyword,
+            "number" => &mut self.number,
+            "string" => &mut self.string,
+            "comment" => &mut self.comment,
+            "builtin" => &mut self.builtin,
+            "lambda" => &mut self.lambda,
+            "operator" => &mut self.operator,
+            _ => bail!("Unknown theme color '{}'", role),
+        };
+        *field = color;
+        Ok(())
+    }
+}
+
+/// A color by name, like `dark_grey`, or as `#rrggbb`. `default` is the
+/// terminal's own color.
+pub fn parse_color(s: &str) -> Result<Color> {
+    if s == "default" {
+        return Ok(Color::Reset);
+    }
+    if let Some(hex) = s.strip_prefix('#').filter(|hex| hex.len() == 6 && hex.is_ascii()) {
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+        if let (Ok(r), Ok(g), Ok(b)) = (channel(0), channel(2), channel(4)) {
+            return Ok(Color::Rgb { r, g, b });
+        }
+    }
+    Color::try_from(s).map_err(|_| anyhow!("Unknown color '{}'", s))
+}
+
+/// Draw on a background color. The terminal's own background can't stand
+/// out, so it's drawn in reverse video instead; reset attributes after.
+pub fn set_background(stdout: &mut impl Write, color: Color) -> io::Result<()> {
+    match color {
+        Color::Reset => queue!(stdout, SetAttribute(Attribute::Reverse)),
+        color => queue!(stdout, SetBackgroundColor(color)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_colors() {
+        assert_eq!(parse_color("dark_grey").unwrap(), Color::DarkGrey);
+        assert_eq!(parse_color("default").unwrap(), Color::Reset);
+        assert_eq!(parse_color("#ff8000").unwrap(), Color::Rgb { r: 255, g: 128, b: 0 });
+        assert!(parse_color("#ff80").is_err());
+        // Six bytes, but not six hex digits
+        assert!(parse_color("#aééa").is_err());
+        assert!(parse_color("orange").is_err());
+
+        let mut theme = Theme::named("high_contrast").unwrap();
+        theme.set("keyword", Color::Red).unwrap();
+        assert_eq!((theme.keyword, theme.comment), (Color::Red, Color::Grey));
+        assert!(theme.set("background", Color::Red).is_err());
+    }
+}